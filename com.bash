@@ -1,24 +1,16 @@
 #!/usr/bin/env bash
-# 生成さたirファイルを指定の名前で実行可能ファイルとして生成する
+# スクリプトをコンパイルし、指定の名前で実行可能ファイルとして生成する
+# 使い方: ./com.bash hello.tanu hello
+# 出力先の拡張子を.ll/.bc/.s/.oにすると、その形式で書き出す
 
 # 引数がない場合は終了する
-if [ $# -eq 0 ]; then
+if [ $# -lt 2 ]; then
   echo "引数がありません。スクリプトを終了します。"
   exit 1
 fi
 # 引数の取得
-ir_path=$1
+src_path=$1
 ext_path=$2
-ir_file_name=${ir_path%.*}
 
-
-#echo ${ir_path} ${ext_path}
-
-
-# コンパイル
-cargo run --features "lexer,parser,decoder"
-# オブジェクトファイルに変換
-llc -relocation-model=pic -filetype=obj "${ir_path}"
-# オブジェクトファイルをリンクして実行可能ファイルを生成
-#g++ "${ir_file_name}.o" -o  "${ext_path}" -pie
-g++  "${ir_file_name}.o"  -o  "${ext_path}" -pie
+# コンパイルとリンク(リンカは環境変数CCで変更できる)
+cargo run --features "lexer,parser,checker,decoder" -- "${src_path}" -o "${ext_path}" || exit 1
@@ -0,0 +1,29 @@
+use std::process;
+
+#[cfg(any(
+    feature = "full",
+    all(feature = "lexer", feature = "parser", feature = "decoder")
+))]
+fn main() {
+    use tanucc_script_ir::repl::Repl;
+    extern crate inkwell as iw;
+    use iw::{context::Context, OptimizationLevel};
+
+    // ログの出力はRUST_LOGで制御する
+    env_logger::init();
+    let context = Context::create();
+    let mut repl = Repl::new(&context, OptimizationLevel::None, &[]);
+    if let Err(e) = repl.run() {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(any(
+    feature = "full",
+    all(feature = "lexer", feature = "parser", feature = "decoder")
+)))]
+fn main() {
+    eprintln!("tanucc-repl was built without the compiler; enable the `full` feature");
+    process::exit(1);
+}
@@ -0,0 +1,4 @@
+#[cfg(any(feature = "full", feature = "checker"))]
+pub mod resolver;
+#[cfg(any(feature = "full", feature = "checker"))]
+pub mod typeck;
@@ -0,0 +1,594 @@
+use crate::error::{CompilerError, Diagnostic, UNDEFINED_VARIABLE};
+use crate::parser::syntax::Node;
+use crate::source::{SourceMap, Span};
+use crate::types::{
+    ControlFlow, DataType, Declaration, Expr, FunctionArg, NodeValue, Operator, Statement,
+};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+// 組み込みの型名
+const PRIMITIVE_TYPES: &[&str] = &[
+    "()", "i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "int", "f32", "f64", "float",
+    "bool", "str", "string",
+];
+
+// 組み込みのシステム関数名(デコーダのシステム関数の一覧と対応する)
+const SYSTEM_FUNCTIONS: &[&str] = &[
+    "print", "printf", "println", "puts", "exit", "malloc", "alloc", "free",
+];
+
+// スコープの種類
+// 変数は関数・モジュールの境界を越えて見えないが、関数や型の名前は外側のものも見える
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScopeKind {
+    Module,
+    Function,
+    Impl,
+    Block,
+}
+
+// 宣言された変数(宣言位置, 使用されたか)
+#[derive(Debug, Clone)]
+struct Binding {
+    line: usize,
+    column: usize,
+    span: Span, // 変数名の範囲(分からなければ空)
+    is_used: bool,
+}
+
+#[derive(Debug)]
+struct Scope {
+    kind: ScopeKind,
+    variables: HashMap<String, Binding>,
+    functions: HashSet<String>,
+    types: HashSet<String>,
+}
+
+impl Scope {
+    fn new(kind: ScopeKind) -> Self {
+        Scope {
+            kind,
+            variables: HashMap::new(),
+            functions: HashSet::new(),
+            types: HashSet::new(),
+        }
+    }
+}
+
+// 名前解決: 変数・関数・型の参照が宣言済みかを調べる
+// 未定義の名前はエラー(編集距離の近い候補を提案)、未使用の変数と変数の覆い隠しは警告にする
+pub struct Resolver {
+    input_path: String,
+    input_content: String,
+    source_map: SourceMap, // ノードのSpanが指すファイル(他のファイルのノードのエラー表示に使う)
+    errors: CompilerError,
+    warnings: CompilerError,
+    scopes: Vec<Scope>,
+    host_functions: HashSet<String>, // ホストから登録されたシステム関数名
+}
+
+impl Resolver {
+    pub fn new(input_path: &str, input_content: String) -> Self {
+        Resolver {
+            input_path: input_path.to_string(),
+            input_content,
+            source_map: SourceMap::new(),
+            errors: CompilerError::new(),
+            warnings: CompilerError::new(),
+            scopes: Vec::new(),
+            host_functions: HashSet::new(),
+        }
+    }
+
+    // ホストが登録した関数を`@名前`で呼べるシステム関数として扱う
+    pub fn register_host_function(&mut self, name: &str) {
+        self.host_functions.insert(name.to_string());
+    }
+
+    pub fn set_source_map(&mut self, source_map: &SourceMap) {
+        self.source_map = source_map.clone();
+    }
+
+    pub fn input_content(&self) -> String {
+        self.input_content.clone()
+    }
+    pub fn input_path(&self) -> String {
+        self.input_path.clone()
+    }
+
+    // プログラム全体の名前を解決し、警告とエラーを(表示せずに)値として返す
+    pub fn diagnose(&mut self, node: &Node) -> Vec<Diagnostic> {
+        self.resolve_items(ScopeKind::Module, &[Box::new(node.clone())]);
+        let mut diagnostics = mem::take(&mut self.warnings).into_diagnostics();
+        diagnostics.extend(mem::take(&mut self.errors).into_diagnostics());
+        diagnostics
+    }
+
+    // nameは表示上の名前(システム関数なら`@`付き)で、ソース上に見つかればその範囲に置き換えを提案する
+    fn undefined_error(
+        &mut self,
+        node: &Node,
+        name: &str,
+        message: &str,
+        suggestion: Option<String>,
+    ) {
+        let name_span = self.name_span(node, name);
+        let mut diagnostic = Diagnostic::error(message)
+            .with_code(&UNDEFINED_VARIABLE)
+            .at(node.line, node.column)
+            .with_span_label(name_span.unwrap_or(node.span), "not found in this scope");
+        if let Some(suggestion) = suggestion {
+            diagnostic = match name_span {
+                Some(span) => diagnostic.with_suggestion(
+                    span,
+                    &format!("a similar name exists: `{}`", suggestion),
+                    &suggestion,
+                ),
+                None => diagnostic.with_help(&format!("did you mean `{}`?", suggestion)),
+            };
+        }
+        self.errors.push(diagnostic);
+    }
+
+    // ノードの範囲の中で最初に現れるnameの範囲(修飾名に書き換えられた名前などは見つからない)
+    fn name_span(&self, node: &Node, name: &str) -> Option<Span> {
+        let source_file = self.source_map.file(node.span.file)?;
+        let start = node.span.start
+            + source_file
+                .content
+                .get(node.span.start..node.span.end)?
+                .find(name)?;
+        Some(Span::new(node.span.file, start, start + name.len()))
+    }
+
+    // 新しいスコープで文の並びを解決する
+    // 関数と構造体はスコープの先頭で宣言済みとして扱う(定義より前から参照できる)
+    fn resolve_items(&mut self, kind: ScopeKind, statements: &[Box<Node>]) {
+        let mut scope = Scope::new(kind);
+        for statement in statements {
+            for item in statement.iter() {
+                match item.borrow().value {
+                    NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                        ref name,
+                        ..
+                    ))) => {
+                        scope.functions.insert(name.clone());
+                    }
+                    NodeValue::Statement(Statement::Declaration(Declaration::Struct(
+                        ref name,
+                        ..
+                    ))) => {
+                        scope.types.insert(name.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.scopes.push(scope);
+        for statement in statements {
+            for item in statement.iter() {
+                self.resolve(&item.borrow());
+            }
+        }
+        self.pop_scope();
+    }
+
+    // スコープを閉じ、使われなかった変数を警告する
+    fn pop_scope(&mut self) {
+        let scope = match self.scopes.pop() {
+            Some(scope) => scope,
+            None => return,
+        };
+        let mut unused: Vec<(&String, &Binding)> = scope
+            .variables
+            .iter()
+            .filter(|(name, binding)| {
+                !binding.is_used && !name.starts_with('_') && name.as_str() != "self"
+            })
+            .collect();
+        unused.sort_by_key(|(_, binding)| (binding.line, binding.column));
+        for (name, binding) in unused {
+            let diagnostic = Diagnostic::warning(&format!("Unused variable: `{}`", name))
+                .at(binding.line, binding.column)
+                .with_span(binding.span);
+            let help = "if this is intentional, prefix it with an underscore";
+            let replacement = format!("_{}", name);
+            self.warnings.push(if binding.span.is_empty() {
+                diagnostic.with_help(&format!("{}: `{}`", help, replacement))
+            } else {
+                diagnostic.with_suggestion(binding.span, help, &replacement)
+            });
+        }
+    }
+
+    fn declare_variable(&mut self, name: &str, line: usize, column: usize, span: Span) {
+        if name.is_empty() {
+            return;
+        }
+        if let Some(previous) = self.find_variable(name).cloned() {
+            let diagnostic =
+                Diagnostic::warning(&format!("`{}` shadows a previous declaration", name))
+                    .at(line, column)
+                    .with_span(span);
+            self.warnings.push(if previous.span.is_empty() {
+                diagnostic.with_note(&format!(
+                    "`{}` was previously declared at {}:{}",
+                    name, previous.line, previous.column
+                ))
+            } else {
+                diagnostic.with_label(
+                    previous.span,
+                    &format!("`{}` was previously declared here", name),
+                )
+            });
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.variables.insert(
+                name.to_string(),
+                Binding {
+                    line,
+                    column,
+                    span,
+                    is_used: false,
+                },
+            );
+        }
+    }
+
+    // 関数・モジュールの境界までの内側のスコープから変数を探す
+    fn find_variable(&self, name: &str) -> Option<&Binding> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(binding) = scope.variables.get(name) {
+                return Some(binding);
+            }
+            if matches!(scope.kind, ScopeKind::Function | ScopeKind::Module) {
+                break;
+            }
+        }
+        None
+    }
+
+    fn visible_variables(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for scope in self.scopes.iter().rev() {
+            names.extend(scope.variables.keys().cloned());
+            if matches!(scope.kind, ScopeKind::Function | ScopeKind::Module) {
+                break;
+            }
+        }
+        names
+    }
+
+    // 変数の参照を解決する(is_readがtrueなら使用済みにする)
+    fn resolve_variable(&mut self, node: &Node, name: &str, is_read: bool) {
+        if name.is_empty() {
+            return;
+        }
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.variables.get_mut(name) {
+                binding.is_used |= is_read;
+                return;
+            }
+            if matches!(scope.kind, ScopeKind::Function | ScopeKind::Module) {
+                break;
+            }
+        }
+        let suggestion = Resolver::suggest(name, self.visible_variables());
+        self.undefined_error(
+            node,
+            name,
+            &format!("Cannot find value `{}` in this scope", name),
+            suggestion,
+        );
+    }
+
+    fn resolve_function(&mut self, node: &Node, name: &str, is_system: bool) {
+        let is_defined = self.scopes.iter().any(|scope| scope.functions.contains(name));
+        let is_system_function =
+            SYSTEM_FUNCTIONS.contains(&name) || self.host_functions.contains(name);
+        if is_defined || (is_system && is_system_function) {
+            return;
+        }
+        let mut candidates: Vec<String> = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.functions.iter().cloned())
+            .collect();
+        if is_system {
+            candidates.extend(SYSTEM_FUNCTIONS.iter().map(|name| name.to_string()));
+            candidates.extend(self.host_functions.iter().cloned());
+        }
+        let suggestion = Resolver::suggest(name, candidates)
+            .map(|name| if is_system { format!("@{}", name) } else { name });
+        let (display_name, message) = if is_system {
+            (
+                format!("@{}", name),
+                format!("Cannot find system function `@{}`", name),
+            )
+        } else {
+            (
+                name.to_string(),
+                format!("Cannot find function `{}` in this scope", name),
+            )
+        };
+        self.undefined_error(node, &display_name, &message, suggestion);
+    }
+
+    fn resolve_type_name(&mut self, node: &Node, name: &str) {
+        if PRIMITIVE_TYPES.contains(&name) {
+            return;
+        }
+        let is_defined = self.scopes.iter().any(|scope| scope.types.contains(name));
+        if is_defined {
+            return;
+        }
+        let mut candidates: Vec<String> = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.types.iter().cloned())
+            .collect();
+        candidates.extend(PRIMITIVE_TYPES.iter().map(|name| name.to_string()));
+        let suggestion = Resolver::suggest(name, candidates);
+        self.undefined_error(
+            node,
+            name,
+            &format!("Cannot find type `{}` in this scope", name),
+            suggestion,
+        );
+    }
+
+    // 型注釈のノードを解決する
+    fn resolve_type(&mut self, node: &Node) {
+        match node.value {
+            NodeValue::Expr(Expr::Variable(_, ref name, _, _, ref generics)) => {
+                if generics.is_none() && !name.is_empty() {
+                    self.resolve_type_name(node, name);
+                }
+            }
+            NodeValue::Expr(Expr::DataType(DataType::String(ref name))) => {
+                self.resolve_type_name(node, name)
+            }
+            NodeValue::Expr(Expr::DataType(DataType::FixedArray(ref element, _))) => {
+                self.resolve_type(element)
+            }
+            _ => {}
+        }
+    }
+
+    // 編集距離が名前の長さの1/3以内(最低1)で最も近い候補を返す
+    fn suggest(name: &str, candidates: Vec<String>) -> Option<String> {
+        let max_distance = (name.chars().count() / 3).max(1);
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate != name)
+            .map(|candidate| (Resolver::edit_distance(name, &candidate), candidate))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)))
+            .map(|(_, candidate)| candidate)
+    }
+
+    // 編集距離(隣り合う文字の入れ替えも1回の操作と数える)
+    fn edit_distance(a: &str, b: &str) -> usize {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for (i, row) in table.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for (j, cell) in table[0].iter_mut().enumerate() {
+            *cell = j;
+        }
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+                let mut distance = (table[i - 1][j] + 1)
+                    .min(table[i][j - 1] + 1)
+                    .min(table[i - 1][j - 1] + cost);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    distance = distance.min(table[i - 2][j - 2] + 1);
+                }
+                table[i][j] = distance;
+            }
+        }
+        table[a.len()][b.len()]
+    }
+
+    fn resolve(&mut self, node: &Node) {
+        match node.value {
+            NodeValue::Expr(ref expr) => self.resolve_expr(node, expr),
+            NodeValue::Statement(ref statement) => self.resolve_statement(node, statement),
+            NodeValue::SingleComment(..) | NodeValue::MultiComment(..) => {}
+        }
+    }
+
+    fn resolve_expr(&mut self, node: &Node, expr: &Expr) {
+        match *expr {
+            Expr::DataType(DataType::Array(_, ref values)) => {
+                for value in values {
+                    self.resolve(value);
+                }
+            }
+            Expr::DataType(_) => {}
+            Expr::Operator(ref op) => self.resolve_operator(op),
+            Expr::Block(ref statements) => self.resolve_items(ScopeKind::Block, statements),
+            Expr::Variable(_, ref name, ..) => self.resolve_variable(node, name, true),
+            Expr::Assign(ref target, ref value, ref index) => {
+                self.resolve(value);
+                self.resolve(index);
+                // 代入先は読み出しではないので使用済みにしない
+                match target.value {
+                    NodeValue::Expr(Expr::Variable(_, ref name, ..)) => {
+                        self.resolve_variable(target, name, false)
+                    }
+                    _ => self.resolve(target),
+                }
+            }
+            Expr::Call(ref name, ref args, is_system) => {
+                self.resolve_function(node, name, is_system);
+                for arg in args {
+                    self.resolve(arg);
+                }
+            }
+            Expr::StructInstance(ref name, ref fields) => {
+                self.resolve_type_name(node, name);
+                for (_, value) in fields {
+                    self.resolve(value);
+                }
+            }
+            Expr::Index(ref base, ref index) => {
+                self.resolve(base);
+                self.resolve(index);
+            }
+            Expr::MemberAccess(ref base, ref member) => {
+                self.resolve(base);
+                // フィールド名とメソッド名は型に依存するので型検査で調べる
+                if let NodeValue::Expr(Expr::Call(_, ref args, _)) = member.value {
+                    for arg in args {
+                        self.resolve(arg);
+                    }
+                }
+            }
+            // モジュールのパスはモジュールの読み込み時に解決する
+            Expr::ScopeResolution(_) | Expr::Use(_) | Expr::EndStatement => {}
+        }
+    }
+
+    fn resolve_operator(&mut self, op: &Operator) {
+        match *op {
+            Operator::Increment(ref operand, _)
+            | Operator::Decrement(ref operand, _)
+            | Operator::BitNot(ref operand) => self.resolve(operand),
+            Operator::Range(ref start, ref end, ref step, _) => {
+                self.resolve(start);
+                self.resolve(end);
+                self.resolve(step);
+            }
+            Operator::Eq(ref left, ref right)
+            | Operator::Ne(ref left, ref right)
+            | Operator::Lt(ref left, ref right)
+            | Operator::Gt(ref left, ref right)
+            | Operator::Le(ref left, ref right)
+            | Operator::Ge(ref left, ref right)
+            | Operator::And(ref left, ref right)
+            | Operator::Or(ref left, ref right)
+            | Operator::Add(ref left, ref right)
+            | Operator::Sub(ref left, ref right)
+            | Operator::Mul(ref left, ref right)
+            | Operator::Div(ref left, ref right)
+            | Operator::Modulus(ref left, ref right)
+            | Operator::AddAssign(ref left, ref right)
+            | Operator::SubAssign(ref left, ref right)
+            | Operator::MulAssign(ref left, ref right)
+            | Operator::DivAssign(ref left, ref right)
+            | Operator::ModulusAssign(ref left, ref right)
+            | Operator::BitAnd(ref left, ref right)
+            | Operator::BitOr(ref left, ref right)
+            | Operator::BitXor(ref left, ref right)
+            | Operator::ShiftLeft(ref left, ref right)
+            | Operator::ShiftRight(ref left, ref right)
+            | Operator::BitAndAssign(ref left, ref right)
+            | Operator::BitOrAssign(ref left, ref right)
+            | Operator::BitXorAssign(ref left, ref right)
+            | Operator::ShiftLeftAssign(ref left, ref right)
+            | Operator::ShiftRightAssign(ref left, ref right) => {
+                self.resolve(left);
+                self.resolve(right);
+            }
+        }
+    }
+
+    fn resolve_statement(&mut self, node: &Node, statement: &Statement) {
+        match *statement {
+            Statement::Declaration(Declaration::Variable(
+                ref var,
+                ref data_type,
+                ref value,
+                ..,
+            ))
+            | Statement::Declaration(Declaration::Const(ref var, ref data_type, ref value, ..)) => {
+                // 初期値は宣言前のスコープで解決する(`let x = x + 1;`)
+                self.resolve(value);
+                self.resolve_type(data_type);
+                if let NodeValue::Expr(Expr::Variable(_, ref name, ..)) = var.value {
+                    self.declare_variable(name, var.line, var.column, var.span);
+                }
+            }
+            Statement::Declaration(Declaration::Function(
+                _,
+                ref args,
+                ref body,
+                ref return_type,
+                ..,
+            )) => self.resolve_function_definition(args, body, return_type),
+            Statement::Declaration(Declaration::Struct(_, ref members, _)) => {
+                for member in members {
+                    if let NodeValue::Expr(Expr::Variable(ref field_type, ..)) = member.value {
+                        self.resolve_type(field_type);
+                    }
+                }
+            }
+            Statement::Declaration(Declaration::Impl(ref type_name, ref members)) => {
+                self.resolve_type_name(node, type_name);
+                let mut scope = Scope::new(ScopeKind::Impl);
+                scope.types.insert("Self".to_string());
+                self.scopes.push(scope);
+                for member in members {
+                    self.resolve(member);
+                }
+                self.pop_scope();
+            }
+            Statement::ModDeclaration(_, ref items) => {
+                // モジュールの中身は外側の変数を参照できない
+                let saved_scopes = mem::take(&mut self.scopes);
+                self.resolve_items(ScopeKind::Module, items);
+                self.scopes = saved_scopes;
+            }
+            Statement::ControlFlow(ControlFlow::If(ref condition, ref body))
+            | Statement::ControlFlow(ControlFlow::ElseIf(ref condition, ref body))
+            | Statement::ControlFlow(ControlFlow::While(ref condition, ref body)) => {
+                self.resolve(condition);
+                self.resolve(body);
+            }
+            Statement::ControlFlow(ControlFlow::Else(ref body))
+            | Statement::ControlFlow(ControlFlow::Loop(ref body)) => self.resolve(body),
+            Statement::ControlFlow(ControlFlow::For(ref var, ref iterator, ref body)) => {
+                self.resolve(iterator);
+                self.scopes.push(Scope::new(ScopeKind::Block));
+                if let NodeValue::Expr(Expr::Variable(_, ref name, ..)) = var.value {
+                    self.declare_variable(name, var.line, var.column, var.span);
+                }
+                self.resolve(body);
+                self.pop_scope();
+            }
+            Statement::ControlFlow(ControlFlow::Return(ref value)) => self.resolve(value),
+            Statement::ControlFlow(ControlFlow::Break)
+            | Statement::ControlFlow(ControlFlow::Continue) => {}
+            _ => {}
+        }
+    }
+
+    fn resolve_function_definition(
+        &mut self,
+        args: &[FunctionArg],
+        body: &Node,
+        return_type: &Node,
+    ) {
+        self.resolve_type(return_type);
+        self.scopes.push(Scope::new(ScopeKind::Function));
+        for (arg_type, arg_name, name_span) in args {
+            self.resolve_type(arg_type);
+            // 引数名の位置(範囲がなければ型注釈の位置)
+            let (line, column) = self
+                .source_map
+                .file(name_span.file)
+                .filter(|_| !name_span.is_empty())
+                .map_or((arg_type.line, arg_type.column), |file| {
+                    file.line_column(name_span.start)
+                });
+            self.declare_variable(arg_name, line, column, *name_span);
+        }
+        self.resolve(body);
+        self.pop_scope();
+    }
+}
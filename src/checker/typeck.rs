@@ -0,0 +1,1114 @@
+use crate::error::{CompilerError, Diagnostic, TYPE_ERROR};
+use crate::parser::syntax::Node;
+use crate::source::SourceMap;
+use crate::types::{
+    ControlFlow, DataType, Declaration, Expr, FunctionArg, HostType, NodeValue, Operator, Statement,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+
+// 型検査で扱う型
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int(u32, bool),             // 整数(ビット幅, 符号付きか)
+    Float(u32),                 // 浮動小数点数(ビット幅)
+    Bool,                       // 真偽値
+    Str,                        // 文字列
+    Unit,                       // 値なし(())
+    Struct(String),             // 構造体(構造体名)
+    Reference(Box<Type>, bool), // 参照(参照先の型, 可変か)
+    Array(Box<Type>, usize),    // 固定長配列(要素の型, 要素数)
+    Unknown,                    // 型が決まらない(報告済みのエラーや型情報のないCのポインタ)
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int(bits, true) => write!(f, "i{}", bits),
+            Type::Int(bits, false) => write!(f, "u{}", bits),
+            Type::Float(bits) => write!(f, "f{}", bits),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::Unit => write!(f, "()"),
+            Type::Struct(name) => write!(f, "{}", name),
+            Type::Reference(ty, true) => write!(f, "&mut {}", ty),
+            Type::Reference(ty, false) => write!(f, "&{}", ty),
+            Type::Array(ty, length) => write!(f, "[{}; {}]", ty, length),
+            Type::Unknown => write!(f, "_"),
+        }
+    }
+}
+
+impl Type {
+    fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int(..) | Type::Float(_) | Type::Unknown)
+    }
+
+    // 参照なら参照先の型(メンバアクセスと添字アクセスは参照を自動で辿る)
+    fn deref(&self) -> &Type {
+        match self {
+            Type::Reference(ty, _) => ty,
+            ty => ty,
+        }
+    }
+}
+
+// 関数のシグネチャ
+#[derive(Debug, Clone)]
+struct Signature {
+    params: Vec<Type>, // selfを除く引数の型
+    return_type: Type,
+    is_var_arg: bool,
+    has_receiver: bool, // selfを受け取るメソッドか
+}
+
+// 構文木を辿って型を検査する
+// デコーダの前に実行し、見つかった型エラーをまとめて報告する
+pub struct TypeChecker {
+    input_path: String,
+    input_content: String,
+    errors: CompilerError,
+    scopes: Vec<HashMap<String, Type>>,          // 変数名 -> 型(内側のスコープが末尾)
+    functions: HashMap<String, Signature>,       // 関数名(メソッドは`型名::メソッド名`) -> シグネチャ
+    structs: HashMap<String, Vec<(String, Type)>>, // 構造体名 -> フィールドの一覧
+    return_type: Option<Type>,                   // 検査中の関数の戻り値の型(トップレベルではNone)
+    current_impl: Option<String>,                // 検査中のimplの型名(`Self`の解決に使う)
+    host_functions: HashMap<String, Signature>,  // ホストから登録されたシステム関数
+}
+
+impl TypeChecker {
+    pub fn new(input_path: &str, input_content: String) -> Self {
+        TypeChecker {
+            input_path: input_path.to_string(),
+            input_content,
+            errors: CompilerError::new(),
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            return_type: None,
+            current_impl: None,
+            host_functions: HashMap::new(),
+        }
+    }
+
+    // ホストが登録した関数を`@名前`で呼べるシステム関数として扱う
+    pub fn register_host_function(&mut self, name: &str, params: &[HostType], return_type: HostType) {
+        let to_type = |ty: HostType| match ty {
+            HostType::I32 => Type::Int(32, true),
+            HostType::I64 => Type::Int(64, true),
+            HostType::F64 => Type::Float(64),
+            HostType::Str => Type::Str,
+            HostType::Unit => Type::Unit,
+        };
+        let signature = Signature {
+            params: params.iter().map(|&ty| to_type(ty)).collect(),
+            return_type: to_type(return_type),
+            is_var_arg: false,
+            has_receiver: false,
+        };
+        self.host_functions.insert(name.to_string(), signature);
+    }
+
+    pub fn set_source_map(&mut self, source_map: &SourceMap) {
+        self.errors.set_source_map(source_map);
+    }
+
+    pub fn input_content(&self) -> String {
+        self.input_content.clone()
+    }
+    pub fn input_path(&self) -> String {
+        self.input_path.clone()
+    }
+
+    // プログラム全体を検査し、型エラーを(表示せずに)値として返す
+    pub fn diagnose(&mut self, node: &Node) -> Vec<Diagnostic> {
+        // 定義より前の使用を解決できるよう、トップレベルの構造体と関数を先に登録する
+        for statement in node.iter() {
+            if let NodeValue::Statement(Statement::Declaration(Declaration::Struct(ref name, ..))) =
+                statement.borrow().value
+            {
+                self.structs.entry(name.clone()).or_default();
+            }
+        }
+        for statement in node.iter() {
+            let statement = statement.borrow();
+            match statement.value {
+                NodeValue::Statement(Statement::Declaration(Declaration::Struct(
+                    ref name,
+                    ref members,
+                    _,
+                ))) => self.declare_struct(name, members),
+                NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                    ref name,
+                    ref args,
+                    _,
+                    ref return_type,
+                    _,
+                    _,
+                ))) => self.declare_function(name, args, return_type),
+                NodeValue::Statement(Statement::Declaration(Declaration::Impl(
+                    ref type_name,
+                    ref members,
+                ))) => self.declare_methods(type_name, members),
+                _ => {}
+            }
+        }
+
+        self.check_chain(node);
+        mem::take(&mut self.errors).into_diagnostics()
+    }
+
+    // nodeの範囲を主たる範囲とする型エラー
+    fn new_type_error(node: &Node, message: &str) -> Diagnostic {
+        Diagnostic::error(message)
+            .with_code(&TYPE_ERROR)
+            .at(node.line, node.column)
+            .with_span(node.span)
+    }
+
+    fn type_error(&mut self, node: &Node, message: &str) {
+        self.errors.push(TypeChecker::new_type_error(node, message));
+    }
+
+    fn type_error_with_note(&mut self, node: &Node, message: &str, note: &str) {
+        self.errors
+            .push(TypeChecker::new_type_error(node, message).with_note(note));
+    }
+
+    // 値の型`found`を`expected`の場所に渡せるか
+    // 整数同士・整数から浮動小数点数・浮動小数点数同士は暗黙に変換される
+    fn is_assignable(expected: &Type, found: &Type) -> bool {
+        match (expected, found) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (Type::Int(..), Type::Int(..))
+            | (Type::Float(_), Type::Int(..))
+            | (Type::Float(_), Type::Float(_)) => true,
+            // &mut T は &T として渡せる
+            (Type::Reference(expected, false), Type::Reference(found, _))
+            | (Type::Reference(expected, true), Type::Reference(found, true)) => {
+                **expected == Type::Unknown || **found == Type::Unknown || expected == found
+            }
+            // 配列は同じ長さなら要素ごとに変換される
+            (Type::Array(expected, expected_length), Type::Array(found, found_length)) => {
+                expected_length == found_length && TypeChecker::is_assignable(expected, found)
+            }
+            _ => expected == found,
+        }
+    }
+
+    fn expect_assignable(&mut self, node: &Node, expected: &Type, found: &Type, what: &str) {
+        if let Some(diagnostic) = TypeChecker::mismatch(node, expected, found, what) {
+            self.errors.push(diagnostic);
+        }
+    }
+
+    // 渡せなければ型の不一致のエラー(呼び出し側で期待された型の理由をラベルとして足せる)
+    fn mismatch(node: &Node, expected: &Type, found: &Type, what: &str) -> Option<Diagnostic> {
+        if TypeChecker::is_assignable(expected, found) {
+            return None;
+        }
+        let diagnostic = TypeChecker::new_type_error(
+            node,
+            &format!(
+                "Mismatched types{}: expected `{}`, found `{}`",
+                what, expected, found
+            ),
+        )
+        .with_span_label(
+            node.span,
+            &format!("expected `{}`, found `{}`", expected, found),
+        );
+        Some(match (expected, found) {
+            (Type::Int(..), Type::Float(_)) => diagnostic
+                .with_note("floating point values are not implicitly converted to integers"),
+            _ => diagnostic,
+        })
+    }
+
+    // 型ノードを型に解決する(未知の型名はUnknownを返す)
+    fn resolve_type(&mut self, node: &Node) -> Type {
+        let (name, is_mutable, is_reference) = match node.value {
+            NodeValue::Expr(Expr::DataType(DataType::String(ref name))) => {
+                (name.clone(), false, false)
+            }
+            NodeValue::Expr(Expr::Variable(_, ref name, is_mutable, is_reference, _)) => {
+                (name.clone(), is_mutable, is_reference)
+            }
+            NodeValue::Expr(Expr::DataType(DataType::Null))
+            | NodeValue::Expr(Expr::DataType(DataType::Unit(_))) => return Type::Unit,
+            NodeValue::Expr(Expr::DataType(DataType::FixedArray(ref element, length))) => {
+                return Type::Array(Box::new(self.resolve_type(element)), length)
+            }
+            _ => return Type::Unknown,
+        };
+        let ty = match name.as_str() {
+            "()" => Type::Unit,
+            "i8" => Type::Int(8, true),
+            "u8" => Type::Int(8, false),
+            "i16" => Type::Int(16, true),
+            "u16" => Type::Int(16, false),
+            "i32" => Type::Int(32, true),
+            "u32" => Type::Int(32, false),
+            "i64" | "int" => Type::Int(64, true),
+            "u64" => Type::Int(64, false),
+            "f32" => Type::Float(32),
+            "f64" | "float" => Type::Float(64),
+            "bool" => Type::Bool,
+            "str" | "string" => Type::Str,
+            "Self" => match self.current_impl {
+                Some(ref type_name) => Type::Struct(type_name.clone()),
+                None => Type::Unknown,
+            },
+            _ if self.structs.contains_key(&name) => Type::Struct(name.clone()),
+            // 未知の型名は名前解決で報告済み
+            _ => Type::Unknown,
+        };
+        if is_reference {
+            Type::Reference(Box::new(ty), is_mutable)
+        } else {
+            ty
+        }
+    }
+
+    fn declare_struct(&mut self, name: &str, members: &[Box<Node>]) {
+        let mut fields = Vec::new();
+        for member in members {
+            if let NodeValue::Expr(Expr::Variable(ref field_type, ref field_name, _, _, _)) =
+                member.value
+            {
+                let ty = self.resolve_type(field_type);
+                fields.push((field_name.clone(), ty));
+            }
+        }
+        self.structs.insert(name.to_string(), fields);
+    }
+
+    fn signature(&mut self, args: &[FunctionArg], return_type: &Node) -> Signature {
+        let has_receiver = args.first().map_or(false, |(_, name, _)| name == "self");
+        let params = args
+            .iter()
+            .skip(has_receiver as usize)
+            .map(|(arg_type, _, _)| self.resolve_type(arg_type))
+            .collect();
+        Signature {
+            params,
+            return_type: self.resolve_type(return_type),
+            is_var_arg: false,
+            has_receiver,
+        }
+    }
+
+    fn declare_function(&mut self, name: &str, args: &[FunctionArg], return_type: &Node) {
+        let signature = self.signature(args, return_type);
+        self.functions.insert(name.to_string(), signature);
+    }
+
+    fn declare_methods(&mut self, type_name: &str, members: &[Box<Node>]) {
+        // 未知の型へのimplは名前解決で報告済み
+        if !self.structs.contains_key(type_name) {
+            return;
+        }
+        let saved_impl = self.current_impl.replace(type_name.to_string());
+        for member in members {
+            if let NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                ref name,
+                ref args,
+                _,
+                ref return_type,
+                _,
+                _,
+            ))) = member.value
+            {
+                let signature = self.signature(args, return_type);
+                self.functions
+                    .insert(TypeChecker::method_symbol(type_name, name), signature);
+            }
+        }
+        self.current_impl = saved_impl;
+    }
+
+    fn method_symbol(type_name: &str, method: &str) -> String {
+        format!("{}::{}", type_name, method)
+    }
+
+    // システム関数のシグネチャ(デコーダの一覧と対応する)
+    fn system_function(name: &str) -> Option<Signature> {
+        let (params, return_type, is_var_arg) = match name {
+            "print" | "printf" => (vec![Type::Str], Type::Int(32, true), true),
+            "println" | "puts" => (vec![Type::Str], Type::Int(32, true), false),
+            "exit" => (vec![Type::Int(32, true)], Type::Unit, false),
+            "malloc" | "alloc" => (vec![Type::Int(64, true)], Type::Unknown, false),
+            "free" => (vec![Type::Unknown], Type::Unit, false),
+            _ => return None,
+        };
+        Some(Signature {
+            params,
+            return_type,
+            is_var_arg,
+            has_receiver: false,
+        })
+    }
+
+    fn declare_variable(&mut self, name: &str, ty: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), ty);
+        }
+    }
+
+    // 未定義の変数は名前解決で報告済みなので、ここではUnknownとして扱う
+    fn lookup_variable(&self, name: &str) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .unwrap_or(Type::Unknown)
+    }
+
+    // 文のチェーンを順に検査し、最後の文の型を返す
+    fn check_chain(&mut self, node: &Node) -> Type {
+        let mut last_type = Type::Unit;
+        for statement in node.iter() {
+            let statement = statement.borrow();
+            // else/else ifは直前のifと一緒に検査する
+            if let NodeValue::Statement(Statement::ControlFlow(ControlFlow::ElseIf(..)))
+            | NodeValue::Statement(Statement::ControlFlow(ControlFlow::Else(..))) =
+                statement.value
+            {
+                continue;
+            }
+            last_type = self.check(&statement);
+        }
+        last_type
+    }
+
+    fn check(&mut self, node: &Node) -> Type {
+        match node.value {
+            NodeValue::Expr(ref expr) => match *expr {
+                Expr::DataType(ref data_type) => match *data_type {
+                    DataType::Int(_) => Type::Int(64, true),
+                    DataType::Float(_) => Type::Float(64),
+                    DataType::Bool(_) => Type::Bool,
+                    DataType::String(_) => Type::Str,
+                    DataType::Unit(_) | DataType::Null => Type::Unit,
+                    DataType::Array(_, ref values) => self.check_array_literal(values),
+                    _ => Type::Unknown,
+                },
+                Expr::Operator(ref op) => self.check_operator(node, op),
+                Expr::Block(ref statements) => {
+                    self.scopes.push(HashMap::new());
+                    let mut last_type = Type::Unit;
+                    for statement in statements {
+                        last_type = self.check_chain(statement);
+                    }
+                    self.scopes.pop();
+                    last_type
+                }
+                Expr::Variable(_, ref name, is_mutable, is_reference, _) => {
+                    let ty = self.lookup_variable(name);
+                    if is_reference {
+                        Type::Reference(Box::new(ty), is_mutable)
+                    } else {
+                        ty
+                    }
+                }
+                Expr::Assign(ref target, ref value, _) => {
+                    let target_type = self.check(target);
+                    let value_type = self.check(value);
+                    self.expect_assignable(value, &target_type, &value_type, " in assignment");
+                    Type::Unit
+                }
+                Expr::Call(ref name, ref args, is_system) => {
+                    self.check_call(node, name, args, is_system)
+                }
+                Expr::StructInstance(ref name, ref fields) => {
+                    self.check_struct_instance(node, name, fields)
+                }
+                Expr::MemberAccess(ref base, ref member) => {
+                    self.check_member_access(node, base, member)
+                }
+                Expr::Index(ref base, ref index) => self.check_index(node, base, index),
+                Expr::EndStatement => Type::Unit,
+                _ => Type::Unknown,
+            },
+            NodeValue::Statement(ref statement) => match *statement {
+                Statement::Declaration(Declaration::Function(
+                    ref name,
+                    ref args,
+                    ref body,
+                    ref return_type,
+                    _,
+                    _,
+                )) => {
+                    self.check_function(name, args, body, return_type);
+                    Type::Unit
+                }
+                Statement::Declaration(Declaration::Variable(
+                    ref var,
+                    ref data_type,
+                    ref value,
+                    ..,
+                ))
+                | Statement::Declaration(Declaration::Const(ref var, ref data_type, ref value, ..)) => {
+                    self.check_variable_declaration(var, data_type, value);
+                    Type::Unit
+                }
+                Statement::Declaration(Declaration::Struct(ref name, ref members, _)) => {
+                    if !self.structs.contains_key(name) {
+                        self.declare_struct(name, members);
+                    }
+                    Type::Unit
+                }
+                Statement::Declaration(Declaration::Impl(ref type_name, ref members)) => {
+                    self.check_impl(type_name, members);
+                    Type::Unit
+                }
+                Statement::ControlFlow(ControlFlow::Return(ref value)) => {
+                    self.check_return(node, value);
+                    Type::Unit
+                }
+                Statement::ControlFlow(ControlFlow::If(ref condition, ref body)) => {
+                    self.check_if(node, condition, body)
+                }
+                Statement::ControlFlow(ControlFlow::While(ref condition, ref body)) => {
+                    self.check_condition(condition);
+                    self.check(body);
+                    Type::Unit
+                }
+                Statement::ControlFlow(ControlFlow::Loop(ref body)) => {
+                    self.check(body);
+                    Type::Unit
+                }
+                Statement::ControlFlow(ControlFlow::For(ref var, ref iterator, ref body)) => {
+                    self.check_for(var, iterator, body);
+                    Type::Unit
+                }
+                _ => Type::Unit,
+            },
+            NodeValue::SingleComment(..) | NodeValue::MultiComment(..) => Type::Unit,
+        }
+    }
+
+    // 関数本体は独立した変数表で検査する
+    fn check_function(
+        &mut self,
+        name: &str,
+        args: &[FunctionArg],
+        body: &Node,
+        return_type: &Node,
+    ) {
+        if !self.functions.contains_key(name) {
+            self.declare_function(name, args, return_type);
+        }
+        let return_type = self.resolve_type(return_type);
+        let saved_scopes = mem::replace(&mut self.scopes, vec![HashMap::new()]);
+        let saved_return = self.return_type.replace(return_type.clone());
+        for (arg_type, arg_name, _) in args {
+            let ty = self.resolve_type(arg_type);
+            self.declare_variable(arg_name, ty);
+        }
+        // 本体の最後の式の値は暗黙の戻り値になる
+        let body_type = self.check(body);
+        if return_type != Type::Unit && body_type != Type::Unit {
+            self.expect_assignable(body, &return_type, &body_type, " in return value");
+        }
+        self.scopes = saved_scopes;
+        self.return_type = saved_return;
+    }
+
+    fn check_impl(&mut self, type_name: &str, members: &[Box<Node>]) {
+        let is_declared = members.iter().all(|member| match member.value {
+            NodeValue::Statement(Statement::Declaration(Declaration::Function(ref name, ..))) => {
+                self.functions
+                    .contains_key(&TypeChecker::method_symbol(type_name, name))
+            }
+            _ => true,
+        });
+        if !is_declared {
+            self.declare_methods(type_name, members);
+        }
+        let saved_impl = self.current_impl.replace(type_name.to_string());
+        for member in members {
+            if let NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                ref name,
+                ref args,
+                ref body,
+                ref return_type,
+                _,
+                _,
+            ))) = member.value
+            {
+                let symbol = TypeChecker::method_symbol(type_name, name);
+                self.check_function(&symbol, args, body, return_type);
+            }
+        }
+        self.current_impl = saved_impl;
+    }
+
+    // let/const宣言: 型注釈があれば初期値と照合し、なければ初期値の型を変数の型にする
+    fn check_variable_declaration(&mut self, var: &Node, data_type: &Node, value: &Node) {
+        let name = match var.value {
+            NodeValue::Expr(Expr::Variable(_, ref name, ..)) => name.clone(),
+            _ => return,
+        };
+        let declared_type = match self.resolve_type(data_type) {
+            Type::Unit => None,
+            ty => Some(ty),
+        };
+        let value_type = match value.value {
+            NodeValue::Expr(Expr::DataType(DataType::Null)) => None,
+            _ => Some(self.check(value)),
+        };
+        let ty = match (declared_type, value_type) {
+            (Some(declared), Some(found)) => {
+                if let Some(diagnostic) = TypeChecker::mismatch(
+                    value,
+                    &declared,
+                    &found,
+                    &format!(" in the declaration of `{}`", name),
+                ) {
+                    self.errors
+                        .push(diagnostic.with_label(data_type.span, "expected due to this type"));
+                }
+                declared
+            }
+            (Some(declared), None) => declared,
+            (None, Some(Type::Unit)) => {
+                self.type_error(
+                    value,
+                    &format!("Cannot initialize `{}` with a value of type `()`", name),
+                );
+                Type::Unknown
+            }
+            (None, Some(found)) => found,
+            (None, None) => Type::Unknown,
+        };
+        self.declare_variable(&name, ty);
+    }
+
+    fn check_return(&mut self, node: &Node, value: &Node) {
+        let found = match value.value {
+            NodeValue::Expr(Expr::DataType(DataType::Null))
+            | NodeValue::Expr(Expr::DataType(DataType::Unit(_))) => Type::Unit,
+            _ => self.check(value),
+        };
+        // トップレベルのreturnは暗黙のmainの終了コードになる
+        let expected = match self.return_type {
+            Some(ref expected) => expected.clone(),
+            None => return,
+        };
+        match (&expected, &found) {
+            (_, Type::Unknown) => {}
+            (Type::Unit, Type::Unit) => {}
+            (Type::Unit, _) => self.type_error(
+                value,
+                &format!(
+                    "Cannot return a value of type `{}` from a function returning `()`",
+                    found
+                ),
+            ),
+            (_, Type::Unit) => self.type_error(
+                node,
+                &format!("Missing return value: expected `{}`", expected),
+            ),
+            _ => self.expect_assignable(value, &expected, &found, " in return value"),
+        }
+    }
+
+    // 条件式は真偽値か整数(0以外が真)でなければならない
+    fn check_condition(&mut self, condition: &Node) {
+        let ty = self.check(condition);
+        if !matches!(ty, Type::Bool | Type::Int(..) | Type::Unknown) {
+            self.type_error(
+                condition,
+                &format!("Mismatched types in condition: expected `bool`, found `{}`", ty),
+            );
+        }
+    }
+
+    // if/else if/else: elseがあり全ての分岐が同じ型の値を返す場合はその型になる
+    fn check_if(&mut self, node: &Node, condition: &Node, body: &Node) -> Type {
+        self.check_condition(condition);
+        let mut branch_types = vec![self.check(body)];
+        let mut has_else = false;
+        let mut next = node.next.borrow().clone();
+        while let Some(next_node) = next {
+            match next_node.value {
+                NodeValue::Statement(Statement::ControlFlow(ControlFlow::ElseIf(
+                    ref condition,
+                    ref body,
+                ))) => {
+                    self.check_condition(condition);
+                    branch_types.push(self.check(body));
+                    next = next_node.next.borrow().clone();
+                }
+                NodeValue::Statement(Statement::ControlFlow(ControlFlow::Else(ref body))) => {
+                    branch_types.push(self.check(body));
+                    has_else = true;
+                    break;
+                }
+                _ => break,
+            }
+        }
+        let first = branch_types[0].clone();
+        if has_else && branch_types.iter().all(|ty| *ty == first) {
+            first
+        } else {
+            Type::Unit
+        }
+    }
+
+    fn check_for(&mut self, var: &Node, iterator: &Node, body: &Node) {
+        let bounds = match iterator.value {
+            NodeValue::Expr(Expr::Operator(Operator::Range(
+                ref start,
+                ref end,
+                ref step,
+                _,
+            ))) => vec![start, end, step],
+            // 範囲以外の反復はデコーダが報告する
+            _ => {
+                self.check(iterator);
+                vec![]
+            }
+        };
+        let mut var_type = Type::Int(64, true);
+        for bound in bounds {
+            if let NodeValue::Expr(Expr::DataType(DataType::Null)) = bound.value {
+                continue;
+            }
+            let ty = self.check(bound);
+            match ty {
+                Type::Int(bits, _) if bits > 1 => {
+                    if let Type::Int(var_bits, _) = var_type {
+                        if bits > var_bits {
+                            var_type = ty;
+                        }
+                    }
+                }
+                Type::Unknown => {}
+                _ => self.type_error(
+                    bound,
+                    &format!("Range bounds in `for` must be integers, found `{}`", ty),
+                ),
+            }
+        }
+        self.scopes.push(HashMap::new());
+        if let NodeValue::Expr(Expr::Variable(_, ref name, ..)) = var.value {
+            self.declare_variable(name, var_type);
+        }
+        self.check(body);
+        self.scopes.pop();
+    }
+
+    fn operator_symbol(op: &Operator) -> &'static str {
+        match op {
+            Operator::Eq(..) => "==",
+            Operator::Ne(..) => "!=",
+            Operator::Lt(..) => "<",
+            Operator::Gt(..) => ">",
+            Operator::Le(..) => "<=",
+            Operator::Ge(..) => ">=",
+            Operator::And(..) => "&&",
+            Operator::Or(..) => "||",
+            Operator::Add(..) | Operator::AddAssign(..) => "+",
+            Operator::Sub(..) | Operator::SubAssign(..) => "-",
+            Operator::Mul(..) | Operator::MulAssign(..) => "*",
+            Operator::Div(..) | Operator::DivAssign(..) => "/",
+            Operator::Modulus(..) | Operator::ModulusAssign(..) => "%",
+            Operator::Increment(..) => "++",
+            Operator::Decrement(..) => "--",
+            Operator::BitAnd(..) | Operator::BitAndAssign(..) => "&",
+            Operator::BitOr(..) | Operator::BitOrAssign(..) => "|",
+            Operator::BitXor(..) | Operator::BitXorAssign(..) => "^",
+            Operator::BitNot(..) => "~",
+            Operator::ShiftLeft(..) | Operator::ShiftLeftAssign(..) => "<<",
+            Operator::ShiftRight(..) | Operator::ShiftRightAssign(..) => ">>",
+            Operator::Range(_, _, _, false) => "..",
+            Operator::Range(_, _, _, true) => "..=",
+        }
+    }
+
+    // 二項演算の結果の型(適用できない組み合わせはNone)
+    // 整数同士は広い方、整数と浮動小数点数はf64になる(デコーダの昇格規則と同じ)
+    fn binary_result(op: &Operator, lhs: &Type, rhs: &Type) -> Option<Type> {
+        let is_bitwise = matches!(
+            op,
+            Operator::BitAnd(..)
+                | Operator::BitOr(..)
+                | Operator::BitXor(..)
+                | Operator::BitAndAssign(..)
+                | Operator::BitOrAssign(..)
+                | Operator::BitXorAssign(..)
+        );
+        let is_shift = matches!(
+            op,
+            Operator::ShiftLeft(..)
+                | Operator::ShiftRight(..)
+                | Operator::ShiftLeftAssign(..)
+                | Operator::ShiftRightAssign(..)
+        );
+        match (lhs, rhs) {
+            (Type::Unknown, ty) | (ty, Type::Unknown) => Some(ty.clone()),
+            (Type::Bool, Type::Bool) if is_bitwise => Some(Type::Bool),
+            (Type::Int(l_bits, _), Type::Int(r_bits, _)) => {
+                Some(if r_bits > l_bits { rhs.clone() } else { lhs.clone() })
+            }
+            _ if is_bitwise || is_shift => None,
+            (Type::Float(l_bits), Type::Float(r_bits)) => Some(Type::Float(*l_bits.max(r_bits))),
+            (Type::Int(..), Type::Float(_)) | (Type::Float(_), Type::Int(..)) => {
+                Some(Type::Float(64))
+            }
+            _ => None,
+        }
+    }
+
+    fn check_operator(&mut self, node: &Node, op: &Operator) -> Type {
+        match *op {
+            Operator::Add(ref left, ref right)
+            | Operator::Sub(ref left, ref right)
+            | Operator::Mul(ref left, ref right)
+            | Operator::Div(ref left, ref right)
+            | Operator::Modulus(ref left, ref right)
+            | Operator::BitAnd(ref left, ref right)
+            | Operator::BitOr(ref left, ref right)
+            | Operator::BitXor(ref left, ref right)
+            | Operator::ShiftLeft(ref left, ref right)
+            | Operator::ShiftRight(ref left, ref right) => {
+                let lhs = self.check(left);
+                let rhs = self.check(right);
+                match TypeChecker::binary_result(op, &lhs, &rhs) {
+                    Some(ty) => ty,
+                    None => {
+                        self.errors.push(
+                            TypeChecker::new_type_error(
+                                node,
+                                &format!(
+                                    "Cannot apply `{}` to `{}` and `{}`",
+                                    TypeChecker::operator_symbol(op),
+                                    lhs,
+                                    rhs
+                                ),
+                            )
+                            .with_label(left.span, &format!("`{}`", lhs))
+                            .with_label(right.span, &format!("`{}`", rhs)),
+                        );
+                        Type::Unknown
+                    }
+                }
+            }
+            Operator::Eq(ref left, ref right)
+            | Operator::Ne(ref left, ref right)
+            | Operator::Lt(ref left, ref right)
+            | Operator::Gt(ref left, ref right)
+            | Operator::Le(ref left, ref right)
+            | Operator::Ge(ref left, ref right) => {
+                let lhs = self.check(left);
+                let rhs = self.check(right);
+                let is_comparable = (lhs.is_numeric() && rhs.is_numeric())
+                    || matches!(
+                        (&lhs, &rhs),
+                        (Type::Bool, Type::Bool) | (Type::Bool, Type::Unknown) | (Type::Unknown, Type::Bool)
+                    );
+                if !is_comparable {
+                    self.type_error(
+                        node,
+                        &format!(
+                            "Cannot compare `{}` with `{}` using `{}`",
+                            lhs,
+                            rhs,
+                            TypeChecker::operator_symbol(op)
+                        ),
+                    );
+                }
+                Type::Bool
+            }
+            Operator::And(ref left, ref right) | Operator::Or(ref left, ref right) => {
+                self.check_condition(left);
+                self.check_condition(right);
+                Type::Bool
+            }
+            Operator::BitNot(ref operand) => {
+                let ty = self.check(operand);
+                if !matches!(ty, Type::Int(..) | Type::Bool | Type::Unknown) {
+                    self.type_error(
+                        node,
+                        &format!("Cannot apply `~` to a value of type `{}`", ty),
+                    );
+                    return Type::Unknown;
+                }
+                ty
+            }
+            Operator::AddAssign(ref target, ref value)
+            | Operator::SubAssign(ref target, ref value)
+            | Operator::MulAssign(ref target, ref value)
+            | Operator::DivAssign(ref target, ref value)
+            | Operator::ModulusAssign(ref target, ref value)
+            | Operator::BitAndAssign(ref target, ref value)
+            | Operator::BitOrAssign(ref target, ref value)
+            | Operator::BitXorAssign(ref target, ref value)
+            | Operator::ShiftLeftAssign(ref target, ref value)
+            | Operator::ShiftRightAssign(ref target, ref value) => {
+                let target_type = self.check(target);
+                let value_type = self.check(value);
+                match TypeChecker::binary_result(op, &target_type, &value_type) {
+                    Some(result) => self.expect_assignable(
+                        value,
+                        &target_type,
+                        &result,
+                        &format!(" in `{}=`", TypeChecker::operator_symbol(op)),
+                    ),
+                    None => self.type_error(
+                        node,
+                        &format!(
+                            "Cannot apply `{}=` to `{}` and `{}`",
+                            TypeChecker::operator_symbol(op),
+                            target_type,
+                            value_type
+                        ),
+                    ),
+                }
+                Type::Unit
+            }
+            Operator::Increment(ref target, _) | Operator::Decrement(ref target, _) => {
+                let ty = self.check(target);
+                if !ty.is_numeric() {
+                    self.type_error(
+                        node,
+                        &format!(
+                            "Cannot apply `{}` to a value of type `{}`",
+                            TypeChecker::operator_symbol(op),
+                            ty
+                        ),
+                    );
+                    return Type::Unknown;
+                }
+                ty
+            }
+            Operator::Range(ref start, ref end, ref step, _) => {
+                self.check(start);
+                self.check(end);
+                self.check(step);
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_call(&mut self, node: &Node, name: &str, args: &[Node], is_system: bool) -> Type {
+        let signature = if is_system {
+            TypeChecker::system_function(name)
+                .or_else(|| self.host_functions.get(name).cloned())
+                .or_else(|| self.functions.get(name).cloned())
+        } else {
+            self.functions.get(name).cloned()
+        };
+        let display_name = if is_system {
+            format!("@{}", name)
+        } else {
+            name.to_string()
+        };
+        match signature {
+            Some(signature) => self.check_arguments(node, &display_name, &signature, args),
+            // 未定義の関数は名前解決で報告済み
+            None => {
+                for arg in args {
+                    self.check(arg);
+                }
+                Type::Unknown
+            }
+        }
+    }
+
+    // 引数の数と型を照合し、戻り値の型を返す
+    fn check_arguments(
+        &mut self,
+        node: &Node,
+        name: &str,
+        signature: &Signature,
+        args: &[Node],
+    ) -> Type {
+        let expected = signature.params.len();
+        if args.len() < expected || (!signature.is_var_arg && args.len() > expected) {
+            self.type_error(
+                node,
+                &format!(
+                    "Function `{}` takes {}{} argument{} but {} {} supplied",
+                    name,
+                    if signature.is_var_arg { "at least " } else { "" },
+                    expected,
+                    if expected == 1 { "" } else { "s" },
+                    args.len(),
+                    if args.len() == 1 { "was" } else { "were" }
+                ),
+            );
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let found = self.check(arg);
+            if let Some(param) = signature.params.get(i) {
+                if !TypeChecker::is_assignable(param, &found) {
+                    self.type_error(
+                        arg,
+                        &format!(
+                            "Argument {} of `{}` has the wrong type: expected `{}`, found `{}`",
+                            i + 1,
+                            name,
+                            param,
+                            found
+                        ),
+                    );
+                }
+            }
+        }
+        signature.return_type.clone()
+    }
+
+    // 配列リテラルの要素は最初の要素の型に揃える
+    fn check_array_literal(&mut self, values: &[Box<Node>]) -> Type {
+        let mut element_type = Type::Unknown;
+        for (i, value) in values.iter().enumerate() {
+            let found = self.check(value);
+            if i == 0 {
+                element_type = found;
+            } else {
+                self.expect_assignable(value, &element_type, &found, " in array literal");
+            }
+        }
+        Type::Array(Box::new(element_type), values.len())
+    }
+
+    fn check_index(&mut self, node: &Node, base: &Node, index: &Node) -> Type {
+        let base_type = self.check(base);
+        let index_type = self.check(index);
+        if !matches!(index_type, Type::Int(..) | Type::Unknown) {
+            self.type_error(
+                index,
+                &format!("Array index must be an integer, found `{}`", index_type),
+            );
+        }
+        match base_type.deref() {
+            Type::Array(element_type, _) => (**element_type).clone(),
+            Type::Unknown => Type::Unknown,
+            ty => {
+                self.type_error(node, &format!("Cannot index into a value of type `{}`", ty));
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_struct_instance(
+        &mut self,
+        node: &Node,
+        name: &str,
+        fields: &[(String, Box<Node>)],
+    ) -> Type {
+        // 未知の構造体は名前解決で報告済み
+        let declared = match self.structs.get(name) {
+            Some(declared) => declared.clone(),
+            None => {
+                for (_, value) in fields {
+                    self.check(value);
+                }
+                return Type::Unknown;
+            }
+        };
+        for (field_name, value) in fields {
+            let found = self.check(value);
+            match declared.iter().find(|(declared_name, _)| declared_name == field_name) {
+                Some((_, expected)) => self.expect_assignable(
+                    value,
+                    expected,
+                    &found,
+                    &format!(" for field `{}` of `{}`", field_name, name),
+                ),
+                None => self.unknown_field_error(node, name, field_name, &declared),
+            }
+        }
+        Type::Struct(name.to_string())
+    }
+
+    fn unknown_field_error(
+        &mut self,
+        node: &Node,
+        struct_name: &str,
+        field_name: &str,
+        fields: &[(String, Type)],
+    ) {
+        let names: Vec<&str> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        self.type_error_with_note(
+            node,
+            &format!("Struct `{}` has no field named `{}`", struct_name, field_name),
+            &format!("available fields are: {}", names.join(", ")),
+        );
+    }
+
+    fn check_member_access(&mut self, node: &Node, base: &Node, member: &Node) -> Type {
+        let base_type = self.check(base);
+        let struct_name = match base_type.deref() {
+            Type::Struct(name) => Some(name.clone()),
+            Type::Unknown => None,
+            ty => {
+                let message = match member.value {
+                    NodeValue::Expr(Expr::Call(ref method, ..)) => {
+                        format!("No method named `{}` found for type `{}`", method, ty)
+                    }
+                    _ => format!("Cannot access a field of a value of type `{}`", ty),
+                };
+                self.type_error(node, &message);
+                None
+            }
+        };
+
+        match member.value {
+            NodeValue::Expr(Expr::Call(ref method, ref args, _)) => {
+                let struct_name = match struct_name {
+                    Some(name) => name,
+                    None => {
+                        for arg in args {
+                            self.check(arg);
+                        }
+                        return Type::Unknown;
+                    }
+                };
+                let symbol = TypeChecker::method_symbol(&struct_name, method);
+                match self.functions.get(&symbol).cloned() {
+                    Some(signature) if signature.has_receiver => {
+                        self.check_arguments(node, &symbol, &signature, args)
+                    }
+                    Some(_) => {
+                        self.type_error(
+                            node,
+                            &format!("`{}` is an associated function, not a method", symbol),
+                        );
+                        Type::Unknown
+                    }
+                    None => {
+                        self.type_error(
+                            node,
+                            &format!(
+                                "No method named `{}` found for struct `{}`",
+                                method, struct_name
+                            ),
+                        );
+                        Type::Unknown
+                    }
+                }
+            }
+            NodeValue::Expr(Expr::Variable(_, ref field_name, ..)) => {
+                let struct_name = match struct_name {
+                    Some(name) => name,
+                    None => return Type::Unknown,
+                };
+                let fields = self.structs.get(&struct_name).cloned().unwrap_or_default();
+                match fields.iter().find(|(name, _)| name == field_name) {
+                    Some((_, ty)) => ty.clone(),
+                    None => {
+                        self.unknown_field_error(member, &struct_name, field_name, &fields);
+                        Type::Unknown
+                    }
+                }
+            }
+            _ => Type::Unknown,
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::types::R;
+extern crate inkwell as iw;
+use iw::{
+    module::Module,
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+        TargetTriple,
+    },
+    OptimizationLevel,
+};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// 実行可能ファイルのリンクに使うコマンド(環境変数CCで上書きできる)
+pub const DEFAULT_LINKER: &str = "cc";
+
+// 生成するファイルの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    LlvmIr,     // .ll
+    Bitcode,    // .bc
+    Assembly,   // .s
+    Object,     // .o
+    Executable, // リンク済みの実行可能ファイル
+}
+
+impl OutputKind {
+    // 出力先の拡張子から種類を決める(不明な拡張子や拡張子なしは実行可能ファイル)
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ll") => OutputKind::LlvmIr,
+            Some("bc") => OutputKind::Bitcode,
+            Some("s") | Some("asm") => OutputKind::Assembly,
+            Some("o") | Some("obj") => OutputKind::Object,
+            _ => OutputKind::Executable,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputKind::LlvmIr => "ll",
+            OutputKind::Bitcode => "bc",
+            OutputKind::Assembly => "s",
+            OutputKind::Object => "o",
+            OutputKind::Executable => "",
+        }
+    }
+}
+
+// TargetMachineを使ってモジュールをファイルに書き出す
+pub struct Emitter {
+    target_machine: TargetMachine,
+}
+
+impl Emitter {
+    // tripleを省略するとホストのターゲットを使う
+    pub fn new(triple: Option<&str>, opt_level: OptimizationLevel) -> R<Self, String> {
+        let config = InitializationConfig::default();
+        let (triple, cpu, features) = match triple {
+            Some(triple) => {
+                // クロスコンパイルでは全ターゲットを初期化し、CPUは汎用のものにする
+                Target::initialize_all(&config);
+                (
+                    TargetTriple::create(triple),
+                    String::from("generic"),
+                    String::new(),
+                )
+            }
+            None => {
+                Target::initialize_native(&config)
+                    .map_err(|e| format!("Failed to initialize the native target: {}", e))?;
+                (
+                    TargetMachine::get_default_triple(),
+                    TargetMachine::get_host_cpu_name().to_string(),
+                    TargetMachine::get_host_cpu_features().to_string(),
+                )
+            }
+        };
+        let target = Target::from_triple(&triple)
+            .map_err(|e| format!("Unknown target `{}`: {}", triple, e))?;
+        // 実行可能ファイルは-pieでリンクするため、位置独立コードで生成する
+        let target_machine = target
+            .create_target_machine(
+                &triple,
+                &cpu,
+                &features,
+                opt_level,
+                RelocMode::PIC,
+                CodeModel::Default,
+            )
+            .ok_or_else(|| format!("Failed to create a target machine for `{}`", triple))?;
+        Ok(Emitter { target_machine })
+    }
+
+    pub fn target_machine(&self) -> &TargetMachine {
+        &self.target_machine
+    }
+
+    // モジュールのターゲット情報をこのTargetMachineに合わせる
+    pub fn configure_module(&self, module: &Module) {
+        module.set_triple(&self.target_machine.get_triple());
+        module.set_data_layout(&self.target_machine.get_target_data().get_data_layout());
+    }
+
+    // 実行可能ファイルはオブジェクトファイルを経由してリンクする
+    pub fn emit(&self, module: &Module, kind: OutputKind, path: &Path) -> R<(), String> {
+        self.configure_module(module);
+        match kind {
+            OutputKind::LlvmIr => module
+                .print_to_file(path)
+                .map_err(|e| format!("Failed to write `{}`: {}", path.display(), e)),
+            OutputKind::Bitcode => {
+                if module.write_bitcode_to_path(path) {
+                    Ok(())
+                } else {
+                    Err(format!("Failed to write `{}`", path.display()))
+                }
+            }
+            OutputKind::Assembly => self.write_file(module, FileType::Assembly, path),
+            OutputKind::Object => self.write_file(module, FileType::Object, path),
+            OutputKind::Executable => {
+                // 出力先の`.o`を上書きしないよう、中間ファイルは一時ディレクトリに一意な名前で作る
+                static OBJECT_COUNT: AtomicUsize = AtomicUsize::new(0);
+                let object_path = env::temp_dir().join(format!(
+                    "tanucc-{}-emit-{}.{}",
+                    process::id(),
+                    OBJECT_COUNT.fetch_add(1, Ordering::SeqCst),
+                    OutputKind::Object.extension()
+                ));
+                self.write_file(module, FileType::Object, &object_path)?;
+                let result = link(std::slice::from_ref(&object_path), path);
+                // 中間のオブジェクトファイルはリンクの成否にかかわらず残さない
+                let _ = std::fs::remove_file(&object_path);
+                result
+            }
+        }
+    }
+
+    fn write_file(&self, module: &Module, file_type: FileType, path: &Path) -> R<(), String> {
+        self.target_machine
+            .write_to_file(module, file_type, path)
+            .map_err(|e| format!("Failed to write `{}`: {}", path.display(), e))
+    }
+}
+
+// システムのリンカでオブジェクトファイルをリンクし、実行可能ファイルを生成する
+pub fn link(objects: &[PathBuf], output: &Path) -> R<(), String> {
+    let linker = env::var("CC").unwrap_or_else(|_| String::from(DEFAULT_LINKER));
+    let status = Command::new(&linker)
+        .args(objects)
+        .arg("-o")
+        .arg(output)
+        .arg("-pie")
+        .status()
+        .map_err(|e| format!("Failed to run the linker `{}`: {}", linker, e))?;
+    if !status.success() {
+        return Err(format!(
+            "The linker `{}` exited with {} while linking `{}`",
+            linker,
+            status,
+            output.display()
+        ));
+    }
+    Ok(())
+}
@@ -1,62 +1,3568 @@
-use crate::parser::syntax::{Parser,Node};
-use crate::types::{R,IRValue,Expr,Statement,Operator,DataType,NodeValue};
+use crate::error::Diagnostic;
+use crate::parser::syntax::Node;
+use crate::source::SourceMap;
+use crate::{compile_error, compile_error_with_children};
+use crate::types::{
+    ControlFlow, DataType, Declaration, Expr, FunctionArg, HostType, IRValue, NodeValue,
+    Operator, Statement, R,
+};
 extern crate inkwell as iw;
 use iw::{
+    basic_block::BasicBlock,
     builder::Builder,
     context::Context,
-    module::Module,
-    types::{BasicType, BasicTypeEnum, StructType},
-    values::{AnyValue, AsValueRef, BasicValue, BasicValueEnum, FunctionValue, IntValue},
-    AddressSpace,
+    module::{Linkage, Module},
+    types::{
+        AnyTypeEnum, BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType,
+    },
+    values::{
+        BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, GlobalValue, IntValue,
+        PointerValue,
+    },
+    AddressSpace, FloatPredicate, IntPredicate,
 };
+use std::collections::HashMap;
+use std::mem;
+
+// トップレベルの文を格納する暗黙のエントリ関数名
+pub const ENTRY_FUNCTION_NAME: &str = "main";
+
+// ホスト関数のシンボル名の接頭辞(スクリプトの関数名と衝突しないよう`.`を含める)
+pub const HOST_FUNCTION_PREFIX: &str = "host.";
+
+// ローカル変数やそのフィールドの格納場所(スタック上の領域, 型, 可変フラグ)
+#[derive(Debug, Clone, Copy)]
+struct Variable<'ctx> {
+    pointer: PointerValue<'ctx>,
+    ty: BasicTypeEnum<'ctx>,
+    is_mutable: bool,
+    pointee_mutable: bool, // 値が`&mut`で得たポインタで、参照先を変更できるか
+}
+
+// メソッドのselfレシーバの受け取り方
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Receiver {
+    None,             // selfなし(関連関数)
+    Value,            // self
+    Reference,        // &self
+    MutableReference, // &mut self
+}
+
+// 構造体定義(LLVMの名前付き構造体型, 定義順のフィールド名)
+#[derive(Debug, Clone)]
+struct StructInfo<'ctx> {
+    ty: StructType<'ctx>,
+    fields: Vec<String>,
+}
+
+// REPLの入力をまたいで使うトップレベルの変数(グローバル変数として出力される)
+#[derive(Debug, Clone)]
+pub struct GlobalVariable<'ctx> {
+    pub name: String,   // スクリプト上の変数名
+    pub symbol: String, // グローバル変数のシンボル名
+    pub ty: BasicTypeEnum<'ctx>,
+    pub is_mutable: bool,
+}
+
+// ループの分岐先(continueの飛び先, breakの飛び先)
+#[derive(Debug, Clone, Copy)]
+struct LoopContext<'ctx> {
+    continue_block: BasicBlock<'ctx>,
+    break_block: BasicBlock<'ctx>,
+}
+
+pub struct Decoder<'ctx> {
+    input_path: String,
+    input_content: String,
+    source_map: SourceMap, // 読み込んだファイル(ノードの範囲からエラーのファイルを求める)
+    has_user_entry_function: bool,
+    unit_entry_function: bool, // ユーザー定義のmainが()を返す(終了コード0を返すi32の関数として出力する)
+    scopes: Vec<HashMap<String, Variable<'ctx>>>, // ブロックごとの変数表(末尾が最も内側)
+    loop_stack: Vec<LoopContext<'ctx>>,          // 生成中のループ(末尾が最も内側)
+    strings: HashMap<String, GlobalValue<'ctx>>, // 文字列リテラルのグローバル定数(同じ内容は共有する)
+    structs: HashMap<String, StructInfo<'ctx>>,  // 構造体名 -> 構造体定義
+    methods: HashMap<String, Receiver>,          // メソッドのシンボル名 -> レシーバ
+    current_impl: Option<String>,                // 生成中のimplの型名(`Self`の解決に使う)
+    bounds_check: bool,                          // 添字アクセスに実行時の範囲検査を入れるか
+    host_functions: HashMap<String, (Vec<HostType>, HostType)>, // ホスト関数名 -> (引数, 戻り値)
+    entry_function_name: String,                 // トップレベルの文を格納する関数名
+    incremental: bool,                           // REPL用: 他のモジュールから関数・変数を参照できるようにする
+    print_result: bool,                          // REPL用: 最後の式の値を表示する
+    global_variables: Vec<GlobalVariable<'ctx>>, // このモジュールで定義したトップレベルの変数
+}
+impl<'ctx> Decoder<'ctx> {
+    pub fn new(input_path: &str, input_content: String) -> Self {
+        Decoder {
+            input_path: input_path.to_string(),
+            input_content,
+            source_map: SourceMap::new(),
+            has_user_entry_function: false,
+            unit_entry_function: false,
+            scopes: vec![HashMap::new()],
+            loop_stack: Vec::new(),
+            strings: HashMap::new(),
+            structs: HashMap::new(),
+            methods: HashMap::new(),
+            current_impl: None,
+            bounds_check: true,
+            host_functions: HashMap::new(),
+            entry_function_name: ENTRY_FUNCTION_NAME.to_string(),
+            incremental: false,
+            print_result: false,
+            global_variables: Vec::new(),
+        }
+    }
+
+    // トップレベルの文を格納する関数名を変える(REPLでは入力ごとに別の名前にする)
+    pub fn set_entry_function_name(&mut self, name: &str) {
+        self.entry_function_name = name.to_string();
+    }
+    pub fn entry_function_name(&self) -> String {
+        self.entry_function_name.clone()
+    }
+
+    // 有効にすると全ての関数を外部リンケージで出力し、トップレベルの変数をグローバル変数にする
+    // 後から生成する別のモジュールからdeclare_definitionsとimport_global_variableで参照できる
+    pub fn set_incremental(&mut self, enabled: bool) {
+        self.incremental = enabled;
+    }
+
+    // 有効にするとトップレベルの最後の式の値をprintfで表示する
+    pub fn set_print_result(&mut self, enabled: bool) {
+        self.print_result = enabled;
+    }
+
+    // このモジュールで定義したトップレベルの変数(incrementalが有効な場合のみ)
+    pub fn global_variables(&self) -> &[GlobalVariable<'ctx>] {
+        &self.global_variables
+    }
+
+    // 別のモジュールで定義済みのトップレベルの変数を外部のグローバル変数として宣言する
+    pub fn import_global_variable(&mut self, module: &Module<'ctx>, variable: &GlobalVariable<'ctx>) {
+        let global = match module.get_global(&variable.symbol) {
+            Some(global) => global,
+            None => {
+                let global = module.add_global(variable.ty, None, &variable.symbol);
+                global.set_linkage(Linkage::External);
+                global
+            }
+        };
+        if let Some(scope) = self.scopes.first_mut() {
+            scope.insert(
+                variable.name.clone(),
+                Variable {
+                    pointer: global.as_pointer_value(),
+                    ty: variable.ty,
+                    is_mutable: variable.is_mutable,
+                    pointee_mutable: false,
+                },
+            );
+        }
+    }
+
+    // ホストが登録した関数を`@名前`で呼べるようにする
+    // 生成されるのは外部シンボルの宣言だけで、実体のアドレスはJIT側で割り当てる
+    pub fn register_host_function(&mut self, name: &str, params: &[HostType], return_type: HostType) {
+        self.host_functions
+            .insert(name.to_string(), (params.to_vec(), return_type));
+    }
+
+    // ホスト関数として宣言されるシンボル名
+    pub fn host_symbol(name: &str) -> String {
+        format!("{}{}", HOST_FUNCTION_PREFIX, name)
+    }
+
+    // 実行時の配列の範囲検査を有効/無効にする(定数の添字はどちらでもコンパイル時に検査する)
+    pub fn set_bounds_check(&mut self, enabled: bool) {
+        self.bounds_check = enabled;
+    }
+    pub fn input_content(&self) -> String {
+        self.input_content.clone()
+    }
+    pub fn input_path(&self) -> String {
+        self.input_path.clone()
+    }
+    pub fn set_source_map(&mut self, source_map: &SourceMap) {
+        self.source_map = source_map.clone();
+    }
+    // ノードがあるファイルの内容(SourceMapにない場合は入力の内容)
+    fn source_content(&self, node: &Node) -> String {
+        self.source_map
+            .file(node.span.file)
+            .map_or_else(|| self.input_content(), |file| file.content.clone())
+    }
+    fn source_path(&self, node: &Node) -> String {
+        self.source_map
+            .file(node.span.file)
+            .map_or_else(|| self.input_path(), |file| file.path.clone())
+    }
+
+    // Parser::parseが返す文のチェーンを先頭から順に生成する
+    // 実行文は暗黙のmain関数に出力され、最後の値(整数の場合)が終了コードになる
+    pub fn decode_program(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        self.declare_definitions(context, module, node)?;
+
+        let mut last_value = IRValue::None;
+        for statement in node.iter() {
+            let statement = statement.borrow();
+            // else/else ifは直前のifと一緒に生成済み
+            if Decoder::is_else_branch(&statement) {
+                continue;
+            }
+            if builder.get_insert_block().is_none() && Decoder::is_executable(&statement) {
+                if self.has_user_entry_function {
+                    return Err(compile_error!(
+                        "error",
+                        statement.line,
+                        statement.column,
+                        statement.span,
+                        &self.source_path(&statement),
+                        &self.source_content(&statement),
+                        "Top-level statements cannot be combined with a user-defined `{}` function",
+                        self.entry_function_name
+                    ));
+                }
+                self.build_entry_function(builder, context, module);
+            }
+            // return以降の実行文は到達不能なので生成しない
+            if Decoder::is_executable(&statement) && Decoder::is_terminated(builder) {
+                continue;
+            }
+            last_value = self.decode(builder, context, module, &statement)?;
+        }
+        self.finish_entry_function(builder, context, module, &last_value)?;
+        Ok(last_value)
+    }
+
+    // トップレベルの構造体を定義し、関数とメソッドのシグネチャを宣言する
+    // REPLでは前の入力で定義済みのものを、本体のない外部宣言として取り込むのにも使う
+    pub fn declare_definitions(
+        &mut self,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+    ) -> R<(), Diagnostic> {
+        // 関数のシグネチャで使えるよう、トップレベルの構造体を先に定義する
+        for statement in node.iter() {
+            let statement = statement.borrow();
+            if let NodeValue::Statement(Statement::Declaration(Declaration::Struct(
+                ref name,
+                ref members,
+                _,
+            ))) = statement.value
+            {
+                if self.structs.contains_key(name) {
+                    return Err(compile_error!(
+                        "error",
+                        statement.line,
+                        statement.column,
+                        statement.span,
+                        &self.source_path(&statement),
+                        &self.source_content(&statement),
+                        "Struct `{}` is defined more than once",
+                        name
+                    ));
+                }
+                self.decode_struct(context, name, members)?;
+            }
+        }
+        // 定義より前の呼び出しを解決できるよう、トップレベルの関数を先に宣言する
+        for statement in node.iter() {
+            let statement = statement.borrow();
+            if let NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                ref name,
+                ref args,
+                _,
+                ref return_type,
+                is_system,
+                is_public,
+            ))) = statement.value
+            {
+                self.declare_function(
+                    context,
+                    module,
+                    &statement,
+                    name,
+                    args,
+                    return_type,
+                    is_system,
+                    is_public,
+                )?;
+            }
+            if let NodeValue::Statement(Statement::Declaration(Declaration::Impl(
+                ref type_name,
+                ref members,
+            ))) = statement.value
+            {
+                self.declare_methods(context, module, &statement, type_name, members)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 文のチェーンを順に生成し、最後の文の値を返す
+    fn decode_chain(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let mut last_value = IRValue::None;
+        for statement in node.iter() {
+            let statement = statement.borrow();
+            if Decoder::is_else_branch(&statement) {
+                continue;
+            }
+            if Decoder::is_terminated(builder) {
+                break;
+            }
+            last_value = self.decode(builder, context, module, &statement)?;
+        }
+        Ok(last_value)
+    }
+
+    fn is_else_branch(node: &Node) -> bool {
+        matches!(
+            node.value,
+            NodeValue::Statement(Statement::ControlFlow(ControlFlow::ElseIf(..)))
+                | NodeValue::Statement(Statement::ControlFlow(ControlFlow::Else(..)))
+        )
+    }
+
+    // 現在のブロックが既に終端命令(ret, br)で閉じているか
+    fn is_terminated(builder: &Builder<'ctx>) -> bool {
+        builder
+            .get_insert_block()
+            .and_then(|block| block.get_terminator())
+            .is_some()
+    }
+
+    // 暗黙のmain関数に出力する必要がある文かどうか
+    fn is_executable(node: &Node) -> bool {
+        match node.value {
+            NodeValue::Statement(Statement::Declaration(Declaration::Variable(..)))
+            | NodeValue::Statement(Statement::Declaration(Declaration::Const(..))) => true,
+            NodeValue::Statement(Statement::Declaration(_)) => false,
+            NodeValue::SingleComment(..) | NodeValue::MultiComment(..) => false,
+            NodeValue::Expr(Expr::EndStatement) => false,
+            _ => true,
+        }
+    }
+
+    fn build_entry_function(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+    ) {
+        let function = match module.get_function(&self.entry_function_name) {
+            Some(function) => function,
+            None => {
+                let fn_type = context.i32_type().fn_type(&[], false);
+                module.add_function(&self.entry_function_name, fn_type, None)
+            }
+        };
+        let block = match function.get_last_basic_block() {
+            Some(block) => block,
+            None => context.append_basic_block(function, "entry"),
+        };
+        builder.position_at_end(block);
+    }
+
+    // 暗黙のmain関数を終端する(終端命令が既にある場合は何もしない)
+    fn finish_entry_function(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        last_value: &IRValue<'ctx>,
+    ) -> R<(), Diagnostic> {
+        if module.get_function(&self.entry_function_name).is_none() || self.has_user_entry_function {
+            return Ok(());
+        }
+        let block = match builder.get_insert_block() {
+            Some(block) => block,
+            None => return Ok(()),
+        };
+        if block.get_terminator().is_some() {
+            return Ok(());
+        }
+        if self.print_result {
+            if let IRValue::BasicValue(value) = *last_value {
+                self.build_print_result(builder, context, module, value)?;
+            }
+        }
+        let i32_type = context.i32_type();
+        let exit_code = match *last_value {
+            IRValue::BasicValue(BasicValueEnum::IntValue(value)) => builder
+                .build_int_cast_sign_flag(value, i32_type, true, "exitcode")
+                .map_err(|e| e.to_string())?,
+            _ => i32_type.const_zero(),
+        };
+        builder
+            .build_return(Some(&exit_code))
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // REPL用: 値を型に応じた書式でprintfする(表示できない型の値は何もしない)
+    fn build_print_result(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        value: BasicValueEnum<'ctx>,
+    ) -> R<(), Diagnostic> {
+        let (format, args): (&str, Vec<BasicMetadataValueEnum<'ctx>>) = match value {
+            BasicValueEnum::IntValue(value) if value.get_type().get_bit_width() == 1 => {
+                let true_string = self.build_result_string(builder, "true")?;
+                let false_string = self.build_result_string(builder, "false")?;
+                let string = builder
+                    .build_select(value, true_string, false_string, "bool.str")
+                    .map_err(|e| e.to_string())?;
+                ("%s\n", vec![string.into()])
+            }
+            BasicValueEnum::IntValue(value) => {
+                let value = builder
+                    .build_int_cast_sign_flag(value, context.i64_type(), true, "result")
+                    .map_err(|e| e.to_string())?;
+                ("%lld\n", vec![value.into()])
+            }
+            BasicValueEnum::FloatValue(value) => {
+                let value = builder
+                    .build_float_cast(value, context.f64_type(), "result")
+                    .map_err(|e| e.to_string())?;
+                ("%g\n", vec![value.into()])
+            }
+            BasicValueEnum::StructValue(value) if Decoder::is_string_type(value.get_type()) => {
+                let pointer = builder
+                    .build_extract_value(value, 0, "str.ptr")
+                    .map_err(|e| e.to_string())?;
+                let length = builder
+                    .build_extract_value(value, 1, "str.len")
+                    .map_err(|e| e.to_string())?
+                    .into_int_value();
+                let length = builder
+                    .build_int_cast(length, context.i32_type(), "str.len")
+                    .map_err(|e| e.to_string())?;
+                ("\"%.*s\"\n", vec![length.into(), pointer.into()])
+            }
+            _ => return Ok(()),
+        };
+        let format = self.build_result_string(builder, format)?;
+        let printf = self.declare_system_function(context, module, &Node::default(), "printf")?;
+        let mut call_args: Vec<BasicMetadataValueEnum<'ctx>> = vec![format.into()];
+        call_args.extend(args);
+        builder
+            .build_call(printf, &call_args, "")
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
 
-pub struct Decoder;
-impl Decoder {
-    pub fn new()->Self{Decoder{}}
-    pub fn decode<'ctx>(&mut self, builder: &'ctx mut Builder, context: &'ctx Context, module: &'ctx mut Module, node: &Node) -> R<IRValue<'ctx>, String> {
+    fn build_result_string(
+        &self,
+        builder: &Builder<'ctx>,
+        value: &str,
+    ) -> R<BasicValueEnum<'ctx>, Diagnostic> {
+        let global = builder
+            .build_global_string_ptr(value, "result.fmt")
+            .map_err(|e| e.to_string())?;
+        Ok(global.as_pointer_value().as_basic_value_enum())
+    }
+
+    pub fn decode(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
         match &node.value {
-            NodeValue::Expr(ref expr) => {
-                match *expr {
-                    Expr::DataType(ref datatype) => {
-                        match *datatype {
-                            DataType::Int(value) => {
-                                let int_value = context.i64_type().const_int(value as u64, false);
-                                Ok(IRValue::BasicValue(int_value.as_basic_value_enum()))
-                            },
-                            DataType::Float(value) => {
-                                let float_value = context.f64_type().const_float(value);
-                                Ok(IRValue::BasicValue(float_value.as_basic_value_enum()))
-                            },
-                            _ => todo!(),
+            NodeValue::Expr(ref expr) => match *expr {
+                Expr::DataType(ref datatype) => match *datatype {
+                    DataType::Int(value) => {
+                        let int_value = context.i64_type().const_int(value as u64, false);
+                        Ok(IRValue::BasicValue(int_value.as_basic_value_enum()))
+                    }
+                    DataType::Float(value) => {
+                        let float_value = context.f64_type().const_float(value);
+                        Ok(IRValue::BasicValue(float_value.as_basic_value_enum()))
+                    }
+                    DataType::Bool(value) => {
+                        let bool_value = context.bool_type().const_int(value as u64, false);
+                        Ok(IRValue::BasicValue(bool_value.as_basic_value_enum()))
+                    }
+                    DataType::String(ref value) => self.decode_string(builder, context, value),
+                    DataType::Array(_, ref values) => {
+                        self.decode_array_literal(builder, context, module, node, values)
+                    }
+                    DataType::Null => Err(self.unsupported(node, "`null`")),
+                    DataType::Unit(_) => Err(self.unsupported(node, "`()` as a value")),
+                    DataType::Generic(..) => Err(self.unsupported(node, "A generic type")),
+                    DataType::FixedArray(..) => {
+                        Err(self.unsupported(node, "An array type as a value"))
+                    }
+                },
+                Expr::Operator(ref op) => self.decode_operator(builder, context, module, node, op),
+                Expr::Block(ref statements) => {
+                    self.scopes.push(HashMap::new());
+                    let result = self.decode_block(builder, context, module, statements);
+                    self.scopes.pop();
+                    result
+                }
+                Expr::Variable(_, ref name, is_mutable, is_reference, _) => {
+                    self.decode_variable(builder, node, name, is_mutable, is_reference)
+                }
+                Expr::Assign(ref target, ref value, ref index) => {
+                    self.decode_assign(builder, context, module, node, target, value, index)
+                }
+                Expr::Call(ref name, ref args, is_system) => {
+                    self.decode_call(builder, context, module, node, name, args, is_system)
+                }
+                Expr::StructInstance(ref name, ref fields) => {
+                    self.decode_struct_instance(builder, context, module, node, name, fields)
+                }
+                Expr::MemberAccess(ref base, ref member) => {
+                    self.decode_member_access(builder, context, module, node, base, member)
+                }
+                Expr::Index(ref base, ref index) => {
+                    self.decode_index(builder, context, module, node, base, index)
+                }
+                Expr::EndStatement => Ok(IRValue::None),
+                Expr::ScopeResolution(..) => Err(self.unsupported(node, "A path expression")),
+                Expr::Use(..) => Err(self.unsupported(node, "`use`")),
+            },
+            NodeValue::Statement(ref statement) => match *statement {
+                Statement::Declaration(Declaration::Function(
+                    ref name,
+                    ref args,
+                    ref body,
+                    ref return_type,
+                    is_system,
+                    is_public,
+                )) => self.decode_function(
+                    builder,
+                    context,
+                    module,
+                    node,
+                    name,
+                    args,
+                    body,
+                    return_type,
+                    is_system,
+                    is_public,
+                ),
+                Statement::Declaration(Declaration::Variable(
+                    ref var,
+                    ref data_type,
+                    ref value,
+                    _,
+                    is_mutable,
+                    _,
+                )) => self.decode_variable_declaration(
+                    builder, context, module, node, var, data_type, value, is_mutable,
+                ),
+                Statement::Declaration(Declaration::Const(
+                    ref var,
+                    ref data_type,
+                    ref value,
+                    _,
+                    _,
+                )) => self.decode_variable_declaration(
+                    builder, context, module, node, var, data_type, value, false,
+                ),
+                Statement::Declaration(Declaration::Struct(ref name, ref members, _)) => {
+                    // トップレベルの構造体はdecode_programで定義済み
+                    if !self.structs.contains_key(name) {
+                        self.decode_struct(context, name, members)?;
+                    }
+                    Ok(IRValue::None)
+                }
+                Statement::Declaration(Declaration::Impl(ref type_name, ref members)) => {
+                    self.decode_impl(builder, context, module, node, type_name, members)
+                }
+                Statement::ControlFlow(ControlFlow::Return(ref value)) => {
+                    self.decode_return(builder, context, module, node, value)
+                }
+                Statement::ControlFlow(ControlFlow::If(ref condition, ref body)) => {
+                    self.decode_if(builder, context, module, node, condition, body)
+                }
+                Statement::ControlFlow(ControlFlow::ElseIf(..))
+                | Statement::ControlFlow(ControlFlow::Else(..)) => Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "`else` without a preceding `if`"
+                )),
+                Statement::ControlFlow(ControlFlow::While(ref condition, ref body)) => {
+                    self.decode_while(builder, context, module, node, condition, body)
+                }
+                Statement::ControlFlow(ControlFlow::Loop(ref body)) => {
+                    self.decode_loop(builder, context, module, node, body)
+                }
+                Statement::ControlFlow(ControlFlow::For(ref var, ref iterator, ref body)) => {
+                    self.decode_for(builder, context, module, node, var, iterator, body)
+                }
+                Statement::ControlFlow(ControlFlow::Break) => {
+                    self.decode_loop_jump(builder, node, true)
+                }
+                Statement::ControlFlow(ControlFlow::Continue) => {
+                    self.decode_loop_jump(builder, node, false)
+                }
+                Statement::Declaration(Declaration::Type(..)) => {
+                    Err(self.unsupported(node, "A `type` alias"))
+                }
+                Statement::Declaration(Declaration::CallBackFunction(..)) => {
+                    Err(self.unsupported(node, "`callback fn`"))
+                }
+                Statement::Declaration(Declaration::UserSyntax(..)) => {
+                    Err(self.unsupported(node, "User-defined syntax"))
+                }
+                Statement::Use(..) => Err(self.unsupported(node, "`use`")),
+                Statement::Mod(..) | Statement::ModDeclaration(..) => {
+                    Err(self.unsupported(node, "`mod`"))
+                }
+            },
+            NodeValue::SingleComment(..) | NodeValue::MultiComment(..) => Ok(IRValue::None),
+        }
+    }
+
+    // 関数定義を生成する
+    fn decode_function(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        name: &str,
+        args: &[FunctionArg],
+        body: &Node,
+        return_type: &Node,
+        is_system: bool,
+        is_public: bool,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let function = self.declare_function(
+            context,
+            module,
+            node,
+            name,
+            args,
+            return_type,
+            is_system,
+            is_public,
+        )?;
+        if function.count_basic_blocks() > 0 {
+            return Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Function `{}` is defined more than once",
+                name
+            ));
+        }
+        let ret_type = self.return_type_of(function);
+
+        // 関数本体は独立した変数表で生成し、終わったら呼び出し元の挿入位置と変数表へ戻す
+        let saved_block = builder.get_insert_block();
+        let saved_scopes = mem::replace(&mut self.scopes, vec![HashMap::new()]);
+        let saved_loops = mem::take(&mut self.loop_stack);
+        let entry = context.append_basic_block(function, "entry");
+        builder.position_at_end(entry);
+        let result = self
+            .bind_parameters(builder, context, node, function, args)
+            .and_then(|_| self.decode(builder, context, module, body))
+            .and_then(|body_value| {
+                self.finish_function(builder, context, node, name, function, ret_type, body_value)
+            });
+        self.scopes = saved_scopes;
+        self.loop_stack = saved_loops;
+        match saved_block {
+            Some(block) => builder.position_at_end(block),
+            None => builder.clear_insertion_position(),
+        }
+        result?;
+        Ok(IRValue::Function(function))
+    }
+
+    // 関数のシグネチャを宣言する(宣言済みならそれを返す)
+    // 公開関数(およびmain・システム関数)は外部リンケージ、それ以外は内部リンケージになる
+    fn declare_function(
+        &mut self,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        name: &str,
+        args: &[FunctionArg],
+        return_type: &Node,
+        is_system: bool,
+        is_public: bool,
+    ) -> R<FunctionValue<'ctx>, Diagnostic> {
+        let mut param_types: Vec<BasicMetadataTypeEnum<'ctx>> = Vec::new();
+        for (arg_type, arg_name, _) in args {
+            match self.resolve_type(context, arg_type)? {
+                Some(ty) => param_types.push(ty.into()),
+                None => {
+                    return Err(compile_error!(
+                        "error",
+                        arg_type.line,
+                        arg_type.column,
+                        arg_type.span,
+                        &self.source_path(arg_type),
+                        &self.source_content(arg_type),
+                        "Parameter `{}` of function `{}` needs a non-unit type annotation",
+                        arg_name,
+                        name
+                    ))
+                }
+            }
+        }
+        let ret_type = self.resolve_type(context, return_type)?;
+        let is_entry = name == self.entry_function_name;
+        // mainは引数なしで呼ばれ、戻り値が終了コードになる
+        if is_entry
+            && (!args.is_empty() || ret_type.is_some_and(|ty| ty != context.i32_type().into()))
+        {
+            return Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "The `{}` function must take no parameters and return `()` or `i32`",
+                name
+            ));
+        }
+        let fn_type = match ret_type {
+            Some(ty) => ty.fn_type(&param_types, false),
+            None if is_entry => {
+                self.unit_entry_function = true;
+                context.i32_type().fn_type(&param_types, false)
+            }
+            None => context.void_type().fn_type(&param_types, false),
+        };
+        let linkage = if self.incremental
+            || is_public
+            || is_system
+            || name == self.entry_function_name
+        {
+            Linkage::External
+        } else {
+            Linkage::Internal
+        };
+        let function = match module.get_function(name) {
+            Some(function) => {
+                function.set_linkage(linkage);
+                function
+            }
+            None => module.add_function(name, fn_type, Some(linkage)),
+        };
+        if is_entry {
+            self.has_user_entry_function = true;
+        }
+        for (param, (_, arg_name, _)) in function.get_param_iter().zip(args.iter()) {
+            param.set_name(arg_name);
+        }
+        Ok(function)
+    }
+
+    // 引数をスタック領域にコピーし、関数の変数表に登録する
+    fn bind_parameters(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        node: &Node,
+        function: FunctionValue<'ctx>,
+        args: &[FunctionArg],
+    ) -> R<(), Diagnostic> {
+        for (param, (arg_type, arg_name, _)) in function.get_param_iter().zip(args.iter()) {
+            let ty = param.get_type();
+            let pointer = self.build_entry_alloca(builder, context, node, ty, arg_name)?;
+            builder
+                .build_store(pointer, param)
+                .map_err(|e| e.to_string())?;
+            self.declare_variable(
+                arg_name,
+                Variable {
+                    pointer,
+                    ty,
+                    is_mutable: false,
+                    pointee_mutable: Decoder::is_mutable_reference(arg_type),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn decode_block(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        statements: &[Box<Node>],
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let mut last_value = IRValue::None;
+        for statement in statements {
+            if Decoder::is_terminated(builder) {
+                break;
+            }
+            last_value = self.decode_chain(builder, context, module, statement)?;
+        }
+        Ok(last_value)
+    }
+
+    // let/const宣言: 入口ブロックに領域を確保して初期値を格納する
+    // 型注釈がない場合は初期値の型を使う
+    fn decode_variable_declaration(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        var: &Node,
+        data_type: &Node,
+        value: &Node,
+        is_mutable: bool,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let name = self.variable_name(var)?;
+        let declared_type = self.resolve_type(context, data_type)?;
+        let initial_value = match value.value {
+            NodeValue::Expr(Expr::DataType(DataType::Null)) => None,
+            _ => {
+                let value_ir = self.decode(builder, context, module, value)?;
+                Some(self.expect_value(value, value_ir)?)
+            }
+        };
+        let ty = match (declared_type, initial_value) {
+            (Some(ty), _) => ty,
+            (None, Some(value)) => value.get_type(),
+            (None, None) => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Type annotations needed for `{}`",
+                    name
+                ))
+            }
+        };
+        let pointer = if self.is_global_scope(builder) {
+            self.build_global_variable(module, &name, ty, is_mutable)
+        } else {
+            self.build_entry_alloca(builder, context, node, ty, &name)?
+        };
+        if let Some(value) = initial_value {
+            let value = self.coerce_value(builder, node, value, ty)?;
+            builder
+                .build_store(pointer, value)
+                .map_err(|e| e.to_string())?;
+        }
+        self.declare_variable(
+            &name,
+            Variable {
+                pointer,
+                ty,
+                is_mutable,
+                pointee_mutable: Decoder::is_mutable_reference(value),
+            },
+        );
+        Ok(IRValue::None)
+    }
+
+    // incrementalが有効で、エントリ関数のトップレベルを生成中か
+    fn is_global_scope(&self, builder: &Builder<'ctx>) -> bool {
+        self.incremental
+            && self.scopes.len() == 1
+            && builder
+                .get_insert_block()
+                .and_then(|block| block.get_parent())
+                .is_some_and(|function| {
+                    function.get_name().to_bytes() == self.entry_function_name.as_bytes()
+                })
+    }
+
+    // トップレベルの変数を、エントリ関数名を接頭辞にしたグローバル変数として出力する
+    // (同じ名前の変数を別の入力で定義し直してもシンボルが衝突しない)
+    fn build_global_variable(
+        &mut self,
+        module: &Module<'ctx>,
+        name: &str,
+        ty: BasicTypeEnum<'ctx>,
+        is_mutable: bool,
+    ) -> PointerValue<'ctx> {
+        let global = module.add_global(ty, None, &format!("{}.{}", self.entry_function_name, name));
+        global.set_initializer(&ty.const_zero());
+        self.global_variables.push(GlobalVariable {
+            name: name.to_string(),
+            symbol: global.get_name().to_string_lossy().to_string(),
+            ty,
+            is_mutable,
+        });
+        global.as_pointer_value()
+    }
+
+    // 変数の読み出し(参照の場合はアドレスをそのまま返す)
+    fn decode_variable(
+        &self,
+        builder: &Builder<'ctx>,
+        node: &Node,
+        name: &str,
+        is_mutable: bool,
+        is_reference: bool,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let variable = self.lookup_variable(node, name)?;
+        if is_reference {
+            if is_mutable && !variable.is_mutable {
+                return Err(compile_error_with_children!(
+                    "error",
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    node.line,
+                    node.column,
+                    node.span,
+                    &format!(
+                        "Cannot borrow `{}` as mutable, as it is not declared as mutable",
+                        name
+                    ),
+                    "note",
+                    &format!("consider changing this to be mutable: `let mut {}`", name)
+                ));
+            }
+            return Ok(IRValue::BasicValue(
+                variable.pointer.as_basic_value_enum(),
+            ));
+        }
+        let value = builder
+            .build_load(variable.ty, variable.pointer, name)
+            .map_err(|e| e.to_string())?;
+        Ok(IRValue::BasicValue(value))
+    }
+
+    // 代入: 可変な変数にのみ値を格納できる
+    fn decode_assign(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        target: &Node,
+        value: &Node,
+        index: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        if index.value != NodeValue::Expr(Expr::DataType(DataType::Null)) {
+            return Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Indexed assignment is not supported"
+            ));
+        }
+        let variable = self.assignable_variable(builder, context, module, node, target)?;
+        let value_ir = self.decode(builder, context, module, value)?;
+        let new_value = self.expect_value(value, value_ir)?;
+        let new_value = self.coerce_value(builder, value, new_value, variable.ty)?;
+        builder
+            .build_store(variable.pointer, new_value)
+            .map_err(|e| e.to_string())?;
+        Ok(IRValue::None)
+    }
+
+    // 複合代入: 現在値を読み出して演算し、代入先の型に戻して格納する
+    fn decode_compound_assign(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        op: &Operator,
+        target: &Node,
+        value: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let variable = self.assignable_variable(builder, context, module, node, target)?;
+        let current = builder
+            .build_load(variable.ty, variable.pointer, "loadtmp")
+            .map_err(|e| e.to_string())?;
+        let value_ir = self.decode(builder, context, module, value)?;
+        let rhs = self.expect_value(value, value_ir)?;
+        let result = self.build_arithmetic(builder, context, node, op, current, rhs)?;
+        let result = self.coerce_value(builder, node, result, variable.ty)?;
+        builder
+            .build_store(variable.pointer, result)
+            .map_err(|e| e.to_string())?;
+        Ok(IRValue::None)
+    }
+
+    // ++/--: 前置は更新後の値、後置は更新前の値を返す
+    fn decode_increment(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        target: &Node,
+        is_prefix: bool,
+        is_increment: bool,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let variable = self.assignable_variable(builder, context, module, node, target)?;
+        let current = builder
+            .build_load(variable.ty, variable.pointer, "loadtmp")
+            .map_err(|e| e.to_string())?;
+        let updated = match current {
+            BasicValueEnum::IntValue(value) => {
+                let one = value.get_type().const_int(1, false);
+                if is_increment {
+                    builder.build_int_add(value, one, "inctmp")
+                } else {
+                    builder.build_int_sub(value, one, "dectmp")
+                }
+                .map_err(|e| e.to_string())?
+                .as_basic_value_enum()
+            }
+            BasicValueEnum::FloatValue(value) => {
+                let one = value.get_type().const_float(1.0);
+                if is_increment {
+                    builder.build_float_add(value, one, "inctmp")
+                } else {
+                    builder.build_float_sub(value, one, "dectmp")
+                }
+                .map_err(|e| e.to_string())?
+                .as_basic_value_enum()
+            }
+            _ => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Cannot {} a value of type {:?}",
+                    if is_increment { "increment" } else { "decrement" },
+                    variable.ty
+                ))
+            }
+        };
+        builder
+            .build_store(variable.pointer, updated)
+            .map_err(|e| e.to_string())?;
+        Ok(IRValue::BasicValue(if is_prefix { updated } else { current }))
+    }
+
+    // 代入先(変数, フィールド, 配列の要素)を探し、可変でなければエラーにする
+    fn assignable_variable(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        target: &Node,
+    ) -> R<Variable<'ctx>, Diagnostic> {
+        let variable = self.decode_place(builder, context, module, target)?;
+        if !variable.is_mutable {
+            let name = self.root_variable_name(target)?;
+            return Err(compile_error_with_children!(
+                "error",
+                &self.source_path(node),
+                &self.source_content(node),
+                node.line,
+                node.column,
+                node.span,
+                &format!("Cannot assign twice to immutable variable `{}`", name),
+                "note",
+                &format!("consider making this binding mutable: `let mut {}`", name)
+            ));
+        }
+        Ok(variable)
+    }
+
+    fn declare_variable(&mut self, name: &str, variable: Variable<'ctx>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), variable);
+        }
+    }
+
+    // 内側のスコープから順に変数を探す
+    fn lookup_variable(&self, node: &Node, name: &str) -> R<Variable<'ctx>, Diagnostic> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+            .ok_or_else(|| {
+                compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Undefined variable `{}`",
+                    name
+                )
+            })
+    }
+
+    fn variable_name(&self, node: &Node) -> R<String, Diagnostic> {
+        match node.value {
+            NodeValue::Expr(Expr::Variable(_, ref name, _, _, _)) => Ok(name.clone()),
+            _ => Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Expected a variable name, found: {:?}",
+                node.value
+            )),
+        }
+    }
+
+    // 関数の入口ブロックにallocaを配置する(mem2regで昇格できるように)
+    fn build_entry_alloca(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        node: &Node,
+        ty: BasicTypeEnum<'ctx>,
+        name: &str,
+    ) -> R<PointerValue<'ctx>, Diagnostic> {
+        let function = self.current_function(builder, node)?;
+        let entry = function
+            .get_first_basic_block()
+            .ok_or_else(|| "Function has no entry block".to_string())?;
+        let entry_builder = context.create_builder();
+        match entry.get_first_instruction() {
+            Some(instruction) => entry_builder.position_before(&instruction),
+            None => entry_builder.position_at_end(entry),
+        }
+        entry_builder
+            .build_alloca(ty, name)
+            .map_err(|e| e.to_string().into())
+    }
+
+    // 構文解析と検査は通るが、まだIRを生成できない構文のエラー
+    fn unsupported(&self, node: &Node, what: &str) -> Diagnostic {
+        compile_error!(
+            "error",
+            node.line,
+            node.column,
+            node.span,
+            &self.source_path(node),
+            &self.source_content(node),
+            "{} is not supported yet",
+            what
+        )
+    }
+
+    // 値を生成しない式が値として使われた場合はエラーにする
+    fn expect_value(
+        &self,
+        node: &Node,
+        value: IRValue<'ctx>,
+    ) -> R<BasicValueEnum<'ctx>, Diagnostic> {
+        match value {
+            IRValue::BasicValue(value) => Ok(value),
+            _ => Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Expression does not produce a value"
+            )),
+        }
+    }
+
+    // 関数末尾に終端命令がなければ暗黙のretを補う
+    fn finish_function(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        node: &Node,
+        name: &str,
+        function: FunctionValue<'ctx>,
+        ret_type: Option<BasicTypeEnum<'ctx>>,
+        body_value: IRValue<'ctx>,
+    ) -> R<(), Diagnostic> {
+        let block = match builder.get_insert_block() {
+            Some(block) => block,
+            None => return Ok(()),
+        };
+        if block.get_terminator().is_some() {
+            return Ok(());
+        }
+        match (ret_type, body_value) {
+            (None, _) => self.build_unit_return(builder, context, function)?,
+            (Some(ty), IRValue::BasicValue(value)) => {
+                let value = self.coerce_value(builder, node, value, ty)?;
+                builder
+                    .build_return(Some(&value))
+                    .map_err(|e| e.to_string())?;
+            }
+            (Some(_), _) => {
+                // 全ての分岐がreturnした後の合流ブロック(どこからも分岐されない)
+                if block.get_first_use().is_none()
+                    && function.get_first_basic_block() != Some(block)
+                {
+                    builder.build_unreachable().map_err(|e| e.to_string())?;
+                } else {
+                    return Err(compile_error!(
+                        "error",
+                        node.line,
+                        node.column,
+                        node.span,
+                        &self.source_path(node),
+                        &self.source_content(node),
+                        "Function `{}` must return a value",
+                        name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_return(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        value: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let function = self.current_function(builder, node)?;
+        let ret_type = self.return_type_of(function);
+        let value = match value.value {
+            NodeValue::Expr(Expr::DataType(DataType::Null))
+            | NodeValue::Expr(Expr::DataType(DataType::Unit(_))) => IRValue::None,
+            _ => self.decode(builder, context, module, value)?,
+        };
+        match (ret_type, value) {
+            (None, IRValue::None) => self.build_unit_return(builder, context, function)?,
+            (Some(ty), IRValue::BasicValue(value)) => {
+                let value = self.coerce_value(builder, node, value, ty)?;
+                builder
+                    .build_return(Some(&value))
+                    .map_err(|e| e.to_string())?;
+            }
+            (None, _) => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Cannot return a value from a function returning ()"
+                ))
+            }
+            (Some(_), _) => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Missing return value"
+                ))
+            }
+        }
+        Ok(IRValue::None)
+    }
+
+    // if/else if/else: 各分岐から合流ブロックへ分岐する
+    // elseがあり全ての分岐が同じ型の値を返す場合はphiで合流させ、その値を返す
+    fn decode_if(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        condition: &Node,
+        body: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let function = self.current_function(builder, node)?;
+
+        // ifノードのnextに連なるelse if/elseを集める
+        let mut branches: Vec<(Option<Box<Node>>, Box<Node>)> =
+            vec![(Some(Box::new(condition.clone())), Box::new(body.clone()))];
+        let mut next = node.next.borrow().clone();
+        while let Some(next_node) = next {
+            match next_node.value {
+                NodeValue::Statement(Statement::ControlFlow(ControlFlow::ElseIf(
+                    ref condition,
+                    ref body,
+                ))) => {
+                    branches.push((Some(condition.clone()), body.clone()));
+                    next = next_node.next.borrow().clone();
+                }
+                NodeValue::Statement(Statement::ControlFlow(ControlFlow::Else(ref body))) => {
+                    branches.push((None, body.clone()));
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        let merge_block = context.append_basic_block(function, "if.end");
+        let mut incoming: Vec<(Option<BasicValueEnum<'ctx>>, BasicBlock<'ctx>)> = Vec::new();
+        let count = branches.len();
+        for (i, (condition, body)) in branches.iter().enumerate() {
+            if let Some(condition) = condition {
+                let condition_value = self.decode_condition(builder, context, module, condition)?;
+                let then_block = context.append_basic_block(function, "if.then");
+                // 最後の条件が偽ならelseなしで合流ブロックへ
+                let else_block = if i + 1 < count {
+                    context.append_basic_block(function, "if.else")
+                } else {
+                    merge_block
+                };
+                builder
+                    .build_conditional_branch(condition_value, then_block, else_block)
+                    .map_err(|e| e.to_string())?;
+                if else_block == merge_block {
+                    incoming.push((None, builder.get_insert_block().unwrap()));
+                }
+                builder.position_at_end(then_block);
+                let value = self.decode(builder, context, module, body)?;
+                self.branch_to_merge(builder, merge_block, value, &mut incoming)?;
+                if else_block != merge_block {
+                    builder.position_at_end(else_block);
+                }
+            } else {
+                let value = self.decode(builder, context, module, body)?;
+                self.branch_to_merge(builder, merge_block, value, &mut incoming)?;
+            }
+        }
+        builder.position_at_end(merge_block);
+
+        // 全ての流入元が同じ型の値を持つ場合のみphiを作る
+        let values: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)> = incoming
+            .iter()
+            .filter_map(|(value, block)| value.map(|value| (value, *block)))
+            .collect();
+        if values.is_empty()
+            || values.len() != incoming.len()
+            || values
+                .iter()
+                .any(|(value, _)| value.get_type() != values[0].0.get_type())
+        {
+            return Ok(IRValue::None);
+        }
+        let phi = builder
+            .build_phi(values[0].0.get_type(), "if.value")
+            .map_err(|e| e.to_string())?;
+        for (value, block) in &values {
+            phi.add_incoming(&[(value as &dyn BasicValue<'ctx>, *block)]);
+        }
+        Ok(IRValue::BasicValue(phi.as_basic_value()))
+    }
+
+    // 分岐の末尾が終端していなければ合流ブロックへ分岐し、流入元として記録する
+    fn branch_to_merge(
+        &self,
+        builder: &Builder<'ctx>,
+        merge_block: BasicBlock<'ctx>,
+        value: IRValue<'ctx>,
+        incoming: &mut Vec<(Option<BasicValueEnum<'ctx>>, BasicBlock<'ctx>)>,
+    ) -> R<(), Diagnostic> {
+        if Decoder::is_terminated(builder) {
+            return Ok(());
+        }
+        let block = builder.get_insert_block().unwrap();
+        builder
+            .build_unconditional_branch(merge_block)
+            .map_err(|e| e.to_string())?;
+        let value = match value {
+            IRValue::BasicValue(value) => Some(value),
+            _ => None,
+        };
+        incoming.push((value, block));
+        Ok(())
+    }
+
+    fn decode_while(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        condition: &Node,
+        body: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let function = self.current_function(builder, node)?;
+        let cond_block = context.append_basic_block(function, "while.cond");
+        let body_block = context.append_basic_block(function, "while.body");
+        let end_block = context.append_basic_block(function, "while.end");
+
+        builder
+            .build_unconditional_branch(cond_block)
+            .map_err(|e| e.to_string())?;
+        builder.position_at_end(cond_block);
+        let condition_value = self.decode_condition(builder, context, module, condition)?;
+        builder
+            .build_conditional_branch(condition_value, body_block, end_block)
+            .map_err(|e| e.to_string())?;
+
+        builder.position_at_end(body_block);
+        self.decode_loop_body(builder, context, module, body, cond_block, end_block)?;
+        builder.position_at_end(end_block);
+        Ok(IRValue::None)
+    }
+
+    fn decode_loop(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        body: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let function = self.current_function(builder, node)?;
+        let body_block = context.append_basic_block(function, "loop.body");
+        let end_block = context.append_basic_block(function, "loop.end");
+
+        builder
+            .build_unconditional_branch(body_block)
+            .map_err(|e| e.to_string())?;
+        builder.position_at_end(body_block);
+        self.decode_loop_body(builder, context, module, body, body_block, end_block)?;
+        builder.position_at_end(end_block);
+        Ok(IRValue::None)
+    }
+
+    // 範囲for文: カウンタ変数を増分ずつ進める計数ループ
+    // 増分が負の場合は終点に向かって減少させる
+    fn decode_for(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        var: &Node,
+        iterator: &Node,
+        body: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let (start, end, step, is_inclusive) = match iterator.value {
+            NodeValue::Expr(Expr::Operator(Operator::Range(
+                ref start,
+                ref end,
+                ref step,
+                is_inclusive,
+            ))) => (start, end, step, is_inclusive),
+            _ => {
+                return Err(compile_error!(
+                    "error",
+                    iterator.line,
+                    iterator.column,
+                    iterator.span,
+                    &self.source_path(iterator),
+                    &self.source_content(iterator),
+                    "Only ranges can be iterated with `for`"
+                ))
+            }
+        };
+        let name = self.variable_name(var)?;
+        let function = self.current_function(builder, node)?;
+
+        // 端点と増分は一度だけ評価し、最も広い整数型に揃える
+        let start_value = self.decode_range_bound(builder, context, module, start)?;
+        let end_value = self.decode_range_bound(builder, context, module, end)?;
+        let (start_value, end_value) = match self.promote_operands(
+            builder,
+            context,
+            iterator,
+            start_value.as_basic_value_enum(),
+            end_value.as_basic_value_enum(),
+        )? {
+            (BasicValueEnum::IntValue(s), BasicValueEnum::IntValue(e)) => (s, e),
+            _ => unreachable!(),
+        };
+        let step_value = match step.value {
+            NodeValue::Expr(Expr::DataType(DataType::Null)) => {
+                start_value.get_type().const_int(1, false)
+            }
+            _ => {
+                let step_value = self.decode_range_bound(builder, context, module, step)?;
+                let width = start_value.get_type().get_bit_width();
+                if step_value.get_type().get_bit_width() > width {
+                    return Err(compile_error!(
+                        "error",
+                        step.line,
+                        step.column,
+                        step.span,
+                        &self.source_path(step),
+                        &self.source_content(step),
+                        "Range step is wider than its bounds (i{} > i{})",
+                        step_value.get_type().get_bit_width(),
+                        width
+                    ));
+                }
+                let step_value = self.extend_int(builder, step_value, start_value)?;
+                // 増分が0だと終わらないので、定数ならここで、そうでなければ実行時に弾く
+                if step_value.get_sign_extended_constant() == Some(0) {
+                    return Err(compile_error!(
+                        "error",
+                        step.line,
+                        step.column,
+                        step.span,
+                        &self.source_path(step),
+                        &self.source_content(step),
+                        "Range step cannot be zero"
+                    ));
+                }
+                if !step_value.is_const() {
+                    let is_zero = builder
+                        .build_int_compare(
+                            IntPredicate::EQ,
+                            step_value,
+                            step_value.get_type().const_zero(),
+                            "for.zero",
+                        )
+                        .map_err(|e| e.to_string())?;
+                    let zero_block = context.append_basic_block(function, "for.zero_step");
+                    let init_block = context.append_basic_block(function, "for.init");
+                    builder
+                        .build_conditional_branch(is_zero, zero_block, init_block)
+                        .map_err(|e| e.to_string())?;
+                    builder.position_at_end(zero_block);
+                    self.build_runtime_error(
+                        builder,
+                        context,
+                        module,
+                        step,
+                        "range step cannot be zero",
+                        &[],
+                    )?;
+                    builder.position_at_end(init_block);
+                }
+                step_value
+            }
+        };
+
+        let ty = start_value.get_type().as_basic_type_enum();
+        let counter = self.build_entry_alloca(builder, context, node, ty, &name)?;
+        builder
+            .build_store(counter, start_value)
+            .map_err(|e| e.to_string())?;
+        let is_ascending = builder
+            .build_int_compare(
+                IntPredicate::SGE,
+                step_value,
+                step_value.get_type().const_zero(),
+                "for.ascending",
+            )
+            .map_err(|e| e.to_string())?;
+
+        let cond_block = context.append_basic_block(function, "for.cond");
+        let body_block = context.append_basic_block(function, "for.body");
+        let step_block = context.append_basic_block(function, "for.step");
+        let end_block = context.append_basic_block(function, "for.end");
+        builder
+            .build_unconditional_branch(cond_block)
+            .map_err(|e| e.to_string())?;
+
+        // for.cond: 増加時は i < end (i <= end)、減少時は i > end (i >= end)
+        builder.position_at_end(cond_block);
+        let current = builder
+            .build_load(ty, counter, &name)
+            .map_err(|e| e.to_string())?
+            .into_int_value();
+        let (up, down) = if is_inclusive {
+            (IntPredicate::SLE, IntPredicate::SGE)
+        } else {
+            (IntPredicate::SLT, IntPredicate::SGT)
+        };
+        let up_cond = builder
+            .build_int_compare(up, current, end_value, "for.up")
+            .map_err(|e| e.to_string())?;
+        let down_cond = builder
+            .build_int_compare(down, current, end_value, "for.down")
+            .map_err(|e| e.to_string())?;
+        let condition = builder
+            .build_select(is_ascending, up_cond, down_cond, "for.cond")
+            .map_err(|e| e.to_string())?
+            .into_int_value();
+        builder
+            .build_conditional_branch(condition, body_block, end_block)
+            .map_err(|e| e.to_string())?;
+
+        // for.body: ループ変数は本体のスコープで不変の変数として見える
+        builder.position_at_end(body_block);
+        self.scopes.push(HashMap::new());
+        self.declare_variable(
+            &name,
+            Variable {
+                pointer: counter,
+                ty,
+                is_mutable: false,
+                pointee_mutable: false,
+            },
+        );
+        let result =
+            self.decode_loop_body(builder, context, module, body, step_block, end_block);
+        self.scopes.pop();
+        result?;
+
+        // for.step: カウンタを進めて条件へ戻る
+        // 型の範囲を越える(`..=`の終端が最大値の場合など)ときは一周せずにループを抜ける
+        builder.position_at_end(step_block);
+        let current = builder
+            .build_load(ty, counter, &name)
+            .map_err(|e| e.to_string())?
+            .into_int_value();
+        let sadd = Decoder::declare_runtime_function(
+            module,
+            &format!(
+                "llvm.sadd.with.overflow.i{}",
+                step_value.get_type().get_bit_width()
+            ),
+            context
+                .struct_type(&[ty, context.bool_type().into()], false)
+                .fn_type(&[ty.into(), ty.into()], false),
+        );
+        let sum = match builder
+            .build_call(sadd, &[current.into(), step_value.into()], "for.sum")
+            .map_err(|e| e.to_string())?
+            .try_as_basic_value()
+            .left()
+        {
+            Some(value) => value.into_struct_value(),
+            None => unreachable!(),
+        };
+        let next = builder
+            .build_extract_value(sum, 0, "for.next")
+            .map_err(|e| e.to_string())?;
+        let overflow = builder
+            .build_extract_value(sum, 1, "for.overflow")
+            .map_err(|e| e.to_string())?
+            .into_int_value();
+        builder
+            .build_store(counter, next)
+            .map_err(|e| e.to_string())?;
+        builder
+            .build_conditional_branch(overflow, end_block, cond_block)
+            .map_err(|e| e.to_string())?;
+
+        builder.position_at_end(end_block);
+        Ok(IRValue::None)
+    }
+
+    fn decode_range_bound(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+    ) -> R<IntValue<'ctx>, Diagnostic> {
+        let value = self.decode(builder, context, module, node)?;
+        match self.expect_value(node, value)? {
+            BasicValueEnum::IntValue(value) => Ok(value),
+            value => Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Range bounds must be integers, found {:?}",
+                value.get_type()
+            )),
+        }
+    }
+
+    // ループ本体を生成し、末尾からcontinue先へ戻る
+    fn decode_loop_body(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        body: &Node,
+        continue_block: BasicBlock<'ctx>,
+        break_block: BasicBlock<'ctx>,
+    ) -> R<(), Diagnostic> {
+        self.loop_stack.push(LoopContext {
+            continue_block,
+            break_block,
+        });
+        let result = self.decode(builder, context, module, body);
+        self.loop_stack.pop();
+        result?;
+        if !Decoder::is_terminated(builder) {
+            builder
+                .build_unconditional_branch(continue_block)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    // break/continue: 最も内側のループの飛び先へ分岐する
+    fn decode_loop_jump(
+        &self,
+        builder: &Builder<'ctx>,
+        node: &Node,
+        is_break: bool,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let keyword = if is_break { "break" } else { "continue" };
+        let loop_context = match self.loop_stack.last() {
+            Some(loop_context) => *loop_context,
+            None => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "`{}` outside of a loop",
+                    keyword
+                ))
+            }
+        };
+        let target = if is_break {
+            loop_context.break_block
+        } else {
+            loop_context.continue_block
+        };
+        builder
+            .build_unconditional_branch(target)
+            .map_err(|e| e.to_string())?;
+        Ok(IRValue::None)
+    }
+
+    // 条件式をi1に変換する(整数・浮動小数点数は0と比較する)
+    fn decode_condition(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        condition: &Node,
+    ) -> R<IntValue<'ctx>, Diagnostic> {
+        let value = self.decode(builder, context, module, condition)?;
+        match self.expect_value(condition, value)? {
+            BasicValueEnum::IntValue(value) if value.get_type().get_bit_width() == 1 => Ok(value),
+            BasicValueEnum::IntValue(value) => builder
+                .build_int_compare(
+                    IntPredicate::NE,
+                    value,
+                    value.get_type().const_zero(),
+                    "cond",
+                )
+                .map_err(|e| e.to_string().into()),
+            BasicValueEnum::FloatValue(value) => builder
+                .build_float_compare(
+                    FloatPredicate::ONE,
+                    value,
+                    value.get_type().const_zero(),
+                    "cond",
+                )
+                .map_err(|e| e.to_string().into()),
+            _ => Err(compile_error!(
+                "error",
+                condition.line,
+                condition.column,
+                condition.span,
+                &self.source_path(condition),
+                &self.source_content(condition),
+                "Condition must be a boolean or numeric value"
+            )),
+        }
+    }
+
+    fn current_function(
+        &self,
+        builder: &Builder<'ctx>,
+        node: &Node,
+    ) -> R<FunctionValue<'ctx>, Diagnostic> {
+        builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| {
+                compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Statement is not inside a function"
+                )
+            })
+    }
+
+    // 言語上の関数の戻り値の型(()を返すmainはi32の関数として出力しているが、()として扱う)
+    fn return_type_of(&self, function: FunctionValue<'ctx>) -> Option<BasicTypeEnum<'ctx>> {
+        if self.is_unit_entry_function(function) {
+            None
+        } else {
+            function.get_type().get_return_type()
+        }
+    }
+
+    // 値を返さないreturnを生成する(()を返すmainは終了コード0を返す)
+    fn build_unit_return(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        function: FunctionValue<'ctx>,
+    ) -> R<(), Diagnostic> {
+        if self.is_unit_entry_function(function) {
+            builder.build_return(Some(&context.i32_type().const_zero()))
+        } else {
+            builder.build_return(None)
+        }
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn is_unit_entry_function(&self, function: FunctionValue<'ctx>) -> bool {
+        self.unit_entry_function
+            && function.get_name().to_bytes() == self.entry_function_name.as_bytes()
+    }
+
+    // メソッドのシンボル名(型ごとに名前空間を分ける)
+    fn method_symbol(type_name: &str, method: &str) -> String {
+        format!("{}::{}", type_name, method)
+    }
+
+    // implのメソッドを宣言し、レシーバの受け取り方を記録する
+    fn declare_methods(
+        &mut self,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        type_name: &str,
+        members: &[Box<Node>],
+    ) -> R<(), Diagnostic> {
+        if !self.structs.contains_key(type_name) {
+            return Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Cannot define methods for unknown type `{}`",
+                type_name
+            ));
+        }
+        let saved_impl = self.current_impl.replace(type_name.to_string());
+        let result = members.iter().try_for_each(|member| {
+            if let NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                ref name,
+                ref args,
+                _,
+                ref return_type,
+                is_system,
+                is_public,
+            ))) = member.value
+            {
+                let symbol = Decoder::method_symbol(type_name, name);
+                self.declare_function(
+                    context,
+                    module,
+                    member,
+                    &symbol,
+                    args,
+                    return_type,
+                    is_system,
+                    is_public,
+                )?;
+                let receiver = match args.first() {
+                    Some((arg_type, arg_name, _)) if arg_name == "self" => match arg_type.value {
+                        NodeValue::Expr(Expr::Variable(_, _, true, true, _)) => {
+                            Receiver::MutableReference
                         }
+                        NodeValue::Expr(Expr::Variable(_, _, _, true, _)) => Receiver::Reference,
+                        _ => Receiver::Value,
                     },
-                        /*
-                    Expr::Operator(ref op) => {
-                        match *op {
-                            Operator::Add(ref left, ref right) => {
-                                // まずleftを計算し、借用期間を終了させる
-                                let left_ir_value = self.decode(builder, context, module, &*left)?;
-                                let left_value = left_ir_value.as_basic_value();
-
-                                // rightの計算を別のスコープで行う
-                                let right_ir_value = {
-                                    self.decode(builder, context, module, &*right)?
-                                };
-                                let right_value = right_ir_value.as_basic_value();
-                                
-                                // 加算処理にmap_errを使用
-                                let sum = builder.build_int_add(left_value.into_int_value(), right_value.into_int_value(), "tmpadd")
-                                    .map_err(|e| e.to_string())?;
-                                
-                                Ok(IRValue::BasicValue(sum.as_basic_value_enum()))
-                            },
-                            _ => todo!(),
-                        }
-                    },*/
-                    _ => todo!(),
+                    _ => Receiver::None,
+                };
+                self.methods.insert(symbol, receiver);
+            }
+            Ok(())
+        });
+        self.current_impl = saved_impl;
+        result
+    }
+
+    // implブロック: 各メソッドを`型名::メソッド名`の関数として生成する
+    fn decode_impl(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        type_name: &str,
+        members: &[Box<Node>],
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        // トップレベル以外のimplはここで宣言する
+        let is_declared = members.iter().all(|member| match member.value {
+            NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                ref name,
+                ..
+            ))) => self
+                .methods
+                .contains_key(&Decoder::method_symbol(type_name, name)),
+            _ => true,
+        });
+        if !is_declared {
+            self.declare_methods(context, module, node, type_name, members)?;
+        }
+
+        let saved_impl = self.current_impl.replace(type_name.to_string());
+        let result = members.iter().try_for_each(|member| match member.value {
+            NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                ref name,
+                ref args,
+                ref body,
+                ref return_type,
+                is_system,
+                is_public,
+            ))) => {
+                let symbol = Decoder::method_symbol(type_name, name);
+                self.decode_function(
+                    builder,
+                    context,
+                    module,
+                    member,
+                    &symbol,
+                    args,
+                    body,
+                    return_type,
+                    is_system,
+                    is_public,
+                )
+                .map(|_| ())
+            }
+            NodeValue::SingleComment(..)
+            | NodeValue::MultiComment(..)
+            | NodeValue::Expr(Expr::EndStatement) => Ok(()),
+            _ => Err(compile_error!(
+                "error",
+                member.line,
+                member.column,
+                member.span,
+                &self.source_path(member),
+                &self.source_content(member),
+                "Only functions can be defined inside `impl {}`",
+                type_name
+            )),
+        });
+        self.current_impl = saved_impl;
+        result?;
+        Ok(IRValue::None)
+    }
+
+    // 構造体定義から名前付きの構造体型を作る
+    fn decode_struct(
+        &mut self,
+        context: &'ctx Context,
+        name: &str,
+        members: &[Box<Node>],
+    ) -> R<(), Diagnostic> {
+        let mut fields = Vec::new();
+        let mut field_types = Vec::new();
+        for member in members {
+            let (field_type, field_name) = match member.value {
+                NodeValue::Expr(Expr::Variable(ref field_type, ref field_name, _, _, _)) => {
+                    (field_type, field_name)
+                }
+                _ => {
+                    return Err(compile_error!(
+                        "error",
+                        member.line,
+                        member.column,
+                        member.span,
+                        &self.source_path(member),
+                        &self.source_content(member),
+                        "Expected a field declaration in struct `{}`",
+                        name
+                    ))
+                }
+            };
+            if fields.contains(field_name) {
+                return Err(compile_error!(
+                    "error",
+                    member.line,
+                    member.column,
+                    member.span,
+                    &self.source_path(member),
+                    &self.source_content(member),
+                    "Field `{}` is already declared in struct `{}`",
+                    field_name,
+                    name
+                ));
+            }
+            match self.resolve_type(context, field_type)? {
+                Some(ty) => field_types.push(ty),
+                None => {
+                    return Err(compile_error!(
+                        "error",
+                        member.line,
+                        member.column,
+                        member.span,
+                        &self.source_path(member),
+                        &self.source_content(member),
+                        "Field `{}` of struct `{}` needs a non-unit type",
+                        field_name,
+                        name
+                    ))
                 }
+            }
+            fields.push(field_name.clone());
+        }
+        let ty = context.opaque_struct_type(name);
+        ty.set_body(&field_types, false);
+        self.structs
+            .insert(name.to_string(), StructInfo { ty, fields });
+        Ok(())
+    }
+
+    // 構造体リテラル: 一時領域の各フィールドを初期化し、構造体の値として読み出す
+    fn decode_struct_instance(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        name: &str,
+        fields: &[(String, Box<Node>)],
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let info = match self.structs.get(name) {
+            Some(info) => info.clone(),
+            None => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Unknown struct `{}`",
+                    name
+                ))
+            }
+        };
+        let pointer = self.build_entry_alloca(
+            builder,
+            context,
+            node,
+            info.ty.as_basic_type_enum(),
+            &format!("{}.tmp", name),
+        )?;
+        let mut initialized: Vec<&str> = Vec::new();
+        for (field_name, value) in fields {
+            let index = self.field_index(value, &info, name, field_name)?;
+            if initialized.contains(&field_name.as_str()) {
+                return Err(compile_error!(
+                    "error",
+                    value.line,
+                    value.column,
+                    value.span,
+                    &self.source_path(value),
+                    &self.source_content(value),
+                    "Field `{}` is initialized more than once",
+                    field_name
+                ));
+            }
+            initialized.push(field_name);
+            let field_type = info.ty.get_field_type_at_index(index).unwrap();
+            let value_ir = self.decode(builder, context, module, value)?;
+            let field_value = self.expect_value(value, value_ir)?;
+            let field_value = self.coerce_value(builder, value, field_value, field_type)?;
+            let field_pointer = builder
+                .build_struct_gep(info.ty, pointer, index, field_name)
+                .map_err(|e| e.to_string())?;
+            builder
+                .build_store(field_pointer, field_value)
+                .map_err(|e| e.to_string())?;
+        }
+        let missing: Vec<String> = info
+            .fields
+            .iter()
+            .filter(|field| !initialized.contains(&field.as_str()))
+            .map(|field| format!("`{}`", field))
+            .collect();
+        if !missing.is_empty() {
+            return Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Missing field{} {} in initializer of `{}`",
+                if missing.len() == 1 { "" } else { "s" },
+                missing.join(", "),
+                name
+            ));
+        }
+        let value = builder
+            .build_load(info.ty, pointer, name)
+            .map_err(|e| e.to_string())?;
+        Ok(IRValue::BasicValue(value))
+    }
+
+    // フィールド読み出し: 変数(またはそのフィールド)はGEPで直接読み、それ以外は値から取り出す
+    fn decode_member_access(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        base: &Node,
+        member: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        if let NodeValue::Expr(Expr::Call(ref method, ref args, _)) = member.value {
+            return self.decode_method_call(builder, context, module, node, base, method, args);
+        }
+        if Decoder::is_place(base) {
+            let place = self.decode_place(builder, context, module, node)?;
+            let value = builder
+                .build_load(place.ty, place.pointer, "fieldtmp")
+                .map_err(|e| e.to_string())?;
+            return Ok(IRValue::BasicValue(value));
+        }
+        let base_ir = self.decode(builder, context, module, base)?;
+        let struct_value = match self.expect_value(base, base_ir)? {
+            BasicValueEnum::StructValue(struct_value) => struct_value,
+            value => {
+                return Err(compile_error!(
+                    "error",
+                    base.line,
+                    base.column,
+                    base.span,
+                    &self.source_path(base),
+                    &self.source_content(base),
+                    "Cannot access a field of a non-struct value of type {:?}",
+                    value.get_type()
+                ))
+            }
+        };
+        let (info, struct_name) = self.struct_info(base, struct_value.get_type())?;
+        let field_name = self.variable_name(member)?;
+        let index = self.field_index(member, &info, &struct_name, &field_name)?;
+        builder
+            .build_extract_value(struct_value, index, &field_name)
+            .map(IRValue::BasicValue)
+            .map_err(|e| e.to_string().into())
+    }
+
+    // メソッド呼び出し `value.method(args)`: レシーバの型のimplから関数を探して呼び出す
+    fn decode_method_call(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        base: &Node,
+        method: &str,
+        args: &[Node],
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        // 変数(やそのフィールド)は格納場所、それ以外は一時的な値をレシーバにする
+        let (place, value) = if Decoder::is_place(base) {
+            let place = self.decode_place(builder, context, module, base)?;
+            (Some(self.deref_pointer_place(builder, place)?), None)
+        } else {
+            let value_ir = self.decode(builder, context, module, base)?;
+            (None, Some(self.expect_value(base, value_ir)?))
+        };
+        let ty = match (place, value) {
+            (Some(place), _) => place.ty,
+            (None, Some(value)) => value.get_type(),
+            (None, None) => unreachable!(),
+        };
+        let struct_type = match ty {
+            BasicTypeEnum::StructType(struct_type) => struct_type,
+            ty => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "No method named `{}` found for type {:?}",
+                    method,
+                    ty
+                ))
+            }
+        };
+        let (_, type_name) = self.struct_info(base, struct_type)?;
+        let symbol = Decoder::method_symbol(&type_name, method);
+        let (function, receiver) = match (module.get_function(&symbol), self.methods.get(&symbol)) {
+            (Some(function), Some(receiver)) => (function, *receiver),
+            _ => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "No method named `{}` found for struct `{}`",
+                    method,
+                    type_name
+                ))
+            }
+        };
+
+        let receiver_value = match receiver {
+            Receiver::None => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "`{}` is an associated function, not a method",
+                    symbol
+                ))
+            }
+            Receiver::Value => match (place, value) {
+                (Some(place), _) => builder
+                    .build_load(place.ty, place.pointer, "selftmp")
+                    .map_err(|e| e.to_string())?,
+                (None, Some(value)) => value,
+                (None, None) => unreachable!(),
+            },
+            Receiver::Reference | Receiver::MutableReference => match (place, value) {
+                (Some(place), _) => {
+                    if receiver == Receiver::MutableReference && !place.is_mutable {
+                        let name = self.root_variable_name(base)?;
+                        return Err(compile_error_with_children!(
+                            "error",
+                            &self.source_path(node),
+                            &self.source_content(node),
+                            node.line,
+                            node.column,
+                            node.span,
+                            &format!(
+                                "Cannot borrow `{}` as mutable, as it is not declared as mutable",
+                                name
+                            ),
+                            "note",
+                            &format!("`{}` takes `&mut self`", symbol)
+                        ));
+                    }
+                    place.pointer.as_basic_value_enum()
+                }
+                // 一時的な値は領域に退避してそのアドレスを渡す
+                (None, Some(value)) => {
+                    let pointer =
+                        self.build_entry_alloca(builder, context, node, ty, "self.tmp")?;
+                    builder
+                        .build_store(pointer, value)
+                        .map_err(|e| e.to_string())?;
+                    pointer.as_basic_value_enum()
+                }
+                (None, None) => unreachable!(),
             },
-            _ => todo!(),
+        };
+        self.build_checked_call(
+            builder,
+            context,
+            module,
+            node,
+            &symbol,
+            function,
+            Some(receiver_value),
+            args,
+        )
+    }
+
+    fn is_place(node: &Node) -> bool {
+        match node.value {
+            NodeValue::Expr(Expr::Variable(_, _, _, false, _))
+            | NodeValue::Expr(Expr::MemberAccess(..)) => true,
+            NodeValue::Expr(Expr::Index(ref base, _)) => Decoder::is_place(base),
+            _ => false,
+        }
+    }
+
+    // 変数, フィールドアクセス, 添字アクセスの格納場所を求める
+    // 構造体や配列へのポインタを持つ変数は自動的に参照先を指す
+    fn decode_place(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+    ) -> R<Variable<'ctx>, Diagnostic> {
+        match node.value {
+            NodeValue::Expr(Expr::Variable(_, ref name, _, false, _)) => {
+                self.lookup_variable(node, name)
+            }
+            NodeValue::Expr(Expr::Index(ref base, ref index)) => {
+                let place = self.decode_place(builder, context, module, base)?;
+                let place = self.deref_pointer_place(builder, place)?;
+                self.element_place(builder, context, module, node, place, index)
+            }
+            NodeValue::Expr(Expr::MemberAccess(ref base, ref member)) => {
+                let place = self.decode_place(builder, context, module, base)?;
+                let place = self.deref_pointer_place(builder, place)?;
+                let struct_type = match place.ty {
+                    BasicTypeEnum::StructType(struct_type) => struct_type,
+                    ty => {
+                        return Err(compile_error!(
+                            "error",
+                            base.line,
+                            base.column,
+                            base.span,
+                            &self.source_path(base),
+                            &self.source_content(base),
+                            "Cannot access a field of a non-struct value of type {:?}",
+                            ty
+                        ))
+                    }
+                };
+                let (info, struct_name) = self.struct_info(base, struct_type)?;
+                let field_name = self.variable_name(member)?;
+                let index = self.field_index(member, &info, &struct_name, &field_name)?;
+                let pointer = builder
+                    .build_struct_gep(info.ty, place.pointer, index, &field_name)
+                    .map_err(|e| e.to_string())?;
+                Ok(Variable {
+                    pointer,
+                    ty: info.ty.get_field_type_at_index(index).unwrap(),
+                    is_mutable: place.is_mutable,
+                    pointee_mutable: false,
+                })
+            }
+            _ => Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Expression is not assignable"
+            )),
+        }
+    }
+
+    // 構造体や配列へのポインタ(&self など)なら参照先を指す場所にする
+    // 参照先を変更できるのは`&mut`で得たポインタの場合のみ
+    fn deref_pointer_place(
+        &self,
+        builder: &Builder<'ctx>,
+        place: Variable<'ctx>,
+    ) -> R<Variable<'ctx>, Diagnostic> {
+        if let BasicTypeEnum::PointerType(pointer_type) = place.ty {
+            let pointee_type = match pointer_type.get_element_type() {
+                AnyTypeEnum::StructType(struct_type) => struct_type.as_basic_type_enum(),
+                AnyTypeEnum::ArrayType(array_type) => array_type.as_basic_type_enum(),
+                _ => return Ok(place),
+            };
+            let pointer = builder
+                .build_load(place.ty, place.pointer, "dereftmp")
+                .map_err(|e| e.to_string())?
+                .into_pointer_value();
+            return Ok(Variable {
+                pointer,
+                ty: pointee_type,
+                is_mutable: place.pointee_mutable,
+                pointee_mutable: false,
+            });
+        }
+        Ok(place)
+    }
+
+    // 配列リテラル: 全要素を最初の要素の型に揃えてLLVMの配列値を組み立てる
+    fn decode_array_literal(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        values: &[Box<Node>],
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let mut elements = Vec::new();
+        for value in values {
+            let value_ir = self.decode(builder, context, module, value)?;
+            elements.push(self.expect_value(value, value_ir)?);
+        }
+        let element_type = match elements.first() {
+            Some(element) => element.get_type(),
+            None => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Cannot infer the element type of an empty array literal"
+                ))
+            }
+        };
+        let mut array = element_type.array_type(elements.len() as u32).get_undef();
+        for (i, (element, value)) in elements.into_iter().zip(values.iter()).enumerate() {
+            let element = self.coerce_value(builder, value, element, element_type)?;
+            array = builder
+                .build_insert_value(array, element, i as u32, "arraytmp")
+                .map_err(|e| e.to_string())?
+                .into_array_value();
+        }
+        Ok(IRValue::BasicValue(array.as_basic_value_enum()))
+    }
+
+    // 添字アクセスの読み出し(一時的な配列は領域に退避してから要素を読む)
+    fn decode_index(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        base: &Node,
+        index: &Node,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let place = if Decoder::is_place(base) {
+            let place = self.decode_place(builder, context, module, base)?;
+            self.deref_pointer_place(builder, place)?
+        } else {
+            let value_ir = self.decode(builder, context, module, base)?;
+            let value = self.expect_value(base, value_ir)?;
+            let pointer =
+                self.build_entry_alloca(builder, context, node, value.get_type(), "array.tmp")?;
+            builder
+                .build_store(pointer, value)
+                .map_err(|e| e.to_string())?;
+            Variable {
+                pointer,
+                ty: value.get_type(),
+                is_mutable: false,
+                pointee_mutable: false,
+            }
+        };
+        let element = self.element_place(builder, context, module, node, place, index)?;
+        builder
+            .build_load(element.ty, element.pointer, "elemtmp")
+            .map(IRValue::BasicValue)
+            .map_err(|e| e.to_string().into())
+    }
+
+    // 配列の格納場所から要素の格納場所をGEPで求める
+    fn element_place(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        place: Variable<'ctx>,
+        index: &Node,
+    ) -> R<Variable<'ctx>, Diagnostic> {
+        let array_type = match place.ty {
+            BasicTypeEnum::ArrayType(array_type) => array_type,
+            ty => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Cannot index into a value of type {:?}",
+                    ty
+                ))
+            }
+        };
+        let index_ir = self.decode(builder, context, module, index)?;
+        let index_value = match self.expect_value(index, index_ir)? {
+            BasicValueEnum::IntValue(int_value) => builder
+                .build_int_cast_sign_flag(int_value, context.i64_type(), true, "idxtmp")
+                .map_err(|e| e.to_string())?,
+            value => {
+                return Err(compile_error!(
+                    "error",
+                    index.line,
+                    index.column,
+                    index.span,
+                    &self.source_path(index),
+                    &self.source_content(index),
+                    "Array index must be an integer, found {:?}",
+                    value.get_type()
+                ))
+            }
+        };
+        self.build_bounds_check(builder, context, module, index, index_value, array_type.len())?;
+        let zero = context.i64_type().const_zero();
+        let pointer = unsafe {
+            builder.build_in_bounds_gep(array_type, place.pointer, &[zero, index_value], "elemptr")
+        }
+        .map_err(|e| e.to_string())?;
+        Ok(Variable {
+            pointer,
+            ty: array_type.get_element_type(),
+            is_mutable: place.is_mutable,
+            pointee_mutable: false,
+        })
+    }
+
+    // 添字の範囲検査
+    // 定数の添字はコンパイル時に検査し、それ以外は範囲外なら位置を表示してトラップする
+    fn build_bounds_check(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        index: IntValue<'ctx>,
+        length: u32,
+    ) -> R<(), Diagnostic> {
+        if let Some(constant) = index.get_sign_extended_constant() {
+            if constant < 0 || constant >= length as i64 {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Index out of bounds: the length is {} but the index is {}",
+                    length,
+                    constant
+                ));
+            }
+            return Ok(());
+        }
+        if !self.bounds_check {
+            return Ok(());
+        }
+        let function = self.current_function(builder, node)?;
+        // 負の添字は符号なし比較で大きな値になるので一度の比較で済む
+        let is_in_bounds = builder
+            .build_int_compare(
+                IntPredicate::ULT,
+                index,
+                context.i64_type().const_int(length as u64, false),
+                "inbounds",
+            )
+            .map_err(|e| e.to_string())?;
+        let ok_block = context.append_basic_block(function, "bounds.ok");
+        let fail_block = context.append_basic_block(function, "bounds.fail");
+        builder
+            .build_conditional_branch(is_in_bounds, ok_block, fail_block)
+            .map_err(|e| e.to_string())?;
+
+        builder.position_at_end(fail_block);
+        self.build_runtime_error(
+            builder,
+            context,
+            module,
+            node,
+            &format!(
+                "index out of bounds: the length is {} but the index is %lld",
+                length
+            ),
+            &[index.into()],
+        )?;
+
+        builder.position_at_end(ok_block);
+        Ok(())
+    }
+
+    // 実行時エラー: `パス:行:列: メッセージ`をstderrに書いてtrapで終了する
+    // messageはprintfの書式で、argsがその引数になる
+    fn build_runtime_error(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        message: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> R<(), Diagnostic> {
+        // trapで終了するとバッファに残った出力が失われるので、先に全てflushしてから
+        // バッファを介さずにstderr(fd 2)へ書く(パスの`%`が書式と解釈されないよう`%s`で渡す)
+        let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+        let i32_type = context.i32_type();
+        let fflush = Decoder::declare_runtime_function(
+            module,
+            "fflush",
+            i32_type.fn_type(&[i8_ptr_type.into()], false),
+        );
+        builder
+            .build_call(fflush, &[i8_ptr_type.const_null().into()], "")
+            .map_err(|e| e.to_string())?;
+        let format = builder
+            .build_global_string_ptr(
+                &format!("%s:{}:{}: {}\n", node.line, node.column, message),
+                "error.fmt",
+            )
+            .map_err(|e| e.to_string())?;
+        let path = builder
+            .build_global_string_ptr(&self.source_path(node), "error.path")
+            .map_err(|e| e.to_string())?;
+        let dprintf = Decoder::declare_runtime_function(
+            module,
+            "dprintf",
+            i32_type.fn_type(&[i32_type.into(), i8_ptr_type.into()], true),
+        );
+        let mut dprintf_args: Vec<BasicMetadataValueEnum<'ctx>> = vec![
+            i32_type.const_int(2, false).into(),
+            format.as_pointer_value().into(),
+            path.as_pointer_value().into(),
+        ];
+        dprintf_args.extend_from_slice(args);
+        builder
+            .build_call(dprintf, &dprintf_args, "")
+            .map_err(|e| e.to_string())?;
+        let trap = Decoder::declare_runtime_function(
+            module,
+            "llvm.trap",
+            context.void_type().fn_type(&[], false),
+        );
+        builder.build_call(trap, &[], "").map_err(|e| e.to_string())?;
+        builder.build_unreachable().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // 生成するコードが使うランタイムの関数を宣言する(宣言済みならそれを返す)
+    fn declare_runtime_function(
+        module: &Module<'ctx>,
+        name: &str,
+        fn_type: FunctionType<'ctx>,
+    ) -> FunctionValue<'ctx> {
+        match module.get_function(name) {
+            Some(function) => function,
+            None => module.add_function(name, fn_type, Some(Linkage::External)),
+        }
+    }
+
+    // `&mut x` または `&mut self` の形か
+    fn is_mutable_reference(node: &Node) -> bool {
+        matches!(
+            node.value,
+            NodeValue::Expr(Expr::Variable(_, _, true, true, _))
+        )
+    }
+
+    // `a.b[i].c`の根元の変数名
+    fn root_variable_name(&self, node: &Node) -> R<String, Diagnostic> {
+        match node.value {
+            NodeValue::Expr(Expr::MemberAccess(ref base, _))
+            | NodeValue::Expr(Expr::Index(ref base, _)) => self.root_variable_name(base),
+            _ => self.variable_name(node),
+        }
+    }
+
+    fn struct_info(
+        &self,
+        node: &Node,
+        ty: StructType<'ctx>,
+    ) -> R<(StructInfo<'ctx>, String), Diagnostic> {
+        let name = ty
+            .get_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match self.structs.get(&name) {
+            Some(info) if info.ty == ty => Ok((info.clone(), name)),
+            _ => Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Cannot access a field of a value of type {:?}",
+                ty
+            )),
+        }
+    }
+
+    fn field_index(
+        &self,
+        node: &Node,
+        info: &StructInfo<'ctx>,
+        struct_name: &str,
+        field_name: &str,
+    ) -> R<u32, Diagnostic> {
+        match info.fields.iter().position(|field| field == field_name) {
+            Some(index) => Ok(index as u32),
+            None => Err(compile_error_with_children!(
+                "error",
+                &self.source_path(node),
+                &self.source_content(node),
+                node.line,
+                node.column,
+                node.span,
+                &format!("Struct `{}` has no field named `{}`", struct_name, field_name),
+                "note",
+                &format!("available fields are: {}", info.fields.join(", "))
+            )),
+        }
+    }
+
+    // 文字列の実行時表現: { i8* データ, i64 長さ }
+    // データはNUL終端されているのでCの関数にもそのまま渡せる(長さにNULは含まない)
+    fn string_type(context: &'ctx Context) -> StructType<'ctx> {
+        let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+        context.struct_type(
+            &[
+                i8_ptr_type.as_basic_type_enum(),
+                context.i64_type().as_basic_type_enum(),
+            ],
+            false,
+        )
+    }
+
+    fn is_string_type(ty: StructType<'ctx>) -> bool {
+        ty.count_fields() == 2
+            && matches!(
+                ty.get_field_type_at_index(0),
+                Some(BasicTypeEnum::PointerType(_))
+            )
+            && matches!(
+                ty.get_field_type_at_index(1),
+                Some(BasicTypeEnum::IntType(int_type)) if int_type.get_bit_width() == 64
+            )
+    }
+
+    // 文字列リテラルをグローバル定数のバイト配列として出力し、{ポインタ, 長さ}の値を返す
+    fn decode_string(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        value: &str,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let global = match self.strings.get(value) {
+            Some(global) => *global,
+            None => {
+                let global = builder
+                    .build_global_string_ptr(value, ".str")
+                    .map_err(|e| e.to_string())?;
+                self.strings.insert(value.to_string(), global);
+                global
+            }
+        };
+        let length = context.i64_type().const_int(value.len() as u64, false);
+        let string = Decoder::string_type(context).const_named_struct(&[
+            global.as_pointer_value().as_basic_value_enum(),
+            length.as_basic_value_enum(),
+        ]);
+        Ok(IRValue::BasicValue(string.as_basic_value_enum()))
+    }
+
+    // 関数呼び出し
+    // 通常の呼び出しはモジュール内の関数、`@`付きの呼び出しはシステム関数の一覧から解決する
+    fn decode_call(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        name: &str,
+        args: &[Node],
+        is_system: bool,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let function = if is_system {
+            self.declare_system_function(context, module, node, name)?
+        } else {
+            self.lookup_function(context, module, node, name)?
+        };
+        let display_name = if is_system {
+            format!("@{}", name)
+        } else {
+            name.to_string()
+        };
+        self.build_checked_call(
+            builder,
+            context,
+            module,
+            node,
+            &display_name,
+            function,
+            None,
+            args,
+        )
+    }
+
+    // 引数の数と型を検査して呼び出しを生成する(レシーバは検査済みの第1引数として渡す)
+    fn build_checked_call(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        name: &str,
+        function: FunctionValue<'ctx>,
+        receiver: Option<BasicValueEnum<'ctx>>,
+        args: &[Node],
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let mut param_types = function.get_type().get_param_types();
+        if receiver.is_some() {
+            param_types.remove(0);
+        }
+        let is_var_arg = function.get_type().is_var_arg();
+        if args.len() < param_types.len() || (!is_var_arg && args.len() > param_types.len()) {
+            return Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Function `{}` takes {}{} argument{} but {} {} supplied",
+                name,
+                if is_var_arg { "at least " } else { "" },
+                param_types.len(),
+                if param_types.len() == 1 { "" } else { "s" },
+                args.len(),
+                if args.len() == 1 { "was" } else { "were" }
+            ));
+        }
+
+        let mut arg_values: Vec<BasicMetadataValueEnum<'ctx>> = Vec::new();
+        if let Some(receiver) = receiver {
+            arg_values.push(receiver.into());
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let value_ir = self.decode(builder, context, module, arg)?;
+            let value = self.expect_value(arg, value_ir)?;
+            let value = match param_types.get(i) {
+                Some(ty) => self.coerce_argument(builder, arg, name, i, value, *ty)?,
+                None => self.promote_variadic_arg(builder, context, arg, value)?,
+            };
+            arg_values.push(value.into());
+        }
+        let call = builder
+            .build_call(function, &arg_values, "calltmp")
+            .map_err(|e| e.to_string())?;
+        Ok(match call.try_as_basic_value().left() {
+            Some(value) => IRValue::BasicValue(value),
+            None => IRValue::None,
+        })
+    }
+
+    fn lookup_function(
+        &self,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        name: &str,
+    ) -> R<FunctionValue<'ctx>, Diagnostic> {
+        if let Some(function) = module.get_function(name) {
+            return Ok(function);
+        }
+        // システム関数を`@`なしで呼んだ場合は書き方を案内する
+        if Decoder::system_function(context, name).is_some() {
+            return Err(compile_error_with_children!(
+                "error",
+                &self.source_path(node),
+                &self.source_content(node),
+                node.line,
+                node.column,
+                node.span,
+                &format!("Unknown function `{}`", name),
+                "help",
+                &format!("system functions are called with `@`: `@{}(...)`", name)
+            ));
+        }
+        Err(compile_error!(
+            "error",
+            node.line,
+            node.column,
+            node.span,
+            &self.source_path(node),
+            &self.source_content(node),
+            "Unknown function `{}`",
+            name
+        ))
+    }
+
+    // 引数を仮引数の型に合わせる(変換できない場合は何番目の引数かを示す)
+    fn coerce_argument(
+        &self,
+        builder: &Builder<'ctx>,
+        arg: &Node,
+        name: &str,
+        index: usize,
+        value: BasicValueEnum<'ctx>,
+        ty: BasicTypeEnum<'ctx>,
+    ) -> R<BasicValueEnum<'ctx>, Diagnostic> {
+        let is_compatible = match (value, ty) {
+            (BasicValueEnum::IntValue(_), BasicTypeEnum::IntType(_))
+            | (BasicValueEnum::IntValue(_), BasicTypeEnum::FloatType(_))
+            | (BasicValueEnum::FloatValue(_), BasicTypeEnum::FloatType(_)) => true,
+            (BasicValueEnum::StructValue(struct_value), BasicTypeEnum::PointerType(_)) => {
+                Decoder::is_string_type(struct_value.get_type())
+            }
+            _ => value.get_type() == ty,
+        };
+        if !is_compatible {
+            return Err(compile_error!(
+                "error",
+                arg.line,
+                arg.column,
+                arg.span,
+                &self.source_path(arg),
+                &self.source_content(arg),
+                "Argument {} of `{}` has the wrong type: expected {:?}, found {:?}",
+                index + 1,
+                name,
+                ty,
+                value.get_type()
+            ));
+        }
+        self.coerce_value(builder, arg, value, ty)
+    }
+
+    // 可変長引数はCの既定の昇格規則に従う(i32未満の整数はi32, f32はf64, 文字列はポインタ)
+    fn promote_variadic_arg(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        node: &Node,
+        value: BasicValueEnum<'ctx>,
+    ) -> R<BasicValueEnum<'ctx>, Diagnostic> {
+        match value {
+            BasicValueEnum::IntValue(int_value) if int_value.get_type().get_bit_width() < 32 => {
+                self.coerce_value(builder, node, value, context.i32_type().as_basic_type_enum())
+            }
+            BasicValueEnum::FloatValue(float_value)
+                if float_value.get_type() == context.f32_type() =>
+            {
+                self.coerce_value(builder, node, value, context.f64_type().as_basic_type_enum())
+            }
+            BasicValueEnum::StructValue(struct_value)
+                if Decoder::is_string_type(struct_value.get_type()) =>
+            {
+                let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+                self.coerce_value(builder, node, value, i8_ptr_type.as_basic_type_enum())
+            }
+            _ => Ok(value),
+        }
+    }
+
+    // システム関数の一覧: `@名前` -> (Cのシンボル, シグネチャ)
+    fn system_function(
+        context: &'ctx Context,
+        name: &str,
+    ) -> Option<(&'static str, FunctionType<'ctx>)> {
+        let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+        let i32_type = context.i32_type();
+        let void_type = context.void_type();
+        Some(match name {
+            "print" | "printf" => ("printf", i32_type.fn_type(&[i8_ptr_type.into()], true)),
+            "println" | "puts" => ("puts", i32_type.fn_type(&[i8_ptr_type.into()], false)),
+            "exit" => ("exit", void_type.fn_type(&[i32_type.into()], false)),
+            "malloc" | "alloc" => (
+                "malloc",
+                i8_ptr_type.fn_type(&[context.i64_type().into()], false),
+            ),
+            "free" => ("free", void_type.fn_type(&[i8_ptr_type.into()], false)),
+            _ => return None,
+        })
+    }
+
+    // システム関数を外部のCシンボルとして宣言する(宣言済みならそれを返す)
+    fn declare_system_function(
+        &self,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        name: &str,
+    ) -> R<FunctionValue<'ctx>, Diagnostic> {
+        let (symbol, fn_type) = match Decoder::system_function(context, name) {
+            Some((symbol, fn_type)) => (symbol.to_string(), fn_type),
+            None if self.host_functions.contains_key(name) => (
+                Decoder::host_symbol(name),
+                self.host_function_type(context, name),
+            ),
+            // スクリプト側で定義されたシステム関数
+            None if module.get_function(name).is_some() => {
+                return Ok(module.get_function(name).unwrap())
+            }
+            None => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Unknown system function `@{}`",
+                    name
+                ))
+            }
+        };
+        Ok(match module.get_function(&symbol) {
+            Some(function) => function,
+            None => module.add_function(&symbol, fn_type, Some(Linkage::External)),
+        })
+    }
+
+    fn host_function_type(&self, context: &'ctx Context, name: &str) -> FunctionType<'ctx> {
+        let (params, return_type) = &self.host_functions[name];
+        let to_type = |ty: HostType| -> Option<BasicTypeEnum<'ctx>> {
+            match ty {
+                HostType::I32 => Some(context.i32_type().into()),
+                HostType::I64 => Some(context.i64_type().into()),
+                HostType::F64 => Some(context.f64_type().into()),
+                HostType::Str => Some(context.i8_type().ptr_type(AddressSpace::default()).into()),
+                HostType::Unit => None,
+            }
+        };
+        let params: Vec<BasicMetadataTypeEnum<'ctx>> = params
+            .iter()
+            .filter_map(|&ty| to_type(ty))
+            .map(|ty| ty.into())
+            .collect();
+        match to_type(*return_type) {
+            Some(ty) => ty.fn_type(&params, false),
+            None => context.void_type().fn_type(&params, false),
+        }
+    }
+
+    // 型ノードをLLVMの型に解決する(Unit型はNoneを返す)
+    fn resolve_type(
+        &self,
+        context: &'ctx Context,
+        node: &Node,
+    ) -> R<Option<BasicTypeEnum<'ctx>>, Diagnostic> {
+        let (name, is_reference) = match node.value {
+            NodeValue::Expr(Expr::DataType(DataType::String(ref name))) => (name.as_str(), false),
+            NodeValue::Expr(Expr::Variable(_, ref name, _, is_reference, _)) => {
+                (name.as_str(), is_reference)
+            }
+            NodeValue::Expr(Expr::DataType(DataType::Null))
+            | NodeValue::Expr(Expr::DataType(DataType::Unit(_))) => return Ok(None),
+            NodeValue::Expr(Expr::DataType(DataType::FixedArray(ref element, length))) => {
+                return match self.resolve_type(context, element)? {
+                    Some(ty) => Ok(Some(ty.array_type(length as u32).as_basic_type_enum())),
+                    None => Err(compile_error!(
+                        "error",
+                        node.line,
+                        node.column,
+                        node.span,
+                        &self.source_path(node),
+                        &self.source_content(node),
+                        "Array elements need a non-unit type"
+                    )),
+                };
+            }
+            _ => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Unsupported type: {:?}",
+                    node.value
+                ))
+            }
+        };
+        // implの中では`Self`がその型を指す
+        let name = match (name, &self.current_impl) {
+            ("Self", Some(type_name)) => type_name.as_str(),
+            ("Self", None) => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "`Self` is only available inside an `impl` block"
+                ))
+            }
+            _ => name,
+        };
+        let ty = match name {
+            "()" => return Ok(None),
+            "i8" | "u8" => context.i8_type().as_basic_type_enum(),
+            "i16" | "u16" => context.i16_type().as_basic_type_enum(),
+            "i32" | "u32" => context.i32_type().as_basic_type_enum(),
+            "i64" | "u64" | "int" => context.i64_type().as_basic_type_enum(),
+            "f32" => context.f32_type().as_basic_type_enum(),
+            "f64" | "float" => context.f64_type().as_basic_type_enum(),
+            "bool" => context.bool_type().as_basic_type_enum(),
+            "str" | "string" => Decoder::string_type(context).as_basic_type_enum(),
+            _ if self.structs.contains_key(name) => self.structs[name].ty.as_basic_type_enum(),
+            _ => {
+                return Err(compile_error!(
+                    "error",
+                    node.line,
+                    node.column,
+                    node.span,
+                    &self.source_path(node),
+                    &self.source_content(node),
+                    "Unknown type: {}",
+                    name
+                ))
+            }
+        };
+        if is_reference {
+            return Ok(Some(ty.ptr_type(AddressSpace::default()).as_basic_type_enum()));
+        }
+        Ok(Some(ty))
+    }
+
+    // 値を指定の型に変換する(整数の拡張・縮小、整数から浮動小数点への変換のみ暗黙に行う)
+    fn coerce_value(
+        &self,
+        builder: &Builder<'ctx>,
+        node: &Node,
+        value: BasicValueEnum<'ctx>,
+        ty: BasicTypeEnum<'ctx>,
+    ) -> R<BasicValueEnum<'ctx>, Diagnostic> {
+        if value.get_type() == ty {
+            return Ok(value);
+        }
+        match (value, ty) {
+            (BasicValueEnum::IntValue(int_value), BasicTypeEnum::IntType(int_type)) => {
+                let is_signed = int_value.get_type().get_bit_width() != 1;
+                let value = builder
+                    .build_int_cast_sign_flag(int_value, int_type, is_signed, "casttmp")
+                    .map_err(|e| e.to_string())?;
+                Ok(value.as_basic_value_enum())
+            }
+            (BasicValueEnum::IntValue(int_value), BasicTypeEnum::FloatType(float_type)) => {
+                let value = builder
+                    .build_signed_int_to_float(int_value, float_type, "itoftmp")
+                    .map_err(|e| e.to_string())?;
+                Ok(value.as_basic_value_enum())
+            }
+            (BasicValueEnum::FloatValue(float_value), BasicTypeEnum::FloatType(float_type)) => {
+                let value = builder
+                    .build_float_cast(float_value, float_type, "fcasttmp")
+                    .map_err(|e| e.to_string())?;
+                Ok(value.as_basic_value_enum())
+            }
+            // 配列は同じ長さなら要素ごとに変換する
+            (BasicValueEnum::ArrayValue(array_value), BasicTypeEnum::ArrayType(array_type))
+                if array_value.get_type().len() == array_type.len() =>
+            {
+                let mut array = array_type.get_undef();
+                for i in 0..array_type.len() {
+                    let element = builder
+                        .build_extract_value(array_value, i, "elemtmp")
+                        .map_err(|e| e.to_string())?;
+                    let element =
+                        self.coerce_value(builder, node, element, array_type.get_element_type())?;
+                    array = builder
+                        .build_insert_value(array, element, i, "arraytmp")
+                        .map_err(|e| e.to_string())?
+                        .into_array_value();
+                }
+                Ok(array.as_basic_value_enum())
+            }
+            // Cの関数に渡す文字列はデータへのポインタだけを取り出す
+            (BasicValueEnum::StructValue(struct_value), BasicTypeEnum::PointerType(_))
+                if Decoder::is_string_type(struct_value.get_type()) =>
+            {
+                builder
+                    .build_extract_value(struct_value, 0, "strptr")
+                    .map_err(|e| e.to_string().into())
+            }
+            _ => Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Mismatched types: expected {:?}, found {:?}",
+                ty,
+                value.get_type()
+            )),
+        }
+    }
+
+    fn decode_operator(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        op: &Operator,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        match *op {
+            Operator::Add(ref left, ref right)
+            | Operator::Sub(ref left, ref right)
+            | Operator::Mul(ref left, ref right)
+            | Operator::Div(ref left, ref right)
+            | Operator::Modulus(ref left, ref right)
+            | Operator::BitAnd(ref left, ref right)
+            | Operator::BitOr(ref left, ref right)
+            | Operator::BitXor(ref left, ref right)
+            | Operator::ShiftLeft(ref left, ref right)
+            | Operator::ShiftRight(ref left, ref right) => {
+                let lhs_ir = self.decode(builder, context, module, left)?;
+                let lhs = self.expect_value(left, lhs_ir)?;
+                let rhs_ir = self.decode(builder, context, module, right)?;
+                let rhs = self.expect_value(right, rhs_ir)?;
+                let value = self.build_arithmetic(builder, context, node, op, lhs, rhs)?;
+                Ok(IRValue::BasicValue(value))
+            }
+            Operator::BitNot(ref operand) => {
+                let value_ir = self.decode(builder, context, module, operand)?;
+                let value = self.expect_value(operand, value_ir)?;
+                match value {
+                    BasicValueEnum::IntValue(int_value) => {
+                        let not = builder
+                            .build_not(int_value, "nottmp")
+                            .map_err(|e| e.to_string())?;
+                        Ok(IRValue::BasicValue(not.as_basic_value_enum()))
+                    }
+                    _ => Err(compile_error!(
+                        "error",
+                        node.line,
+                        node.column,
+                        node.span,
+                        &self.source_path(node),
+                        &self.source_content(node),
+                        "Bitwise not requires an integer operand, found: {:?}",
+                        value.get_type()
+                    )),
+                }
+            }
+            Operator::Eq(ref left, ref right)
+            | Operator::Ne(ref left, ref right)
+            | Operator::Lt(ref left, ref right)
+            | Operator::Gt(ref left, ref right)
+            | Operator::Le(ref left, ref right)
+            | Operator::Ge(ref left, ref right) => {
+                let lhs_ir = self.decode(builder, context, module, left)?;
+                let lhs = self.expect_value(left, lhs_ir)?;
+                let rhs_ir = self.decode(builder, context, module, right)?;
+                let rhs = self.expect_value(right, rhs_ir)?;
+                let value = self.build_comparison(builder, context, node, op, lhs, rhs)?;
+                Ok(IRValue::BasicValue(value.as_basic_value_enum()))
+            }
+            Operator::AddAssign(ref target, ref value)
+            | Operator::SubAssign(ref target, ref value)
+            | Operator::MulAssign(ref target, ref value)
+            | Operator::DivAssign(ref target, ref value)
+            | Operator::ModulusAssign(ref target, ref value)
+            | Operator::BitAndAssign(ref target, ref value)
+            | Operator::BitOrAssign(ref target, ref value)
+            | Operator::BitXorAssign(ref target, ref value)
+            | Operator::ShiftLeftAssign(ref target, ref value)
+            | Operator::ShiftRightAssign(ref target, ref value) => {
+                self.decode_compound_assign(builder, context, module, node, op, target, value)
+            }
+            Operator::Increment(ref target, is_prefix) => {
+                self.decode_increment(builder, context, module, node, target, is_prefix, true)
+            }
+            Operator::Decrement(ref target, is_prefix) => {
+                self.decode_increment(builder, context, module, node, target, is_prefix, false)
+            }
+            Operator::And(ref left, ref right) => {
+                self.build_short_circuit(builder, context, module, node, left, right, true)
+            }
+            Operator::Or(ref left, ref right) => {
+                self.build_short_circuit(builder, context, module, node, left, right, false)
+            }
+            Operator::Range(..) => Err(self.unsupported(node, "A range outside of a `for` loop")),
+        }
+    }
+
+    // 比較演算をicmp/fcmpで生成し、i1を返す
+    // 整数は符号付き(i1同士は符号なし)、浮動小数点数は順序付き比較(!=のみ非順序)で比較する
+    fn build_comparison(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        node: &Node,
+        op: &Operator,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+    ) -> R<IntValue<'ctx>, Diagnostic> {
+        let (lhs, rhs) = self.promote_operands(builder, context, node, lhs, rhs)?;
+        match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                let is_bool = l.get_type().get_bit_width() == 1;
+                let predicate = match (op, is_bool) {
+                    (Operator::Eq(..), _) => IntPredicate::EQ,
+                    (Operator::Ne(..), _) => IntPredicate::NE,
+                    (Operator::Lt(..), false) => IntPredicate::SLT,
+                    (Operator::Gt(..), false) => IntPredicate::SGT,
+                    (Operator::Le(..), false) => IntPredicate::SLE,
+                    (Operator::Ge(..), false) => IntPredicate::SGE,
+                    (Operator::Lt(..), true) => IntPredicate::ULT,
+                    (Operator::Gt(..), true) => IntPredicate::UGT,
+                    (Operator::Le(..), true) => IntPredicate::ULE,
+                    (Operator::Ge(..), true) => IntPredicate::UGE,
+                    _ => unreachable!(),
+                };
+                builder
+                    .build_int_compare(predicate, l, r, "cmptmp")
+                    .map_err(|e| e.to_string().into())
+            }
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
+                let predicate = match op {
+                    Operator::Eq(..) => FloatPredicate::OEQ,
+                    Operator::Ne(..) => FloatPredicate::UNE,
+                    Operator::Lt(..) => FloatPredicate::OLT,
+                    Operator::Gt(..) => FloatPredicate::OGT,
+                    Operator::Le(..) => FloatPredicate::OLE,
+                    Operator::Ge(..) => FloatPredicate::OGE,
+                    _ => unreachable!(),
+                };
+                builder
+                    .build_float_compare(predicate, l, r, "fcmptmp")
+                    .map_err(|e| e.to_string().into())
+            }
+            (lhs, rhs) => Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Cannot compare {:?} with {:?}",
+                lhs.get_type(),
+                rhs.get_type()
+            )),
+        }
+    }
+
+    // &&/||: 左辺で結果が決まる場合は右辺を評価せずに合流ブロックへ分岐する
+    fn build_short_circuit(
+        &mut self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        node: &Node,
+        left: &Node,
+        right: &Node,
+        is_and: bool,
+    ) -> R<IRValue<'ctx>, Diagnostic> {
+        let function = self.current_function(builder, node)?;
+        let prefix = if is_and { "and" } else { "or" };
+        let lhs = self.decode_condition(builder, context, module, left)?;
+        let lhs_block = builder.get_insert_block().unwrap();
+        let rhs_block = context.append_basic_block(function, &format!("{}.rhs", prefix));
+        let merge_block = context.append_basic_block(function, &format!("{}.end", prefix));
+        if is_and {
+            builder.build_conditional_branch(lhs, rhs_block, merge_block)
+        } else {
+            builder.build_conditional_branch(lhs, merge_block, rhs_block)
+        }
+        .map_err(|e| e.to_string())?;
+
+        builder.position_at_end(rhs_block);
+        let rhs = self.decode_condition(builder, context, module, right)?;
+        let rhs_end_block = builder.get_insert_block().unwrap();
+        builder
+            .build_unconditional_branch(merge_block)
+            .map_err(|e| e.to_string())?;
+
+        // 左辺で決まった場合の値は && なら false、|| なら true
+        builder.position_at_end(merge_block);
+        let short_value = context.bool_type().const_int(!is_and as u64, false);
+        let phi = builder
+            .build_phi(context.bool_type(), &format!("{}tmp", prefix))
+            .map_err(|e| e.to_string())?;
+        phi.add_incoming(&[(&short_value, lhs_block), (&rhs, rhs_end_block)]);
+        Ok(IRValue::BasicValue(phi.as_basic_value()))
+    }
+
+    // 算術・ビット演算を生成する
+    // 両辺が整数なら整数命令、どちらかが浮動小数点なら整数側をf64に昇格して浮動小数点命令を使う
+    fn build_arithmetic(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        node: &Node,
+        op: &Operator,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+    ) -> R<BasicValueEnum<'ctx>, Diagnostic> {
+        let (lhs, rhs) = self.promote_operands(builder, context, node, lhs, rhs)?;
+        match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                let value = match *op {
+                    Operator::Add(..) | Operator::AddAssign(..) => {
+                        builder.build_int_add(l, r, "addtmp")
+                    }
+                    Operator::Sub(..) | Operator::SubAssign(..) => {
+                        builder.build_int_sub(l, r, "subtmp")
+                    }
+                    Operator::Mul(..) | Operator::MulAssign(..) => {
+                        builder.build_int_mul(l, r, "multmp")
+                    }
+                    Operator::Div(..) | Operator::DivAssign(..) => {
+                        builder.build_int_signed_div(l, r, "divtmp")
+                    }
+                    Operator::Modulus(..) | Operator::ModulusAssign(..) => {
+                        builder.build_int_signed_rem(l, r, "modtmp")
+                    }
+                    Operator::BitAnd(..) | Operator::BitAndAssign(..) => {
+                        builder.build_and(l, r, "andtmp")
+                    }
+                    Operator::BitOr(..) | Operator::BitOrAssign(..) => {
+                        builder.build_or(l, r, "ortmp")
+                    }
+                    Operator::BitXor(..) | Operator::BitXorAssign(..) => {
+                        builder.build_xor(l, r, "xortmp")
+                    }
+                    Operator::ShiftLeft(..) | Operator::ShiftLeftAssign(..) => {
+                        builder.build_left_shift(l, r, "shltmp")
+                    }
+                    Operator::ShiftRight(..) | Operator::ShiftRightAssign(..) => {
+                        builder.build_right_shift(l, r, true, "shrtmp")
+                    }
+                    _ => unreachable!("not an arithmetic operator: {:?}", op),
+                }
+                .map_err(|e| e.to_string())?;
+                Ok(value.as_basic_value_enum())
+            }
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
+                let value = match *op {
+                    Operator::Add(..) | Operator::AddAssign(..) => {
+                        builder.build_float_add(l, r, "addtmp")
+                    }
+                    Operator::Sub(..) | Operator::SubAssign(..) => {
+                        builder.build_float_sub(l, r, "subtmp")
+                    }
+                    Operator::Mul(..) | Operator::MulAssign(..) => {
+                        builder.build_float_mul(l, r, "multmp")
+                    }
+                    Operator::Div(..) | Operator::DivAssign(..) => {
+                        builder.build_float_div(l, r, "divtmp")
+                    }
+                    Operator::Modulus(..) | Operator::ModulusAssign(..) => {
+                        builder.build_float_rem(l, r, "modtmp")
+                    }
+                    _ => {
+                        return Err(compile_error!(
+                            "error",
+                            node.line,
+                            node.column,
+                            node.span,
+                            &self.source_path(node),
+                            &self.source_content(node),
+                            "Bitwise operators cannot be applied to floating point values"
+                        ))
+                    }
+                }
+                .map_err(|e| e.to_string())?;
+                Ok(value.as_basic_value_enum())
+            }
+            (lhs, rhs) => Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Unsupported operand types for arithmetic: {:?} and {:?}",
+                lhs.get_type(),
+                rhs.get_type()
+            )),
+        }
+    }
+
+    // 演算前に両辺の型を揃える
+    // 整数同士はビット幅の大きい方へ拡張、整数と浮動小数点の組み合わせはf64へ変換する
+    fn promote_operands(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        node: &Node,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+    ) -> R<(BasicValueEnum<'ctx>, BasicValueEnum<'ctx>), Diagnostic> {
+        match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                let l_width = l.get_type().get_bit_width();
+                let r_width = r.get_type().get_bit_width();
+                if l_width == r_width {
+                    Ok((lhs, rhs))
+                } else if l_width < r_width {
+                    let l = self.extend_int(builder, l, r)?;
+                    Ok((l.as_basic_value_enum(), rhs))
+                } else {
+                    let r = self.extend_int(builder, r, l)?;
+                    Ok((lhs, r.as_basic_value_enum()))
+                }
+            }
+            // 整数は相手の浮動小数点数と同じ型に変換する
+            (BasicValueEnum::IntValue(l), BasicValueEnum::FloatValue(r)) => {
+                let l = builder
+                    .build_signed_int_to_float(l, r.get_type(), "itoftmp")
+                    .map_err(|e| e.to_string())?;
+                Ok((l.as_basic_value_enum(), rhs))
+            }
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::IntValue(r)) => {
+                let r = builder
+                    .build_signed_int_to_float(r, l.get_type(), "itoftmp")
+                    .map_err(|e| e.to_string())?;
+                Ok((lhs, r.as_basic_value_enum()))
+            }
+            // f32とf64はf32の方をf64に拡張する
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
+                if l.get_type() == r.get_type() {
+                    Ok((lhs, rhs))
+                } else if l.get_type() == context.f32_type() {
+                    let l = builder
+                        .build_float_ext(l, r.get_type(), "fexttmp")
+                        .map_err(|e| e.to_string())?;
+                    Ok((l.as_basic_value_enum(), rhs))
+                } else {
+                    let r = builder
+                        .build_float_ext(r, l.get_type(), "fexttmp")
+                        .map_err(|e| e.to_string())?;
+                    Ok((lhs, r.as_basic_value_enum()))
+                }
+            }
+            (lhs, rhs) => Err(compile_error!(
+                "error",
+                node.line,
+                node.column,
+                node.span,
+                &self.source_path(node),
+                &self.source_content(node),
+                "Mismatched operand types: {:?} and {:?}",
+                lhs.get_type(),
+                rhs.get_type()
+            )),
+        }
+    }
+
+    // 整数を相手側のビット幅まで拡張する(i1は符号なしとして扱う)
+    fn extend_int(
+        &self,
+        builder: &Builder<'ctx>,
+        value: IntValue<'ctx>,
+        target: IntValue<'ctx>,
+    ) -> R<IntValue<'ctx>, Diagnostic> {
+        if value.get_type().get_bit_width() == 1 {
+            builder
+                .build_int_z_extend(value, target.get_type(), "zexttmp")
+                .map_err(|e| e.to_string().into())
+        } else {
+            builder
+                .build_int_s_extend(value, target.get_type(), "sexttmp")
+                .map_err(|e| e.to_string().into())
         }
     }
 }
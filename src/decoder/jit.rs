@@ -0,0 +1,120 @@
+use crate::decoder::ir::{Decoder, ENTRY_FUNCTION_NAME};
+use crate::types::{HostType, R};
+extern crate inkwell as iw;
+use iw::{
+    execution_engine::ExecutionEngine,
+    module::Module,
+    targets::{InitializationConfig, Target},
+    types::BasicTypeEnum,
+    OptimizationLevel,
+};
+
+// `@名前`で呼べるよう、JITに登録するホスト(Rust)側の関数
+// addressは`extern "C"`で定義した関数のアドレスで、paramsとreturn_typeはそのシグネチャに一致させる
+#[derive(Debug, Clone)]
+pub struct HostFunction {
+    pub name: String,
+    pub params: Vec<HostType>,
+    pub return_type: HostType,
+    pub address: usize,
+}
+
+impl HostFunction {
+    pub fn new(name: &str, params: &[HostType], return_type: HostType, address: usize) -> Self {
+        HostFunction {
+            name: name.to_string(),
+            params: params.to_vec(),
+            return_type,
+            address,
+        }
+    }
+
+    // デコーダにシグネチャを登録する(型検査器と名前解決には別途登録する)
+    pub fn register(&self, decoder: &mut Decoder) {
+        decoder.register_host_function(&self.name, &self.params, self.return_type);
+    }
+}
+
+// Decoderが生成したモジュールをプロセス内でコンパイルして実行する
+// REPLのように後からモジュールを追加すると、先に追加したモジュールのシンボルを参照できる
+pub struct Jit<'ctx> {
+    engine: ExecutionEngine<'ctx>,
+    host_functions: Vec<HostFunction>,
+}
+
+impl<'ctx> Jit<'ctx> {
+    // 実行エンジンを作り、モジュール内で宣言されたホスト関数にアドレスを割り当てる
+    // printfなどCのシンボルは、このプロセスにリンクされたものが使われる
+    pub fn new(
+        module: &Module<'ctx>,
+        opt_level: OptimizationLevel,
+        host_functions: &[HostFunction],
+    ) -> R<Self, String> {
+        Target::initialize_native(&InitializationConfig::default())
+            .map_err(|e| format!("Failed to initialize the native target: {}", e))?;
+        let engine = module
+            .create_jit_execution_engine(opt_level)
+            .map_err(|e| format!("Failed to create the JIT execution engine: {}", e))?;
+        let jit = Jit {
+            engine,
+            host_functions: host_functions.to_vec(),
+        };
+        jit.map_host_functions(module);
+        Ok(jit)
+    }
+
+    pub fn engine(&self) -> &ExecutionEngine<'ctx> {
+        &self.engine
+    }
+
+    // モジュールを追加する(モジュールは実行エンジンが破棄されるまで有効にしておく)
+    pub fn add_module(&self, module: &Module<'ctx>) -> R<(), String> {
+        self.engine
+            .add_module(module)
+            .map_err(|_| String::from("The module is already added to an execution engine"))?;
+        self.map_host_functions(module);
+        Ok(())
+    }
+
+    fn map_host_functions(&self, module: &Module<'ctx>) {
+        for host_function in &self.host_functions {
+            // 呼び出されていないホスト関数はモジュールに宣言されない
+            if let Some(function) = module.get_function(&Decoder::host_symbol(&host_function.name))
+            {
+                self.engine
+                    .add_global_mapping(&function, host_function.address);
+            }
+        }
+    }
+
+    // main関数を実行し、その戻り値(終了コード)を返す
+    pub fn run_main(&self) -> R<i32, String> {
+        self.run_function(ENTRY_FUNCTION_NAME)
+    }
+
+    // 引数なしでi32を返す関数を実行する
+    pub fn run_function(&self, name: &str) -> R<i32, String> {
+        // シグネチャが違う関数をi32を返す関数として呼ぶと、戻り値が不定になる
+        let fn_type = self
+            .engine
+            .get_function_value(name)
+            .map_err(|e| format!("Cannot run the program: no `{}` function ({})", name, e))?
+            .get_type();
+        let returns_i32 = matches!(
+            fn_type.get_return_type(),
+            Some(BasicTypeEnum::IntType(ty)) if ty.get_bit_width() == 32
+        );
+        if fn_type.count_param_types() != 0 || !returns_i32 {
+            return Err(format!(
+                "Cannot run the program: `{}` must take no parameters and return `i32`",
+                name
+            ));
+        }
+        let function = unsafe {
+            self.engine
+                .get_function::<unsafe extern "C" fn() -> i32>(name)
+                .map_err(|e| format!("Cannot run the program: no `{}` function ({})", name, e))?
+        };
+        Ok(unsafe { function.call() })
+    }
+}
@@ -1,2 +1,8 @@
 #[cfg(any(feature = "full", feature = "decoder"))]
 pub mod ir;
+#[cfg(any(feature = "full", feature = "decoder"))]
+pub mod emit;
+#[cfg(any(feature = "full", feature = "decoder"))]
+pub mod jit;
+#[cfg(any(feature = "full", feature = "decoder"))]
+pub mod optimize;
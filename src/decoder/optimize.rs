@@ -0,0 +1,257 @@
+use crate::types::R;
+extern crate inkwell as iw;
+use iw::{
+    module::Module,
+    passes::{PassManager, PassManagerBuilder},
+    values::FunctionValue,
+    OptimizationLevel,
+};
+use std::fmt;
+use std::str::FromStr;
+
+// 最適化レベル(-O0 .. -O3, -Os)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    O3,
+    Os, // O2相当の最適化をコードサイズ優先で行う
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+    // `-O`に続く部分("0".."3", "s")を受け取る
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            _ => Err(format!(
+                "Unknown optimization level `-O{}` (expected -O0, -O1, -O2, -O3 or -Os)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptLevel::O0 => write!(f, "-O0"),
+            OptLevel::O1 => write!(f, "-O1"),
+            OptLevel::O2 => write!(f, "-O2"),
+            OptLevel::O3 => write!(f, "-O3"),
+            OptLevel::Os => write!(f, "-Os"),
+        }
+    }
+}
+
+impl OptLevel {
+    // TargetMachineやJITに渡すコード生成の最適化レベル
+    pub fn codegen_level(&self) -> OptimizationLevel {
+        match self {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+
+    fn size_level(&self) -> u32 {
+        match self {
+            OptLevel::Os => 1,
+            _ => 0,
+        }
+    }
+
+    // インライン展開の閾値(clangの既定値に合わせる, O1以下は常時インライン指定のみ)
+    fn inline_threshold(&self) -> Option<u32> {
+        match self {
+            OptLevel::O0 | OptLevel::O1 => None,
+            OptLevel::O2 => Some(225),
+            OptLevel::O3 => Some(275),
+            OptLevel::Os => Some(75),
+        }
+    }
+}
+
+// 個別に有効にできるパス
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    Mem2Reg,
+    InstCombine,
+    Reassociate,
+    Gvn,
+    SimplifyCfg,
+    Inline,
+    Sccp,
+    Dce,
+    Licm,
+    LoopUnroll,
+    TailCallElim,
+    GlobalDce,
+}
+
+impl Pass {
+    pub const ALL: &'static [Pass] = &[
+        Pass::Mem2Reg,
+        Pass::InstCombine,
+        Pass::Reassociate,
+        Pass::Gvn,
+        Pass::SimplifyCfg,
+        Pass::Inline,
+        Pass::Sccp,
+        Pass::Dce,
+        Pass::Licm,
+        Pass::LoopUnroll,
+        Pass::TailCallElim,
+        Pass::GlobalDce,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Mem2Reg => "mem2reg",
+            Pass::InstCombine => "instcombine",
+            Pass::Reassociate => "reassociate",
+            Pass::Gvn => "gvn",
+            Pass::SimplifyCfg => "simplifycfg",
+            Pass::Inline => "inline",
+            Pass::Sccp => "sccp",
+            Pass::Dce => "dce",
+            Pass::Licm => "licm",
+            Pass::LoopUnroll => "loop-unroll",
+            Pass::TailCallElim => "tailcallelim",
+            Pass::GlobalDce => "globaldce",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Pass::Mem2Reg => "Promote stack variables (alloca) to SSA registers",
+            Pass::InstCombine => "Combine and simplify redundant instructions",
+            Pass::Reassociate => "Reassociate expressions to expose constant folding",
+            Pass::Gvn => "Global value numbering: remove redundant loads and computations",
+            Pass::SimplifyCfg => "Merge and remove unnecessary basic blocks",
+            Pass::Inline => "Inline function calls",
+            Pass::Sccp => "Sparse conditional constant propagation",
+            Pass::Dce => "Aggressively remove dead instructions",
+            Pass::Licm => "Hoist loop-invariant code out of loops",
+            Pass::LoopUnroll => "Unroll loops with small constant trip counts",
+            Pass::TailCallElim => "Turn self-recursive tail calls into loops",
+            Pass::GlobalDce => "Remove unused functions and global variables",
+        }
+    }
+
+    fn add_to(&self, pass_manager: &PassManager<Module>) {
+        match self {
+            Pass::Mem2Reg => pass_manager.add_promote_memory_to_register_pass(),
+            Pass::InstCombine => pass_manager.add_instruction_combining_pass(),
+            Pass::Reassociate => pass_manager.add_reassociate_pass(),
+            Pass::Gvn => pass_manager.add_gvn_pass(),
+            Pass::SimplifyCfg => pass_manager.add_cfg_simplification_pass(),
+            Pass::Inline => pass_manager.add_function_inlining_pass(),
+            Pass::Sccp => pass_manager.add_sccp_pass(),
+            Pass::Dce => pass_manager.add_aggressive_dce_pass(),
+            Pass::Licm => pass_manager.add_licm_pass(),
+            Pass::LoopUnroll => pass_manager.add_loop_unroll_pass(),
+            Pass::TailCallElim => pass_manager.add_tail_call_elimination_pass(),
+            Pass::GlobalDce => pass_manager.add_global_dce_pass(),
+        }
+    }
+}
+
+impl FromStr for Pass {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pass::ALL
+            .iter()
+            .find(|pass| pass.name() == s)
+            .copied()
+            .ok_or_else(|| format!("Unknown pass `{}` (see --list-passes)", s))
+    }
+}
+
+// 一覧表示用(名前と説明)
+pub fn pass_list() -> String {
+    Pass::ALL
+        .iter()
+        .map(|pass| format!("  {:<14}{}\n", pass.name(), pass.description()))
+        .collect()
+}
+
+// モジュールの検証と最適化を行う
+// 最適化レベルの標準パイプラインを実行した後、個別に指定したパスを指定順に実行する
+#[derive(Debug, Clone, Default)]
+pub struct Optimizer {
+    level: OptLevel,
+    passes: Vec<Pass>,
+}
+
+impl Optimizer {
+    pub fn new(level: OptLevel) -> Self {
+        Optimizer {
+            level,
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn add_pass(&mut self, pass: Pass) {
+        self.passes.push(pass);
+    }
+
+    pub fn level(&self) -> OptLevel {
+        self.level
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    pub fn run(&self, module: &Module) -> R<(), String> {
+        // デコーダの誤りでパスが壊れたIRを受け取らないよう、最適化の前にも検証する
+        Optimizer::verify(module, "before optimization")?;
+        if self.level != OptLevel::O0 {
+            self.run_pipeline(module);
+        }
+        if !self.passes.is_empty() {
+            let pass_manager = PassManager::<Module>::create(());
+            for pass in &self.passes {
+                pass.add_to(&pass_manager);
+            }
+            pass_manager.run_on(module);
+        }
+        Optimizer::verify(module, "after optimization")
+    }
+
+    fn run_pipeline(&self, module: &Module) {
+        let builder = PassManagerBuilder::create();
+        builder.set_optimization_level(self.level.codegen_level());
+        builder.set_size_level(self.level.size_level());
+        if let Some(threshold) = self.level.inline_threshold() {
+            builder.set_inliner_with_threshold(threshold);
+        }
+
+        // 関数単位のパス(mem2regなど)を先に全関数へ適用し、その後モジュール単位のパスを実行する
+        let function_passes = PassManager::<FunctionValue>::create(module);
+        builder.populate_function_pass_manager(&function_passes);
+        function_passes.initialize();
+        for function in module.get_functions() {
+            function_passes.run_on(&function);
+        }
+        function_passes.finalize();
+
+        let module_passes = PassManager::<Module>::create(());
+        builder.populate_module_pass_manager(&module_passes);
+        module_passes.run_on(module);
+    }
+
+    fn verify(module: &Module, stage: &str) -> R<(), String> {
+        module
+            .verify()
+            .map_err(|e| format!("Generated invalid LLVM IR ({}): {}", stage, e))
+    }
+}
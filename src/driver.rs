@@ -0,0 +1,566 @@
+use crate::decoder::emit::{link, Emitter, OutputKind};
+use crate::decoder::ir::Decoder;
+use crate::decoder::jit::{HostFunction, Jit};
+use crate::decoder::optimize::{pass_list, OptLevel, Optimizer, Pass};
+use crate::error::{CompilerError, Diagnostic};
+use crate::lexer::preprocess::Preprocessor;
+use crate::lexer::tokenizer::{Lexer, Token};
+use crate::parser::loader::ModuleLoader;
+use crate::parser::syntax::{Node, Parser};
+use crate::source::SourceMap;
+use crate::types::R;
+
+#[cfg(any(feature = "full", feature = "checker"))]
+use crate::checker::resolver::Resolver;
+#[cfg(any(feature = "full", feature = "checker"))]
+use crate::checker::typeck::TypeChecker;
+
+extern crate inkwell as iw;
+use iw::{context::Context, module::Module, OptimizationLevel};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+// 終了コード
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1; // コンパイルエラー、入出力エラー
+pub const EXIT_USAGE: i32 = 2; // コマンドライン引数の誤り
+
+pub const USAGE: &str = "\
+Usage: tanucc [OPTIONS] <INPUT>...
+       tanucc run [OPTIONS] <INPUT>
+
+`run` compiles <INPUT> in memory, executes it with the JIT and exits with its exit code.
+
+Options:
+  -o <PATH>             Write the output to <PATH>
+  --emit=<KIND>         Output kind: tokens, ast, llvm-ir, bc, asm, obj, exe
+  -O0, -O1, -O2, -O3    Optimization level (default: -O0, -O alone means -O2)
+  -Os                   Optimize for size
+  --passes=<P1,P2,..>   Run these passes after the optimization level's pipeline
+  --list-passes         List the passes accepted by --passes
+  --target=<TRIPLE>     Generate code for <TRIPLE> instead of the host
+  --no-bounds-check     Omit runtime bounds checks on array indexing
+  -h, --help            Print this help
+  -V, --version         Print the version
+";
+
+// --emitで指定できる出力の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitKind {
+    Tokens,
+    Ast,
+    LlvmIr,
+    Bitcode,
+    Assembly,
+    Object,
+    Executable,
+}
+
+impl FromStr for EmitKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tokens" => Ok(EmitKind::Tokens),
+            "ast" => Ok(EmitKind::Ast),
+            "llvm-ir" => Ok(EmitKind::LlvmIr),
+            "bc" => Ok(EmitKind::Bitcode),
+            "asm" => Ok(EmitKind::Assembly),
+            "obj" => Ok(EmitKind::Object),
+            "exe" => Ok(EmitKind::Executable),
+            _ => Err(format!(
+                "Unknown emit kind `{}` (expected tokens, ast, llvm-ir, bc, asm, obj or exe)",
+                s
+            )),
+        }
+    }
+}
+
+impl EmitKind {
+    // LLVMのモジュールから書き出す種類ならその出力形式
+    pub fn output_kind(&self) -> Option<OutputKind> {
+        match self {
+            EmitKind::Tokens | EmitKind::Ast => None,
+            EmitKind::LlvmIr => Some(OutputKind::LlvmIr),
+            EmitKind::Bitcode => Some(OutputKind::Bitcode),
+            EmitKind::Assembly => Some(OutputKind::Assembly),
+            EmitKind::Object => Some(OutputKind::Object),
+            EmitKind::Executable => Some(OutputKind::Executable),
+        }
+    }
+
+    // -oを省略したときに標準出力へ書く種類か
+    fn is_textual(&self) -> bool {
+        matches!(self, EmitKind::Tokens | EmitKind::Ast | EmitKind::LlvmIr)
+    }
+
+    fn from_output_kind(kind: OutputKind) -> Self {
+        match kind {
+            OutputKind::LlvmIr => EmitKind::LlvmIr,
+            OutputKind::Bitcode => EmitKind::Bitcode,
+            OutputKind::Assembly => EmitKind::Assembly,
+            OutputKind::Object => EmitKind::Object,
+            OutputKind::Executable => EmitKind::Executable,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub inputs: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub emit: EmitKind,
+    pub opt_level: OptLevel,
+    pub passes: Vec<Pass>, // 最適化レベルのパイプラインの後に実行するパス
+    pub target: Option<String>,
+    pub bounds_check: bool,
+}
+
+// コマンドライン引数の解釈結果
+#[derive(Debug, Clone)]
+pub enum Action {
+    Compile(Options),
+    Run(Options),
+    Help,
+    ListPasses,
+    Version,
+}
+
+impl Action {
+    // プログラム名を除いた引数を解釈する
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> R<Action, String> {
+        let mut args = args.into_iter().peekable();
+        let is_run = args.peek().map(|arg| arg == "run").unwrap_or(false);
+        if is_run {
+            args.next();
+        }
+        let mut inputs = Vec::new();
+        let mut output = None;
+        let mut emit = None;
+        let mut opt_level = OptLevel::O0;
+        let mut passes = Vec::new();
+        let mut target = None;
+        let mut bounds_check = true;
+        let mut only_inputs = false;
+
+        while let Some(arg) = args.next() {
+            if only_inputs || !arg.starts_with('-') || arg == "-" {
+                inputs.push(PathBuf::from(arg));
+                continue;
+            }
+            match arg.as_str() {
+                "--" => only_inputs = true,
+                "-h" | "--help" => return Ok(Action::Help),
+                "-V" | "--version" => return Ok(Action::Version),
+                "--list-passes" => return Ok(Action::ListPasses),
+                "-o" => output = Some(PathBuf::from(Self::value(&mut args, &arg)?)),
+                "--emit" => emit = Some(Self::value(&mut args, &arg)?.parse()?),
+                "--target" => target = Some(Self::value(&mut args, &arg)?),
+                "--passes" => passes.extend(Self::parse_passes(&Self::value(&mut args, &arg)?)?),
+                "--no-bounds-check" => bounds_check = false,
+                "-O" => opt_level = OptLevel::O2,
+                _ => {
+                    if let Some(level) = arg.strip_prefix("-O") {
+                        opt_level = level.parse()?;
+                    } else if let Some(names) = arg.strip_prefix("--passes=") {
+                        passes.extend(Self::parse_passes(names)?);
+                    } else if let Some(kind) = arg.strip_prefix("--emit=") {
+                        emit = Some(kind.parse()?);
+                    } else if let Some(triple) = arg.strip_prefix("--target=") {
+                        target = Some(triple.to_string());
+                    } else if let Some(path) = arg.strip_prefix("-o") {
+                        output = Some(PathBuf::from(path));
+                    } else {
+                        return Err(format!("Unknown option `{}`", arg));
+                    }
+                }
+            }
+        }
+
+        if inputs.is_empty() {
+            return Err(String::from("No input files"));
+        }
+        // --emitがなければ出力先の拡張子から決め、それもなければ実行可能ファイルを生成する
+        let emit = emit.unwrap_or_else(|| match &output {
+            Some(path) => EmitKind::from_output_kind(OutputKind::from_path(path)),
+            None => EmitKind::Executable,
+        });
+        if is_run && inputs.len() > 1 {
+            return Err(String::from("`run` takes exactly one input file"));
+        }
+        if output.is_some() && inputs.len() > 1 && emit != EmitKind::Executable {
+            return Err(String::from(
+                "`-o` cannot be used with multiple input files unless emitting an executable",
+            ));
+        }
+        let options = Options {
+            inputs,
+            output,
+            emit,
+            opt_level,
+            passes,
+            target,
+            bounds_check,
+        };
+        Ok(if is_run {
+            Action::Run(options)
+        } else {
+            Action::Compile(options)
+        })
+    }
+
+    // カンマ区切りのパス名
+    fn parse_passes(names: &str) -> R<Vec<Pass>, String> {
+        names
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .map(|name| name.parse())
+            .collect()
+    }
+
+    fn value(args: &mut impl Iterator<Item = String>, option: &str) -> R<String, String> {
+        args.next()
+            .ok_or_else(|| format!("Option `{}` requires a value", option))
+    }
+}
+
+impl Options {
+    // TargetMachineとJITのコード生成に使う最適化レベル
+    pub fn optimization_level(&self) -> OptimizationLevel {
+        self.opt_level.codegen_level()
+    }
+
+    pub fn optimizer(&self) -> Optimizer {
+        let mut optimizer = Optimizer::new(self.opt_level);
+        for pass in &self.passes {
+            optimizer.add_pass(*pass);
+        }
+        optimizer
+    }
+
+    // 入力ファイルに対応する出力先(Noneなら標準出力)
+    fn output_path(&self, input: &Path) -> Option<PathBuf> {
+        if let Some(output) = &self.output {
+            return Some(output.clone());
+        }
+        if self.emit.is_textual() {
+            return None;
+        }
+        let extension = self
+            .emit
+            .output_kind()
+            .map(|kind| kind.extension())
+            .unwrap_or_default();
+        Some(input.with_extension(extension))
+    }
+}
+
+// コマンドライン引数を解釈してコンパイルし、終了コードを返す
+pub fn main_with_args<I: IntoIterator<Item = String>>(args: I) -> i32 {
+    main_with_host_functions(args, &[])
+}
+
+// `run`で実行するスクリプトから`@名前`で呼べるホスト関数を渡せる
+pub fn main_with_host_functions<I: IntoIterator<Item = String>>(
+    args: I,
+    host_functions: &[HostFunction],
+) -> i32 {
+    match Action::parse(args) {
+        Ok(Action::Help) => {
+            print!("{}", USAGE);
+            EXIT_SUCCESS
+        }
+        Ok(Action::ListPasses) => {
+            print!("{}", pass_list());
+            EXIT_SUCCESS
+        }
+        Ok(Action::Version) => {
+            println!("tanucc {}", env!("CARGO_PKG_VERSION"));
+            EXIT_SUCCESS
+        }
+        Ok(Action::Compile(options)) => match run(&options) {
+            Ok(()) => EXIT_SUCCESS,
+            Err(e) => {
+                eprintln!("{}", e);
+                EXIT_FAILURE
+            }
+        },
+        Ok(Action::Run(options)) => match run_jit(&options, host_functions) {
+            Ok(exit_code) => exit_code,
+            Err(e) => {
+                eprintln!("{}", e);
+                EXIT_FAILURE
+            }
+        },
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            EXIT_USAGE
+        }
+    }
+}
+
+pub fn run(options: &Options) -> R<(), String> {
+    // 複数ファイルの実行可能ファイルは、ファイルごとのオブジェクトをまとめてリンクする
+    if options.emit == EmitKind::Executable && options.inputs.len() > 1 {
+        return compile_and_link(options);
+    }
+    for input in &options.inputs {
+        compile_file(options, input, options.output_path(input))?;
+    }
+    Ok(())
+}
+
+fn compile_and_link(options: &Options) -> R<(), String> {
+    let output = options
+        .output
+        .clone()
+        .unwrap_or_else(|| options.inputs[0].with_extension(""));
+    let mut objects = Vec::new();
+    let mut result = Ok(());
+    for (i, input) in options.inputs.iter().enumerate() {
+        let object = env::temp_dir().join(format!("tanucc-{}-{}.o", process::id(), i));
+        objects.push(object.clone());
+        let mut object_options = options.clone();
+        object_options.emit = EmitKind::Object;
+        result = compile_file(&object_options, input, Some(object));
+        if result.is_err() {
+            break;
+        }
+    }
+    if result.is_ok() {
+        result = link(&objects, &output);
+    }
+    for object in &objects {
+        let _ = fs::remove_file(object);
+    }
+    result
+}
+
+// `run`: 入力ファイルをメモリ上でコンパイルしてJITで実行し、main関数の戻り値を返す
+pub fn run_jit(options: &Options, host_functions: &[HostFunction]) -> R<i32, String> {
+    let input = &options.inputs[0];
+    let (input_path, contents) = read_source(input)?;
+    let source_map = SourceMap::new();
+    let report = |diagnostics| format_diagnostics(&source_map, &input_path, &contents, diagnostics);
+    let tokens = tokenize(&source_map, input, &input_path, &contents).map_err(report)?;
+    let nodes = parse(&source_map, &tokens, &input_path, &contents).map_err(report)?;
+    let program = ModuleLoader::new(&source_map)
+        .load(input, &input_path, contents.clone(), &nodes)
+        .map_err(report)?;
+
+    let context = Context::create();
+    let module = generate_module(
+        &context,
+        options,
+        &source_map,
+        &input_path,
+        contents.clone(),
+        &program,
+        host_functions,
+    )
+    .map_err(report)?;
+    let jit = Jit::new(&module, options.optimization_level(), host_functions)
+        .map_err(|e| format!("error: {}", e))?;
+    jit.run_main().map_err(|e| format!("error: {}", e))
+}
+
+fn compile_file(options: &Options, input: &Path, output: Option<PathBuf>) -> R<(), String> {
+    let (input_path, contents) = read_source(input)?;
+    let source_map = SourceMap::new();
+    let report = |diagnostics| format_diagnostics(&source_map, &input_path, &contents, diagnostics);
+    let tokens = tokenize(&source_map, input, &input_path, &contents).map_err(report)?;
+    if options.emit == EmitKind::Tokens {
+        return write_output(output.as_deref(), &format_tokens(&tokens, &source_map));
+    }
+    let nodes = parse(&source_map, &tokens, &input_path, &contents).map_err(report)?;
+    if options.emit == EmitKind::Ast {
+        return write_output(output.as_deref(), &format_ast(&nodes));
+    }
+    // `mod`で宣言されたファイルを読み込み、1つのプログラムにまとめる
+    let program = ModuleLoader::new(&source_map)
+        .load(input, &input_path, contents.clone(), &nodes)
+        .map_err(report)?;
+
+    let context = Context::create();
+    let module = generate_module(
+        &context,
+        options,
+        &source_map,
+        &input_path,
+        contents.clone(),
+        &program,
+        &[],
+    )
+    .map_err(report)?;
+    let emitter = Emitter::new(options.target.as_deref(), options.optimization_level())
+        .map_err(|e| format!("error: {}", e))?;
+    let kind = options
+        .emit
+        .output_kind()
+        .expect("tokens and ast are emitted before code generation");
+    match output {
+        Some(path) => emitter
+            .emit(&module, kind, &path)
+            .map_err(|e| format!("error: {}", e)),
+        None => {
+            emitter.configure_module(&module);
+            write_output(None, &module.print_to_string().to_string())
+        }
+    }
+}
+
+// 入力ファイルの(表示用のパス, 内容)
+fn read_source(input: &Path) -> R<(String, String), String> {
+    let input_path = input.to_string_lossy().to_string();
+    let contents = fs::read_to_string(input)
+        .map_err(|e| format!("error: Failed to read `{}`: {}", input_path, e))?;
+    Ok((input_path, contents))
+}
+
+// 診断を表示する文字列(エラーがあれば件数のまとめを続ける)
+fn format_diagnostics(
+    source_map: &SourceMap,
+    input_path: &str,
+    contents: &str,
+    diagnostics: Vec<Diagnostic>,
+) -> String {
+    let mut errors = CompilerError::new();
+    errors.set_source_map(source_map);
+    errors.extend(diagnostics);
+    format!(
+        "{}{}",
+        errors.format_error_string(input_path, contents),
+        errors.summary()
+    )
+}
+
+// 字句解析してincludeを展開する(読み込んだファイルはsource_mapに登録する)
+fn tokenize(
+    source_map: &SourceMap,
+    input: &Path,
+    input_path: &str,
+    contents: &str,
+) -> R<Vec<Token>, Vec<Diagnostic>> {
+    let mut lexer = Lexer::new_with_value(input_path, contents.to_string());
+    lexer.set_source_map(source_map.clone());
+    lexer
+        .tokenize()
+        .and_then(|tokens| Preprocessor::new(source_map).preprocess(input, tokens))
+        .map_err(|e| vec![e])
+}
+
+fn parse(
+    source_map: &SourceMap,
+    tokens: &Vec<Token>,
+    input_path: &str,
+    contents: &str,
+) -> R<Box<Node>, Vec<Diagnostic>> {
+    let mut parser = Parser::new(tokens, input_path, contents.to_string());
+    parser.set_source_map(source_map);
+    parser.parse().map_err(|e| vec![e])
+}
+
+// 構文木を検査し、LLVMのモジュールを生成する
+fn generate_module<'ctx>(
+    context: &'ctx Context,
+    options: &Options,
+    source_map: &SourceMap,
+    input_path: &str,
+    contents: String,
+    nodes: &Node,
+    host_functions: &[HostFunction],
+) -> R<Module<'ctx>, Vec<Diagnostic>> {
+    // 名前解決と型のエラーはまとめて報告し、IRの生成には進まない
+    #[cfg(any(feature = "full", feature = "checker"))]
+    {
+        let mut resolver = Resolver::new(input_path, contents.clone());
+        let mut checker = TypeChecker::new(input_path, contents.clone());
+        resolver.set_source_map(source_map);
+        checker.set_source_map(source_map);
+        for host_function in host_functions {
+            resolver.register_host_function(&host_function.name);
+            checker.register_host_function(
+                &host_function.name,
+                &host_function.params,
+                host_function.return_type,
+            );
+        }
+        // 名前解決のエラーがあっても型検査まで進め、両方の診断を1度に表示する
+        let mut diagnostics = CompilerError::new();
+        diagnostics.set_source_map(source_map);
+        diagnostics.extend(resolver.diagnose(nodes));
+        diagnostics.extend(checker.diagnose(nodes));
+        if diagnostics.has_errors() {
+            return Err(diagnostics.into_diagnostics());
+        }
+        eprint!("{}", diagnostics.format_error_string(input_path, &contents));
+    }
+
+    let builder = context.create_builder();
+    let module_name = Path::new(input_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("main"));
+    let module = context.create_module(&module_name);
+    let mut decoder = Decoder::new(input_path, contents);
+    decoder.set_source_map(source_map);
+    decoder.set_bounds_check(options.bounds_check);
+    for host_function in host_functions {
+        host_function.register(&mut decoder);
+    }
+    decoder
+        .decode_program(&builder, context, &module, nodes)
+        .map_err(|e| vec![e])?;
+    options
+        .optimizer()
+        .run(&module)
+        .map_err(|e| vec![Diagnostic::error(&e)])?;
+    Ok(module)
+}
+
+// `パス:行:列\t開始..終了(バイト位置)\tトークンの種類\t値`
+pub(crate) fn format_tokens(tokens: &[Token], source_map: &SourceMap) -> String {
+    tokens
+        .iter()
+        .map(|token| {
+            let span = token.span();
+            format!(
+                "{}:{}:{}\t{}..{}\t{:?}\t{:?}\n",
+                source_map
+                    .file(span.file)
+                    .map(|file| file.path.clone())
+                    .unwrap_or_default(),
+                token.line(),
+                token.column(),
+                span.start,
+                span.end,
+                token.token_type(),
+                token.token_value()
+            )
+        })
+        .collect()
+}
+
+pub(crate) fn format_ast(node: &Node) -> String {
+    node.iter()
+        .map(|statement| format!("{:#?}\n", statement.borrow().value))
+        .collect()
+}
+
+// pathがNoneなら標準出力に書く
+fn write_output(path: Option<&Path>, content: &str) -> R<(), String> {
+    match path {
+        Some(path) => fs::write(path, content)
+            .map_err(|e| format!("error: Failed to write `{}`: {}", path.display(), e)),
+        None => {
+            let mut stdout = std::io::stdout();
+            stdout
+                .write_all(content.as_bytes())
+                .and_then(|_| stdout.flush())
+                .map_err(|e| format!("error: Failed to write to stdout: {}", e))
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::source::{SourceFile, SourceMap, Span};
 use anyhow::{Context, Result};
 use colored::*;
 use std::fmt;
@@ -26,138 +27,674 @@ error_code!(TYPE_ERROR, "E1120", "Type mismatch error");
 error_code!(UNDEFINED_VARIABLE, "E1130", "Undefined variable");
 
 pub struct CompilerError {
-    messages: Vec<ErrorMessage>,
+    messages: Vec<Diagnostic>,
+    source_map: SourceMap, // Spanを持つメッセージの表示に使う
 }
 
-struct ErrorMessage {
+// 1件の診断メッセージ
+// 主たる範囲(`^`で下線)、ラベル付きの副次的な範囲(`-`で下線)、note/help、置き換えの提案を持つ
+// 値として作って`CompilerError::push`で溜め、全ての検査が終わってからまとめて表示する
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    inner: Box<DiagnosticInner>, // Resultのエラーとして返すので小さく保つ
+}
+
+#[derive(Debug, Clone)]
+struct DiagnosticInner {
     level: String,
-    lines: Vec<(usize, usize)>,
+    code: Option<&'static str>,
+    lines: Vec<(usize, usize)>, // Spanがない(またはSourceMapにない)ときに表示する位置
+    source: Option<(String, String)>, // linesを表示するファイルの(パス, 内容)
+    span: Option<Span>,
+    label: Option<String>, // 主たる範囲の下線の後ろに表示する説明
+    labels: Vec<Label>,
     message: String,
     children: Vec<ChildMessage>,
+    suggestions: Vec<Suggestion>,
+}
+
+#[derive(Debug, Clone)]
+struct Label {
+    span: Span,
+    message: String,
 }
 
+#[derive(Debug, Clone)]
 struct ChildMessage {
     level: String,
     message: String,
 }
 
+// spanの範囲をreplacementに置き換える提案
+#[derive(Debug, Clone)]
+struct Suggestion {
+    span: Span,
+    message: String,
+    replacement: String,
+}
+
+// 表示する1行分の下線
+struct Mark {
+    line: usize,
+    column: usize,
+    end_column: Option<usize>, // Noneなら行末まで
+    label: Option<String>,
+    is_primary: bool,
+}
+
+// 複数行にまたがる範囲は、これより長ければ先頭と末尾の2行ずつだけ表示する
+const MAX_RANGE_LINES: usize = 4;
+
+impl Diagnostic {
+    pub fn new(level: &str, message: &str) -> Self {
+        let inner = DiagnosticInner {
+            level: level.to_string(),
+            code: None,
+            lines: Vec::new(),
+            source: None,
+            span: None,
+            label: None,
+            labels: Vec::new(),
+            message: message.to_string(),
+            children: Vec::new(),
+            suggestions: Vec::new(),
+        };
+        Diagnostic {
+            inner: Box::new(inner),
+        }
+    }
+
+    pub fn error(message: &str) -> Self {
+        Diagnostic::new("error", message)
+    }
+
+    pub fn warning(message: &str) -> Self {
+        Diagnostic::new("warning", message)
+    }
+
+    // `error[E1120]: ...`のエラーコード
+    pub fn with_code(mut self, code: &CompilerErrorCode) -> Self {
+        self.inner.code = Some(code.code);
+        self
+    }
+
+    // Spanが使えないときに表示する(行, 列)
+    pub fn at(mut self, line: usize, column: usize) -> Self {
+        self.inner.lines.push((line, column));
+        self
+    }
+
+    // linesを表示するファイル(指定がなければ表示するときに渡されたファイル)
+    pub fn with_source(mut self, path: &str, content: &str) -> Self {
+        self.inner.source = Some((path.to_string(), content.to_string()));
+        self
+    }
+
+    // 主たる範囲(空のSpanは無視する)
+    pub fn with_span(mut self, span: Span) -> Self {
+        if !span.is_empty() {
+            self.inner.span = Some(span);
+        }
+        self
+    }
+
+    // 説明付きの主たる範囲
+    pub fn with_span_label(mut self, span: Span, label: &str) -> Self {
+        self.inner.label = Some(label.to_string());
+        self.with_span(span)
+    }
+
+    // 説明付きの副次的な範囲(別のファイルでもよい)
+    pub fn with_label(mut self, span: Span, message: &str) -> Self {
+        if !span.is_empty() {
+            self.inner.labels.push(Label {
+                span,
+                message: message.to_string(),
+            });
+        }
+        self
+    }
+
+    pub fn with_note(self, message: &str) -> Self {
+        self.with_child("note", message)
+    }
+
+    pub fn with_help(self, message: &str) -> Self {
+        self.with_child("help", message)
+    }
+
+    pub fn with_child(mut self, level: &str, message: &str) -> Self {
+        self.inner.children.push(ChildMessage {
+            level: level.to_string(),
+            message: message.to_string(),
+        });
+        self
+    }
+
+    // spanをreplacementに置き換える提案(表示できなければhelpとして置き換え後の文字列を示す)
+    pub fn with_suggestion(mut self, span: Span, message: &str, replacement: &str) -> Self {
+        self.inner.suggestions.push(Suggestion {
+            span,
+            message: message.to_string(),
+            replacement: replacement.to_string(),
+        });
+        self
+    }
+
+    pub fn level(&self) -> &str {
+        &self.inner.level
+    }
+
+    pub fn code(&self) -> Option<&'static str> {
+        self.inner.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.inner.message
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.inner.span
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.inner.level == "error"
+    }
+}
+
+// SourceMapなしで表示する(Spanは使わず、linesの位置に下線を引く)
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut errors = CompilerError::new();
+        errors.push(self.clone());
+        write!(f, "{}", errors.format_error_string("", ""))
+    }
+}
+
+// 位置を持たないエラー(LLVMやファイルの読み書きのエラーなど)
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Diagnostic::error(&message)
+    }
+}
+
+impl Default for CompilerError {
+    fn default() -> Self {
+        CompilerError::new()
+    }
+}
+
 impl CompilerError {
     pub fn new() -> Self {
         CompilerError {
             messages: Vec::new(),
+            source_map: SourceMap::new(),
         }
     }
 
+    pub fn set_source_map(&mut self, source_map: &SourceMap) {
+        self.source_map = source_map.clone();
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.messages.push(diagnostic);
+    }
+
+    pub fn extend<I: IntoIterator<Item = Diagnostic>>(&mut self, diagnostics: I) {
+        self.messages.extend(diagnostics);
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.messages
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.messages
+    }
+
     pub fn add_group_message(&mut self, level: &str, lines: Vec<(usize, usize)>, message: &str) {
-        self.messages.push(ErrorMessage {
-            level: level.to_string(),
-            lines,
-            message: message.to_string(),
-            children: Vec::new(),
-        });
+        let mut diagnostic = Diagnostic::new(level, message);
+        diagnostic.inner.lines = lines;
+        self.push(diagnostic);
     }
 
     pub fn add_message(&mut self, level: &str, line: usize, column: usize, message: &str) {
-        self.messages.push(ErrorMessage {
-            level: level.to_string(),
-            lines: vec![(line, column)],
-            message: message.to_string(),
-            children: Vec::new(),
-        });
+        self.push(Diagnostic::new(level, message).at(line, column));
+    }
+
+    // エラーコード付きのメッセージ(`error[E1120]: ...`)
+    pub fn add_message_with_code(
+        &mut self,
+        level: &str,
+        code: &CompilerErrorCode,
+        line: usize,
+        column: usize,
+        message: &str,
+    ) {
+        self.push(
+            Diagnostic::new(level, message)
+                .with_code(code)
+                .at(line, column),
+        );
+    }
+
+    // 最後に追加したメッセージのインデックス(子メッセージの追加に使う)
+    pub fn last_index(&self) -> Option<usize> {
+        self.messages.len().checked_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.messages.iter().any(Diagnostic::is_error)
+    }
+
+    pub fn count(&self, level: &str) -> usize {
+        self.messages
+            .iter()
+            .filter(|msg| msg.inner.level == level)
+            .count()
+    }
+
+    // メッセージにSpanを付ける(SourceMapに登録されたファイルなら、そのファイルの範囲に下線を引く)
+    pub fn set_span(&mut self, index: usize, span: Span) {
+        if let Some(msg) = self.messages.get_mut(index) {
+            if !span.is_empty() {
+                msg.inner.span = Some(span);
+            }
+        }
     }
 
     pub fn add_child_message(&mut self, index: usize, level: &str, message: &str) {
         if let Some(msg) = self.messages.get_mut(index) {
-            msg.children.push(ChildMessage {
+            msg.inner.children.push(ChildMessage {
                 level: level.to_string(),
                 message: message.to_string(),
             });
         }
     }
+
+    // エラーの件数のまとめ(エラーがなければ空)
+    pub fn summary(&self) -> String {
+        let errors = self.count("error");
+        if errors == 0 {
+            return String::new();
+        }
+        let warnings = self.count("warning");
+        let mut summary = format!(
+            "{}: aborting due to {} previous error{}",
+            "error".red().bold(),
+            errors,
+            if errors == 1 { "" } else { "s" }
+        );
+        if warnings > 0 {
+            summary.push_str(&format!(
+                "; {} warning{} emitted",
+                warnings,
+                if warnings == 1 { "" } else { "s" }
+            ));
+        }
+        summary.push('\n');
+        summary
+    }
+
+    // file/source_codeはSpanを持たないメッセージの表示に使う
     pub fn format_error_string(&self, file: &str, source_code: &str) -> String {
         let mut result = String::new();
         for msg in &self.messages {
-            let color = match msg.level.as_str() {
-                "warning" => "warning".yellow().bold(),
-                "error" => "error".red().bold(),
-                "note" => "note".blue().bold(),
-                _ => "info".normal(),
-            };
-            result.push_str(&format!(
-                "{}: {}\n  {} {}:{}:{}\n",
-                color,
-                msg.message,
-                "-->".blue().bold(),
-                file,
-                msg.lines[0].0,
-                msg.lines[0].1
-            ));
-            for &(line, column) in &msg.lines {
-                if let Some(source_line) = source_code.lines().nth(line - 1) {
-                    result.push_str(&format!(
-                        "{}  {}   {}  \n",
-                        line.to_string().blue().bold(),
-                        "|".blue().bold(),
-                        source_line
-                    ));
+            result.push_str(&self.format_diagnostic(msg, file, source_code));
+        }
+        result
+    }
 
-                    // 文字幅を正確に計算する
-                    let mut width = 0;
-                    for ch in source_line.chars().take(column - 1) {
-                        width += UnicodeWidthChar::width(ch).unwrap_or(0);
+    fn format_diagnostic(&self, msg: &Diagnostic, file: &str, source_code: &str) -> String {
+        let color = match msg.inner.level.as_str() {
+            "warning" => "warning".yellow().bold(),
+            "error" => "error".red().bold(),
+            "note" => "note".blue().bold(),
+            _ => "info".normal(),
+        };
+        let code = match msg.inner.code {
+            Some(code) => format!("[{}]", code).bold(),
+            None => "".normal(),
+        };
+        let mut result = format!("{}{}: {}\n", color, code, msg.inner.message);
+
+        // 主たる範囲と同じファイルのラベルは一緒に表示し、他のファイルのラベルはファイルごとにまとめる
+        let primary = msg
+            .inner
+            .span
+            .and_then(|span| Some((span, self.source_map.file(span.file)?)));
+        let mut labels: Vec<&Label> = msg.inner.labels.iter().collect();
+        match primary {
+            Some((span, ref source_file)) => {
+                let (line, column) = source_file.line_column(span.start);
+                let location = format!("{}:{}:{}", source_file.path, line, column);
+                let mut marks =
+                    CompilerError::range_marks(source_file, span, &msg.inner.label, true);
+                labels.retain(|label| {
+                    if label.span.file != span.file {
+                        return true;
                     }
-                    result.push_str(&format!(
-                        "   {} {}{}  \n",
-                        "|".blue().bold(),
-                        " ".repeat(width),
-                        "^".red().bold()
+                    marks.extend(CompilerError::range_marks(
+                        source_file,
+                        label.span,
+                        &Some(label.message.clone()),
+                        false,
                     ));
-                }
+                    false
+                });
+                result.push_str(&CompilerError::format_snippet(
+                    "-->",
+                    &location,
+                    marks,
+                    |line| source_file.line(line).map(str::to_string),
+                ));
             }
-            for child in &msg.children {
-                let child_color = match child.level.as_str() {
-                    "note" => "note".blue().bold(),
-                    _ => "info".normal(),
+            None => {
+                let (file, source_code) = match msg.inner.source {
+                    Some((ref path, ref content)) => (path.as_str(), content.as_str()),
+                    None => (file, source_code),
                 };
-                result.push_str(&format!("  = {}: {}\n", child_color, child.message));
+                if let Some(&(line, column)) = msg.inner.lines.first() {
+                    let location = format!("{}:{}:{}", file, line, column);
+                    let marks = msg
+                        .inner
+                        .lines
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &(line, column))| Mark {
+                            line,
+                            column,
+                            end_column: Some(column + 1),
+                            label: if i == 0 {
+                                msg.inner.label.clone()
+                            } else {
+                                None
+                            },
+                            is_primary: true,
+                        })
+                        .collect();
+                    result.push_str(&CompilerError::format_snippet(
+                        "-->",
+                        &location,
+                        marks,
+                        |line| {
+                            source_code
+                                .lines()
+                                .nth(line.checked_sub(1)?)
+                                .map(str::to_string)
+                        },
+                    ));
+                }
+            }
+        }
+        while let Some(first) = labels.first() {
+            let file_id = first.span.file;
+            let group: Vec<&Label> = labels
+                .iter()
+                .filter(|label| label.span.file == file_id)
+                .cloned()
+                .collect();
+            labels.retain(|label| label.span.file != file_id);
+            let source_file = match self.source_map.file(file_id) {
+                Some(source_file) => source_file,
+                None => continue,
+            };
+            let (line, column) = source_file.line_column(group[0].span.start);
+            let location = format!("{}:{}:{}", source_file.path, line, column);
+            let marks = group
+                .iter()
+                .flat_map(|label| {
+                    CompilerError::range_marks(
+                        &source_file,
+                        label.span,
+                        &Some(label.message.clone()),
+                        false,
+                    )
+                })
+                .collect();
+            result.push_str(&CompilerError::format_snippet(
+                ":::",
+                &location,
+                marks,
+                |line| source_file.line(line).map(str::to_string),
+            ));
+        }
+
+        for child in &msg.inner.children {
+            let child_color = match child.level.as_str() {
+                "note" => "note".blue().bold(),
+                "help" => "help".cyan().bold(),
+                _ => "info".normal(),
+            };
+            result.push_str(&format!("  = {}: {}\n", child_color, child.message));
+        }
+        for suggestion in &msg.inner.suggestions {
+            result.push_str(&self.format_suggestion(suggestion));
+        }
+        result
+    }
+
+    // spanの範囲の下線(複数行にまたがる範囲は行ごとに引き、説明は最後の行に付ける)
+    fn range_marks(
+        source_file: &SourceFile,
+        span: Span,
+        label: &Option<String>,
+        is_primary: bool,
+    ) -> Vec<Mark> {
+        let (line, column) = source_file.line_column(span.start);
+        let (mut end_line, mut end_column) = source_file.line_column(span.end);
+        // 改行までを含む範囲は前の行の末尾で終わる
+        if end_line > line && end_column == 1 {
+            end_line -= 1;
+            end_column = source_file
+                .line(end_line)
+                .map_or(1, |s| s.chars().count() + 1);
+        }
+        if end_line == line {
+            return vec![Mark {
+                line,
+                column,
+                end_column: Some(end_column),
+                label: label.clone(),
+                is_primary,
+            }];
+        }
+        // 2行目以降は字下げの後から下線を引く
+        let indent = |line: usize| {
+            source_file.line(line).map_or(1, |s| {
+                s.chars().take_while(|c| c.is_whitespace()).count() + 1
+            })
+        };
+        let count = end_line - line + 1;
+        (line..=end_line)
+            .filter(|&l| count <= MAX_RANGE_LINES || l < line + 2 || l + 2 > end_line)
+            .map(|l| Mark {
+                line: l,
+                column: if l == line { column } else { indent(l) },
+                end_column: if l == end_line {
+                    Some(end_column)
+                } else {
+                    None
+                },
+                label: if l == end_line { label.clone() } else { None },
+                is_primary,
+            })
+            .collect()
+    }
+
+    // `--> 位置`と、下線を引いたソースの抜粋(行番号順、間の省略した行は`...`で示す)
+    fn format_snippet<F: Fn(usize) -> Option<String>>(
+        arrow: &str,
+        location: &str,
+        mut marks: Vec<Mark>,
+        source_line: F,
+    ) -> String {
+        marks.sort_by_key(|mark| (mark.line, !mark.is_primary, mark.column));
+        let width = marks
+            .iter()
+            .map(|mark| mark.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(width);
+        let mut result = format!(
+            "{}{} {}\n{} {}\n",
+            gutter,
+            arrow.blue().bold(),
+            location,
+            gutter,
+            "|".blue().bold()
+        );
+        let mut previous: Option<usize> = None;
+        for mark in &marks {
+            let text = match source_line(mark.line) {
+                Some(text) => text,
+                None => continue,
+            };
+            if previous != Some(mark.line) {
+                if previous.is_some_and(|previous| mark.line > previous + 1) {
+                    result.push_str(&format!("{}\n", "...".blue().bold()));
+                }
+                result.push_str(&format!(
+                    "{} {} {}\n",
+                    format!("{:>width$}", mark.line, width = width)
+                        .blue()
+                        .bold(),
+                    "|".blue().bold(),
+                    text
+                ));
+                previous = Some(mark.line);
             }
+            let (padding, length) = CompilerError::underline(&text, mark.column, mark.end_column);
+            let (underline, label) = match mark.is_primary {
+                true => (
+                    "^".repeat(length).red().bold(),
+                    mark.label.clone().unwrap_or_default().red().bold(),
+                ),
+                false => (
+                    "-".repeat(length).blue().bold(),
+                    mark.label.clone().unwrap_or_default().blue().bold(),
+                ),
+            };
+            let line = format!(
+                "{} {} {}{} {}",
+                gutter,
+                "|".blue().bold(),
+                " ".repeat(padding),
+                underline,
+                label
+            );
+            result.push_str(line.trim_end());
+            result.push('\n');
         }
         result
     }
+
+    // 置き換えた後の行と、置き換えた部分の`~`(1行に収まらない範囲は文字列だけ示す)
+    fn format_suggestion(&self, suggestion: &Suggestion) -> String {
+        let help = "help".cyan().bold();
+        let fallback = format!(
+            "  = {}: {}: `{}`\n",
+            help, suggestion.message, suggestion.replacement
+        );
+        let source_file = match self.source_map.file(suggestion.span.file) {
+            Some(source_file) => source_file,
+            None => return fallback,
+        };
+        let (line, column) = source_file.line_column(suggestion.span.start);
+        let (end_line, _) = source_file.line_column(suggestion.span.end);
+        let text = match source_file.line(line) {
+            Some(text) if end_line == line => text,
+            _ => return fallback,
+        };
+        let line_start = source_file.offset(line, 1);
+        let start = (suggestion.span.start - line_start).min(text.len());
+        let end = (suggestion.span.end - line_start).clamp(start, text.len());
+        let replaced = format!(
+            "{}{}{}",
+            &text[..start],
+            suggestion.replacement,
+            &text[end..]
+        );
+        let width = line.to_string().len();
+        let gutter = " ".repeat(width);
+        let (padding, _) = CompilerError::underline(&replaced, column, Some(column));
+        let length = UnicodeWidthStr::width(suggestion.replacement.as_str()).max(1);
+        format!(
+            "{}: {}\n{} {}\n{} {} {}\n{} {} {}{}\n",
+            help,
+            suggestion.message,
+            gutter,
+            "|".blue().bold(),
+            line.to_string().blue().bold(),
+            "|".blue().bold(),
+            replaced,
+            gutter,
+            "|".blue().bold(),
+            " ".repeat(padding),
+            "~".repeat(length).green().bold()
+        )
+    }
+
+    // columnからend_column(Noneなら行末)の手前までの下線の(字下げ, 長さ)(長さは最低1文字)
+    fn underline(source_line: &str, column: usize, end_column: Option<usize>) -> (usize, usize) {
+        // 文字幅を正確に計算する
+        let mut width = 0;
+        for ch in source_line.chars().take(column.saturating_sub(1)) {
+            width += UnicodeWidthChar::width(ch).unwrap_or(0);
+        }
+        let length = source_line
+            .chars()
+            .skip(column.saturating_sub(1))
+            .take(end_column.map_or(usize::MAX, |end| end.saturating_sub(column)))
+            .map(|ch| UnicodeWidthChar::width(ch).unwrap_or(0))
+            .sum::<usize>()
+            .max(1);
+        (width, length)
+    }
 }
 
+// 以下のマクロはトークン・ノードの位置(行, 列)と範囲(Span)を持つDiagnosticを作る
+// file_name/srcは範囲をSourceMapで表示できないときに使う
 #[macro_export]
 macro_rules! compile_error_with_children {
-    ($level:expr, $file_name:expr, $src:expr, $line:expr, $column:expr, $message:expr, $($child_level:expr, $child_message:expr),*) => {
-        {
-            let mut compiler_error = crate::error::CompilerError::new();
-            compiler_error.add_message($level, $line, $column, $message);
-            $(
-                compiler_error.add_child_message(0, $child_level, $child_message);
-            )*
-            compiler_error.format_error_string($file_name, $src)
-        }
+    ($level:expr, $file_name:expr, $src:expr, $line:expr, $column:expr, $span:expr, $message:expr, $($child_level:expr, $child_message:expr),*) => {
+        crate::error::Diagnostic::new($level, $message)
+            .at($line, $column)
+            .with_span($span)
+            .with_source($file_name, $src)
+            $(.with_child($child_level, $child_message))*
     };
 }
 
 #[macro_export]
 macro_rules! compile_error {
-    ($level:expr, $line:expr, $column:expr, $file_name:expr, $src:expr, $($arg:tt)*) => {
-        {
-            let mut error = crate::error::CompilerError::new();
-            error.add_message($level, $line, $column, &format!($($arg)*));
-            error.format_error_string($file_name, $src)
-        }
+    ($level:expr, $line:expr, $column:expr, $span:expr, $file_name:expr, $src:expr, $($arg:tt)*) => {
+        crate::error::Diagnostic::new($level, &format!($($arg)*))
+            .at($line, $column)
+            .with_span($span)
+            .with_source($file_name, $src)
     };
 }
 
+// 最初の範囲を主たる範囲にし、残りは副次的な範囲として下線を引く
 #[macro_export]
 macro_rules! compile_group_error {
-    ($level:expr, $file_name:expr, $src:expr, $message:expr, $($line:expr, $column:expr),*) => {
+    ($level:expr, $file_name:expr, $src:expr, $message:expr, $($line:expr, $column:expr, $span:expr),*) => {
         {
-            let mut compiler_error = crate::error::CompilerError::new();
-            compiler_error.add_group_message($level, vec![$(($line, $column)),*], $message);
-            compiler_error.format_error_string($file_name, $src)
+            let mut diagnostic = crate::error::Diagnostic::new($level, $message)
+                .with_source($file_name, $src);
+            $(
+                diagnostic = match diagnostic.span() {
+                    Some(_) => diagnostic.with_label($span, ""),
+                    None => diagnostic.with_span($span),
+                }
+                .at($line, $column);
+            )*
+            diagnostic
         }
     };
 }
@@ -1,2 +1,4 @@
 #[cfg(any(feature = "full", feature = "lexer"))]
 pub mod tokenizer;
+#[cfg(any(feature = "full", feature = "lexer"))]
+pub mod preprocess;
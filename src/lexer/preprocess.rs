@@ -0,0 +1,173 @@
+use crate::compile_error_with_children;
+use crate::error::Diagnostic;
+use crate::lexer::tokenizer::{Lexer, Token};
+use crate::source::SourceMap;
+use crate::types::{TokenType, R};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// `include "ファイル";`のキーワード
+pub const INCLUDE_KEYWORD: &str = "include";
+
+// 字句解析の後、構文解析の前に`include`を展開する
+// 読み込んだファイルはSourceMapに登録し、そのトークンは元のファイルのSpanと行・列を持ったまま、include文の位置に差し込まれる
+// パスはincludeを書いたファイルからの相対パスで、同じファイルは最初の1回だけ展開する
+pub struct Preprocessor {
+    included: HashSet<PathBuf>, // 展開済みのファイル(正規化したパス)
+    stack: Vec<PathBuf>,        // 展開中のファイル(循環の検出に使う)
+    source_map: SourceMap,      // 読み込んだファイルの登録先(エラー表示にも使う)
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Preprocessor::new(&SourceMap::new())
+    }
+}
+
+impl Preprocessor {
+    pub fn new(source_map: &SourceMap) -> Self {
+        Preprocessor {
+            included: HashSet::new(),
+            stack: Vec::new(),
+            source_map: source_map.clone(),
+        }
+    }
+
+    // inputのトークン列のincludeを再帰的に展開する
+    pub fn preprocess(&mut self, input: &Path, tokens: Vec<Token>) -> R<Vec<Token>, Diagnostic> {
+        let file = Preprocessor::canonicalize(input);
+        self.included.insert(file.clone());
+        self.stack.push(file);
+        let result = self.expand(input, tokens);
+        self.stack.pop();
+        result
+    }
+
+    // 展開したファイル(最初の入力を含む)
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.included.iter().cloned().collect();
+        files.sort();
+        files
+    }
+
+    fn expand(&mut self, input: &Path, tokens: Vec<Token>) -> R<Vec<Token>, Diagnostic> {
+        let mut expanded = Vec::with_capacity(tokens.len());
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            let is_include = token.token_type() == TokenType::Ident
+                && token.token_value() == INCLUDE_KEYWORD
+                && Preprocessor::is_statement_start(&expanded)
+                && tokens.peek().is_some_and(|next| {
+                    matches!(
+                        next.token_type(),
+                        TokenType::DoubleQuote | TokenType::SingleQuote
+                    )
+                });
+            if !is_include {
+                expanded.push(token);
+                continue;
+            }
+            let path = tokens
+                .next()
+                .map(|path| path.token_value())
+                .unwrap_or_default();
+            if tokens
+                .peek()
+                .is_some_and(|next| next.token_type() == TokenType::Semi)
+            {
+                tokens.next();
+            }
+            expanded.extend(self.include(input, &token, &path)?);
+        }
+        Ok(expanded)
+    }
+
+    // `include`(トークン)で指定されたファイルを読み込んで展開したトークン列(末尾のEofは除く)
+    fn include(&mut self, input: &Path, token: &Token, path: &str) -> R<Vec<Token>, Diagnostic> {
+        let dir = match input.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file = dir.join(path);
+        let canonical = Preprocessor::canonicalize(&file);
+        if let Some(start) = self.stack.iter().position(|entry| *entry == canonical) {
+            let cycle: Vec<String> = self.stack[start..]
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|entry| entry.display().to_string())
+                .collect();
+            return Err(self.error_with_note(
+                token,
+                &format!("Include cycle detected while including `{}`", path),
+                &format!("the include chain is {}", cycle.join(" -> ")),
+            ));
+        }
+        // 2回目以降のincludeは何も展開しない
+        if !self.included.insert(canonical.clone()) {
+            return Ok(Vec::new());
+        }
+
+        let input_path = file.to_string_lossy().to_string();
+        let input_content = fs::read_to_string(&file).map_err(|e| {
+            self.error_with_note(
+                token,
+                &format!("Cannot include `{}`: {}", path, e),
+                &format!(
+                    "include paths are relative to the including file, so this looked for `{}`",
+                    file.display()
+                ),
+            )
+        })?;
+        let mut lexer = Lexer::new_with_value(&input_path, input_content);
+        lexer.set_source_map(self.source_map.clone());
+        let mut tokens = lexer.tokenize()?;
+        tokens.retain(|token| token.token_type() != TokenType::Eof);
+
+        self.stack.push(canonical);
+        let result = self.expand(&file, tokens);
+        self.stack.pop();
+        result
+    }
+
+    // includeは文の先頭にだけ書ける(`include`という名前の変数などと区別する)
+    fn is_statement_start(previous: &[Token]) -> bool {
+        previous
+            .iter()
+            .rev()
+            .find(|token| {
+                !matches!(
+                    token.token_type(),
+                    TokenType::SingleComment(..) | TokenType::MultiComment(..)
+                )
+            })
+            .is_none_or(|token| {
+                matches!(
+                    token.token_type(),
+                    TokenType::Semi | TokenType::LeftCurlyBrace | TokenType::RightCurlyBrace
+                )
+            })
+    }
+
+    fn canonicalize(path: &Path) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    fn error_with_note(&self, token: &Token, message: &str, note: &str) -> Diagnostic {
+        let (input_path, input_content) = match self.source_map.file(token.span().file) {
+            Some(file) => (file.path.clone(), file.content.clone()),
+            None => (String::new(), String::new()),
+        };
+        compile_error_with_children!(
+            "error",
+            &input_path,
+            &input_content,
+            token.line(),
+            token.column(),
+            token.span(),
+            message,
+            "note",
+            note
+        )
+    }
+}
@@ -1,5 +1,6 @@
 use crate::compile_error;
-use crate::error::CompilerError;
+use crate::error::Diagnostic;
+use crate::source::{FileId, SourceMap, Span};
 use crate::types::TokenType;
 use anyhow::{anyhow, Context, Result as R};
 use log::{debug, error, info};
@@ -16,6 +17,8 @@ pub struct Token {
     line: usize,
     #[property(get)]
     column: usize,
+    #[property(get)]
+    span: Span, // ソース上の範囲(includeで展開されても元のファイルを指す)
 }
 
 impl Token {
@@ -25,6 +28,7 @@ impl Token {
             token_type,
             line,
             column,
+            span: Span::default(),
         }
     }
 }
@@ -45,6 +49,10 @@ pub struct Lexer {
     eof_line: usize,
     #[property(get)]
     eof_column: usize,
+    #[property(get, set)]
+    source_map: SourceMap, // 入力を登録するSourceMap(トークンのSpanはこの表のファイルを指す)
+    #[property(get)]
+    file_id: FileId, // 字句解析中の入力のファイル番号
 }
 
 impl Lexer {
@@ -57,6 +65,8 @@ impl Lexer {
             column: 1,
             eof_line: 1,
             eof_column: 1,
+            source_map: SourceMap::new(),
+            file_id: 0,
         }
     }
     pub fn new_with_value_vec(input_content_vec: Vec<String>) -> Self {
@@ -68,6 +78,8 @@ impl Lexer {
             column: 1,
             eof_line: 1,
             eof_column: 1,
+            source_map: SourceMap::new(),
+            file_id: 0,
         }
     }
 
@@ -80,6 +92,8 @@ impl Lexer {
             column: 1,
             eof_line: 1,
             eof_column: 1,
+            source_map: SourceMap::new(),
+            file_id: 0,
         }
     }
 
@@ -89,11 +103,24 @@ impl Lexer {
             '(' | ')' | ',' | '=' | ';' | '@' | '{' | '}' | '<' | '>' | ':' | '[' | ']' | '.'
         )
     }
-    fn tokenize_string(&mut self, input_content: &String) -> R<Vec<Token>, String> {
+    // エスケープシーケンス(`\`の次の文字)を実際の文字に変換する
+    fn unescape(c: char) -> char {
+        match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            _ => c, // \\, \", \' などはそのまま
+        }
+    }
+    fn tokenize_string(&mut self, input_content: &String) -> R<Vec<Token>, Diagnostic> {
         let mut tokens: Vec<Token> = Vec::new();
+        let mut ends: Vec<(usize, usize)> = Vec::new(); // 各トークンの終わりの(行, 列)
         let mut chars = input_content.chars().peekable();
 
         while let Some(&c) = chars.peek() {
+            // 前の繰り返しで追加したトークンは現在の位置で終わる
+            ends.resize(tokens.len(), (self.line, self.column));
             if c.is_whitespace() {
                 if c == '\r' {
                     chars.next();
@@ -160,6 +187,8 @@ impl Lexer {
                                 number.push('.');
                                 has_decimal_point = true;
                             } else if next_char == '.' {
+                                // 範囲演算子(..または..=)の直前の数値
+                                let range_column = self.column - 1;
                                 chars.next();
                                 self.column += 1;
                                 tokens.push(Token::new(
@@ -168,15 +197,27 @@ impl Lexer {
                                     start_line,
                                     start_column,
                                 ));
-                                tokens.push(Token::new(
-                                    "..".to_string(),
-                                    TokenType::Range,
-                                    start_line,
-                                    start_column,
-                                ));
+                                if chars.peek() == Some(&'=') {
+                                    chars.next();
+                                    self.column += 1;
+                                    tokens.push(Token::new(
+                                        "..=".to_string(),
+                                        TokenType::RangeInclusive,
+                                        start_line,
+                                        range_column,
+                                    ));
+                                } else {
+                                    tokens.push(Token::new(
+                                        "..".to_string(),
+                                        TokenType::Range,
+                                        start_line,
+                                        range_column,
+                                    ));
+                                }
                                 number.clear(); // 数字のリセット
                                 range_flag = true;
                                 dot_flag = false;
+                                break; // 終端側は次の字句として解析する
                             } else {
                                 tokens.push(Token::new(
                                     number.clone(),
@@ -248,7 +289,7 @@ impl Lexer {
                     if c == '\\' {
                         // エスケープシーケンスの処理
                         if let Some(next_char) = chars.next() {
-                            string.push(next_char);
+                            string.push(Lexer::unescape(next_char));
                             self.column += 2; // エスケープ文字と次の文字をスキップ
                             continue;
                         }
@@ -265,6 +306,7 @@ impl Lexer {
                         "error",
                         start_line,
                         start_column,
+                        self.span_at(start_line, start_column, 1),
                         &self.input_path.clone(),
                         &self.input_content.clone(),
                         "Single quote not closed",
@@ -285,7 +327,7 @@ impl Lexer {
                     if c == '\\' {
                         // エスケープシーケンスの処理
                         if let Some(next_char) = chars.next() {
-                            string.push(next_char);
+                            string.push(Lexer::unescape(next_char));
                             self.column += 2; // エスケープ文字と次の文字をスキップ
                             continue;
                         }
@@ -302,6 +344,7 @@ impl Lexer {
                         "error",
                         start_line,
                         start_column,
+                        self.span_at(start_line, start_column, 1),
                         &self.input_path.clone(),
                         &self.input_content.clone(),
                         "Double quote not closed",
@@ -384,6 +427,7 @@ impl Lexer {
                                 "error",
                                 start_line,
                                 start_column,
+                                self.span_at(start_line, start_column, 2),
                                 &self.input_path.clone(),
                                 &self.input_content.clone(),
                                 "Multi-line comment not closed",
@@ -612,8 +656,7 @@ impl Lexer {
                         ));
                         self.column += 1;
                         chars.next();
-                    }
-                    if next_char == '=' {
+                    } else if next_char == '=' {
                         tokens.push(Token::new(
                             "|=".to_string(),
                             TokenType::BitOrAssign,
@@ -851,6 +894,37 @@ impl Lexer {
                         ));
                     }
                 }
+            } else if c == '.' {
+                chars.next();
+                self.column += 1;
+                if chars.peek() == Some(&'.') {
+                    chars.next();
+                    self.column += 1;
+                    if chars.peek() == Some(&'=') {
+                        tokens.push(Token::new(
+                            "..=".to_string(),
+                            TokenType::RangeInclusive,
+                            start_line,
+                            start_column,
+                        ));
+                        self.column += 1;
+                        chars.next();
+                    } else {
+                        tokens.push(Token::new(
+                            "..".to_string(),
+                            TokenType::Range,
+                            start_line,
+                            start_column,
+                        ));
+                    }
+                } else {
+                    tokens.push(Token::new(
+                        ".".to_string(),
+                        TokenType::Dot,
+                        start_line,
+                        start_column,
+                    ));
+                }
             } else if self.is_symbol(c) {
                 let token_type = match c {
                     '(' => TokenType::LeftParen,
@@ -869,6 +943,7 @@ impl Lexer {
                             "error",
                             start_line,
                             start_column,
+                            self.span_at(start_line, start_column, c.len_utf8()),
                             &self.input_path.clone(),
                             &self.input_content.clone(),
                             "Unexpected symbol"
@@ -888,6 +963,7 @@ impl Lexer {
                     "error",
                     start_line,
                     start_column,
+                    self.span_at(start_line, start_column, c.len_utf8()),
                     &self.input_path.clone(),
                     &self.input_content.clone(),
                     "Unable to process input_content"
@@ -896,31 +972,59 @@ impl Lexer {
             self.eof_line = start_line;
             self.eof_column = start_column;
         }
+        ends.resize(tokens.len(), (self.line, self.column));
 
+        if let Some(file) = self.source_map.file(self.file_id) {
+            for (token, &(end_line, end_column)) in tokens.iter_mut().zip(&ends) {
+                token.span = Span::new(
+                    file.id,
+                    file.offset(token.line, token.column),
+                    file.offset(end_line, end_column),
+                );
+            }
+        }
         Ok(tokens)
     }
-    pub fn from_tokenize(input_path: &str, input_content: String) -> R<Vec<Token>, String> {
+    // (line, column)から始まるlengthバイトの範囲
+    fn span_at(&self, line: usize, column: usize, length: usize) -> Span {
+        match self.source_map.file(self.file_id) {
+            Some(file) => {
+                let start = file.offset(line, column);
+                Span::new(file.id, start, start + length)
+            }
+            None => Span::default(),
+        }
+    }
+    pub fn from_tokenize(input_path: &str, input_content: String) -> R<Vec<Token>, Diagnostic> {
         let mut lexer = Lexer::new();
         lexer.set_input_path(input_path.to_string());
         lexer.set_input_content(input_content);
         lexer.tokenize()
     }
-    pub fn tokenize(&mut self) -> R<Vec<Token>, String> {
+    pub fn tokenize(&mut self) -> R<Vec<Token>, Diagnostic> {
         let mut all_tokens: Vec<Token> = Vec::new();
 
-        if self.input_content_vec.is_empty() {
-            all_tokens.extend(self.tokenize_string(&self.input_content.clone())?);
+        let input_contents = if self.input_content_vec.is_empty() {
+            vec![self.input_content.clone()]
         } else {
-            for input_content in &self.input_content_vec.clone() {
-                all_tokens.extend(self.tokenize_string(input_content)?);
-            }
+            self.input_content_vec.clone()
+        };
+        for input_content in &input_contents {
+            // 入力ごとに別のファイルとして登録し、行と列を数え直す
+            self.file_id = self.source_map.add_file(&self.input_path, input_content);
+            self.line = 1;
+            self.column = 1;
+            all_tokens.extend(self.tokenize_string(input_content)?);
         }
-        all_tokens.push(Token::new(
+        let mut eof = Token::new(
             String::from(""),
             TokenType::Eof,
             self.eof_line(),
             self.eof_column(),
-        ));
+        );
+        let end = input_contents.last().map_or(0, |content| content.len());
+        eof.span = Span::new(self.file_id, end, end);
+        all_tokens.push(eof);
 
         Ok(all_tokens)
     }
@@ -2,5 +2,17 @@ pub mod decoder;
 pub mod traits;
 pub mod lexer;
 pub mod parser;
+pub mod checker;
 pub mod error;
+pub mod source;
 pub mod types;
+#[cfg(any(
+    feature = "full",
+    all(feature = "lexer", feature = "parser", feature = "decoder")
+))]
+pub mod driver;
+#[cfg(any(
+    feature = "full",
+    all(feature = "lexer", feature = "parser", feature = "decoder")
+))]
+pub mod repl;
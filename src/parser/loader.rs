@@ -0,0 +1,1285 @@
+use crate::compile_error;
+use crate::compile_error_with_children;
+use crate::error::Diagnostic;
+use crate::lexer::preprocess::Preprocessor;
+use crate::lexer::tokenizer::Lexer;
+use crate::parser::syntax::{Node, Parser};
+use crate::source::SourceMap;
+use crate::types::{
+    ControlFlow, DataType, Declaration, Expr, FunctionArg, NodeValue, Operator, Statement, R,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// ソースファイルの拡張子と、ディレクトリで定義するモジュールのファイル名
+pub const SOURCE_FILE_EXTENSION: &str = "tanu";
+pub const MODULE_DIRECTORY_FILE: &str = "mod.tanu";
+// モジュールのパスの区切り(修飾名にもそのまま使う)
+pub const PATH_SEPARATOR: &str = "::";
+
+// モジュールで定義された関数・構造体
+#[derive(Debug, Clone)]
+struct Item {
+    symbol: String, // プログラム全体で一意な修飾名
+    is_public: bool,
+}
+
+// 読み込んだモジュール(ファイルか`mod 名前 { .. }`の1つ分)
+#[derive(Debug)]
+struct Module {
+    path: Vec<String>, // ルートからのモジュールのパス(ルートは空)
+    dir: PathBuf,      // 子モジュールのファイルを探すディレクトリ
+    input_path: String,
+    input_content: String,
+    parent: Option<usize>,
+    children: HashMap<String, usize>,
+    items: Vec<Node>, // トップレベルの文(後続の文とのつながりを持たない)
+    functions: HashMap<String, Item>,
+    types: HashMap<String, Item>,
+    imported_functions: HashMap<String, String>, // useで取り込んだ名前 -> 修飾名
+    imported_types: HashMap<String, String>,
+    imported_modules: HashMap<String, usize>,
+}
+
+impl Module {
+    fn new(
+        path: Vec<String>,
+        dir: PathBuf,
+        input_path: String,
+        input_content: String,
+        parent: Option<usize>,
+        items: Vec<Node>,
+    ) -> Self {
+        Module {
+            path,
+            dir,
+            input_path,
+            input_content,
+            parent,
+            children: HashMap::new(),
+            items,
+            functions: HashMap::new(),
+            types: HashMap::new(),
+            imported_functions: HashMap::new(),
+            imported_types: HashMap::new(),
+            imported_modules: HashMap::new(),
+        }
+    }
+}
+
+// 関数定義の(引数, ボディ, 戻り値の型)
+type FunctionParts = (Vec<FunctionArg>, Box<Node>, Box<Node>);
+
+// `use`の対象
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Namespace {
+    Function,
+    Type,
+}
+
+// `mod 名前;`で参照されるファイルを読み込んでモジュールの木を作り、1つのプログラムにまとめる
+// ルート以外のモジュールの関数・構造体は`a::b::名前`の修飾名に変え、パスと`use`で取り込んだ名前も
+// 修飾名に置き換えるので、以降の名前解決・型検査・IR生成は1つのファイルと同じように扱える
+pub struct ModuleLoader {
+    modules: Vec<Module>,
+    files: HashMap<PathBuf, usize>, // 読み込み済みのファイル(正規化したパス) -> モジュール
+    errors: Vec<Diagnostic>,
+    source_map: SourceMap, // モジュールのファイルの登録先
+}
+
+impl Default for ModuleLoader {
+    fn default() -> Self {
+        ModuleLoader::new(&SourceMap::new())
+    }
+}
+
+impl ModuleLoader {
+    pub fn new(source_map: &SourceMap) -> Self {
+        ModuleLoader {
+            modules: Vec::new(),
+            files: HashMap::new(),
+            errors: Vec::new(),
+            source_map: source_map.clone(),
+        }
+    }
+
+    // ルートのファイル(解析済み)からモジュールを読み込み、全モジュールをつないだプログラムを返す
+    // エラーは全モジュール分をまとめて返す
+    pub fn load(
+        &mut self,
+        input: &Path,
+        input_path: &str,
+        input_content: String,
+        node: &Node,
+    ) -> R<Box<Node>, Vec<Diagnostic>> {
+        let dir = match input.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let root = self.add_module(Module::new(
+            Vec::new(),
+            dir,
+            input_path.to_string(),
+            input_content,
+            None,
+            ModuleLoader::detach_chain(node),
+        ));
+        if let Ok(file) = fs::canonicalize(input) {
+            self.files.insert(file, root);
+        }
+        self.load_children(root);
+        self.check_errors()?;
+
+        for module in 0..self.modules.len() {
+            self.declare_items(module);
+        }
+        self.check_errors()?;
+        for module in 0..self.modules.len() {
+            self.import_items(module);
+        }
+        self.check_errors()?;
+
+        // ルート以外のモジュールの定義を先に置き、ルートの文はそのままの順序で後に続ける
+        let mut statements = Vec::new();
+        for module in (1..self.modules.len()).chain(std::iter::once(root)) {
+            statements.extend(self.link_module(module));
+        }
+        self.check_errors()?;
+        ModuleLoader::link_chain(statements)
+            .ok_or_else(|| vec![Diagnostic::error("No statements found")])
+    }
+
+    // 修飾名(`a::b::名前`)
+    pub fn mangle(module_path: &[String], name: &str) -> String {
+        if module_path.is_empty() {
+            return name.to_string();
+        }
+        format!(
+            "{}{}{}",
+            module_path.join(PATH_SEPARATOR),
+            PATH_SEPARATOR,
+            name
+        )
+    }
+
+    // 読み込んだファイル(ルートを含む)
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.files.keys().cloned().collect();
+        files.sort();
+        files
+    }
+
+    fn add_module(&mut self, module: Module) -> usize {
+        self.modules.push(module);
+        self.modules.len() - 1
+    }
+
+    fn check_errors(&mut self) -> R<(), Vec<Diagnostic>> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn error(&mut self, module: usize, node: &Node, message: &str) {
+        let module = &self.modules[module];
+        let error = compile_error!(
+            "error",
+            node.line,
+            node.column,
+            node.span,
+            &module.input_path,
+            &module.input_content,
+            "{}",
+            message
+        );
+        self.errors.push(error);
+    }
+
+    fn error_with_help(&mut self, module: usize, node: &Node, message: &str, help: &str) {
+        let module = &self.modules[module];
+        let error = compile_error_with_children!(
+            "error",
+            &module.input_path,
+            &module.input_content,
+            node.line,
+            node.column,
+            node.span,
+            message,
+            "help",
+            help
+        );
+        self.errors.push(error);
+    }
+
+    // 表示用のモジュール名
+    fn module_name(&self, module: usize) -> String {
+        let path = &self.modules[module].path;
+        if path.is_empty() {
+            String::from("crate")
+        } else {
+            path.join(PATH_SEPARATOR)
+        }
+    }
+
+    // モジュール内の`mod`宣言から子モジュールを読み込む
+    fn load_children(&mut self, module: usize) {
+        let items = self.modules[module].items.clone();
+        for item in &items {
+            let child = match item.value {
+                NodeValue::Statement(Statement::Mod(ref name)) => {
+                    self.load_file(module, item, name)
+                }
+                NodeValue::Statement(Statement::ModDeclaration(ref name, ref statements)) => {
+                    let parent = &self.modules[module];
+                    let child = Module::new(
+                        [parent.path.clone(), vec![name.clone()]].concat(),
+                        parent.dir.join(name),
+                        parent.input_path.clone(),
+                        parent.input_content.clone(),
+                        Some(module),
+                        statements
+                            .iter()
+                            .flat_map(|statement| ModuleLoader::detach_chain(statement))
+                            .collect(),
+                    );
+                    Some((name.clone(), self.add_module(child)))
+                }
+                _ => None,
+            };
+            if let Some((name, child)) = child {
+                if self.modules[module].children.contains_key(&name) {
+                    let message = format!(
+                        "Module `{}` is defined multiple times in `{}`",
+                        name,
+                        self.module_name(module)
+                    );
+                    self.error(module, item, &message);
+                    continue;
+                }
+                self.modules[module].children.insert(name, child);
+                self.load_children(child);
+            }
+        }
+    }
+
+    // `mod 名前;`に対応する`名前.tanu`か`名前/mod.tanu`を読み込む(同じファイルは一度しか読まない)
+    fn load_file(&mut self, parent: usize, node: &Node, name: &str) -> Option<(String, usize)> {
+        let dir = self.modules[parent].dir.clone();
+        let candidates = [
+            dir.join(format!("{}.{}", name, SOURCE_FILE_EXTENSION)),
+            dir.join(name).join(MODULE_DIRECTORY_FILE),
+        ];
+        let found: Vec<&PathBuf> = candidates.iter().filter(|path| path.is_file()).collect();
+        let file = match found.as_slice() {
+            [file] => (*file).clone(),
+            [] => {
+                self.error_with_help(
+                    parent,
+                    node,
+                    &format!("File not found for module `{}`", name),
+                    &format!(
+                        "create `{}` or `{}`",
+                        candidates[0].display(),
+                        candidates[1].display()
+                    ),
+                );
+                return None;
+            }
+            _ => {
+                self.error_with_help(
+                    parent,
+                    node,
+                    &format!(
+                        "File for module `{}` found at both `{}` and `{}`",
+                        name,
+                        candidates[0].display(),
+                        candidates[1].display()
+                    ),
+                    "delete or rename one of them",
+                );
+                return None;
+            }
+        };
+
+        let canonical = fs::canonicalize(&file).unwrap_or_else(|_| file.clone());
+        if let Some(&loaded) = self.files.get(&canonical) {
+            let message = format!(
+                "`{}` is already loaded as module `{}`",
+                file.display(),
+                self.module_name(loaded)
+            );
+            self.error_with_help(
+                parent,
+                node,
+                &message,
+                "a file can only be declared as a module once; use `use` to refer to it from other modules",
+            );
+            return None;
+        }
+
+        let input_path = file.to_string_lossy().to_string();
+        let input_content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) => {
+                let message = format!("Failed to read `{}`: {}", input_path, e);
+                self.error(parent, node, &message);
+                return None;
+            }
+        };
+        let mut lexer = Lexer::new_with_value(&input_path, input_content.clone());
+        lexer.set_source_map(self.source_map.clone());
+        let items = match lexer
+            .tokenize()
+            .and_then(|tokens| Preprocessor::new(&self.source_map).preprocess(&file, tokens))
+            .and_then(|tokens| {
+                let mut parser = Parser::new(&tokens, &input_path, input_content.clone());
+                parser.set_source_map(&self.source_map);
+                parser.parse()
+            }) {
+            Ok(node) => ModuleLoader::detach_chain(&node),
+            Err(e) => {
+                self.errors.push(e);
+                return None;
+            }
+        };
+        let module = self.add_module(Module::new(
+            [self.modules[parent].path.clone(), vec![name.to_string()]].concat(),
+            dir.join(name),
+            input_path,
+            input_content,
+            Some(parent),
+            items,
+        ));
+        self.files.insert(canonical, module);
+        Some((name.to_string(), module))
+    }
+
+    // トップレベルの関数と構造体を登録する
+    fn declare_items(&mut self, module: usize) {
+        let items = self.modules[module].items.clone();
+        for item in &items {
+            let (namespace, name, is_public) = match item.value {
+                NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                    ref name,
+                    _,
+                    _,
+                    _,
+                    _,
+                    is_public,
+                ))) => (Namespace::Function, name, is_public),
+                NodeValue::Statement(Statement::Declaration(Declaration::Struct(
+                    ref name,
+                    _,
+                    is_public,
+                ))) => (Namespace::Type, name, is_public),
+                // ルートのトップレベルの文はmain関数の本体になる
+                _ if module == 0 => continue,
+                NodeValue::Statement(Statement::Declaration(Declaration::Impl(..)))
+                | NodeValue::Statement(Statement::Mod(..))
+                | NodeValue::Statement(Statement::ModDeclaration(..))
+                | NodeValue::Statement(Statement::Use(..))
+                | NodeValue::Expr(Expr::EndStatement)
+                | NodeValue::SingleComment(..)
+                | NodeValue::MultiComment(..) => continue,
+                _ => {
+                    let message = format!(
+                        "Only `fn`, `struct`, `impl`, `mod` and `use` items are allowed at the top level of module `{}`",
+                        self.module_name(module)
+                    );
+                    self.error(module, item, &message);
+                    continue;
+                }
+            };
+            let item_symbol = Item {
+                symbol: ModuleLoader::mangle(&self.modules[module].path, name),
+                is_public,
+            };
+            let declared = match namespace {
+                Namespace::Function => &mut self.modules[module].functions,
+                Namespace::Type => &mut self.modules[module].types,
+            };
+            if declared.insert(name.clone(), item_symbol).is_some() {
+                let message = format!(
+                    "`{}` is defined multiple times in module `{}`",
+                    name,
+                    self.module_name(module)
+                );
+                self.error(module, item, &message);
+            }
+        }
+    }
+
+    // `use`で取り込む名前を登録する
+    fn import_items(&mut self, module: usize) {
+        let items = self.modules[module].items.clone();
+        for item in &items {
+            if let NodeValue::Statement(Statement::Use(ref path)) = item.value {
+                self.import_path(module, &[], path);
+            }
+        }
+    }
+
+    // prefixは`{..}`の外側にあるパスの区切り
+    fn import_path(&mut self, module: usize, prefix: &[String], path: &Node) {
+        let segments = match path.value {
+            NodeValue::Expr(Expr::ScopeResolution(ref segments)) => segments.clone(),
+            _ => vec![Box::new(path.clone())],
+        };
+        let (last, heads) = match segments.split_last() {
+            Some(split) => split,
+            None => return,
+        };
+        let mut module_path = prefix.to_vec();
+        for segment in heads {
+            match ModuleLoader::segment_name(segment) {
+                Some(name) => module_path.push(name),
+                None => {
+                    self.error(
+                        module,
+                        segment,
+                        "Expected a module name in the path of `use`",
+                    );
+                    return;
+                }
+            }
+        }
+
+        match last.value {
+            NodeValue::Expr(Expr::Block(ref group)) => {
+                for entry in group {
+                    self.import_path(module, &module_path, entry);
+                }
+            }
+            NodeValue::Expr(Expr::DataType(DataType::String(ref glob))) if glob == "*" => {
+                let target = match self.resolve_module(module, &module_path, path) {
+                    Some(target) => target,
+                    None => return,
+                };
+                let accessible = self.is_accessible_from(module, target);
+                let functions: Vec<(String, Item)> = self.modules[target]
+                    .functions
+                    .iter()
+                    .filter(|(_, item)| item.is_public || accessible)
+                    .map(|(name, item)| (name.clone(), item.clone()))
+                    .collect();
+                let types: Vec<(String, Item)> = self.modules[target]
+                    .types
+                    .iter()
+                    .filter(|(_, item)| item.is_public || accessible)
+                    .map(|(name, item)| (name.clone(), item.clone()))
+                    .collect();
+                let children: Vec<(String, usize)> = self.modules[target]
+                    .children
+                    .iter()
+                    .map(|(name, child)| (name.clone(), *child))
+                    .collect();
+                for (name, item) in functions {
+                    self.import(module, last, Namespace::Function, &name, &item.symbol);
+                }
+                for (name, item) in types {
+                    self.import(module, last, Namespace::Type, &name, &item.symbol);
+                }
+                for (name, child) in children {
+                    self.modules[module].imported_modules.insert(name, child);
+                }
+            }
+            _ => {
+                let name = match ModuleLoader::segment_name(last) {
+                    Some(name) => name,
+                    None => {
+                        self.error(
+                            module,
+                            last,
+                            "Expected a name at the end of the path of `use`",
+                        );
+                        return;
+                    }
+                };
+                if module_path.is_empty() {
+                    // `use 名前;`はモジュールを取り込む
+                    if let Some(child) =
+                        self.resolve_module(module, std::slice::from_ref(&name), last)
+                    {
+                        self.modules[module].imported_modules.insert(name, child);
+                    }
+                    return;
+                }
+                let target = match self.resolve_module(module, &module_path, path) {
+                    Some(target) => target,
+                    None => return,
+                };
+                let mut is_found = false;
+                for namespace in [Namespace::Function, Namespace::Type] {
+                    if let Some(item) = self.find_item(module, target, namespace, &name, last) {
+                        self.import(module, last, namespace, &name, &item.symbol);
+                        is_found = true;
+                    }
+                }
+                if let Some(&child) = self.modules[target].children.get(&name) {
+                    self.modules[module]
+                        .imported_modules
+                        .insert(name.clone(), child);
+                    is_found = true;
+                }
+                if !is_found {
+                    let message = format!(
+                        "Cannot find `{}` in module `{}`",
+                        name,
+                        self.module_name(target)
+                    );
+                    self.error(module, last, &message);
+                }
+            }
+        }
+    }
+
+    fn import(
+        &mut self,
+        module: usize,
+        node: &Node,
+        namespace: Namespace,
+        name: &str,
+        symbol: &str,
+    ) {
+        let current = &mut self.modules[module];
+        let (declared, imported) = match namespace {
+            Namespace::Function => (&current.functions, &mut current.imported_functions),
+            Namespace::Type => (&current.types, &mut current.imported_types),
+        };
+        let is_conflict = declared.get(name).is_some_and(|item| item.symbol != symbol)
+            || imported
+                .get(name)
+                .is_some_and(|previous| previous != symbol);
+        if !is_conflict {
+            imported.insert(name.to_string(), symbol.to_string());
+            return;
+        }
+        let message = format!(
+            "`{}` is defined multiple times in module `{}`",
+            name,
+            self.module_name(module)
+        );
+        self.error_with_help(
+            module,
+            node,
+            &message,
+            "the imported name conflicts with another item or import of the same name",
+        );
+    }
+
+    fn segment_name(node: &Node) -> Option<String> {
+        match node.value {
+            NodeValue::Expr(Expr::Variable(_, ref name, ..)) => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    // moduleからtargetの非公開の定義を参照できるか(同じモジュールかその子孫のモジュールなら参照できる)
+    fn is_accessible_from(&self, module: usize, target: usize) -> bool {
+        let mut current = Some(module);
+        while let Some(index) = current {
+            if index == target {
+                return true;
+            }
+            current = self.modules[index].parent;
+        }
+        false
+    }
+
+    // moduleから見たモジュールのパスを解決する
+    // 先頭は`crate`(ルート)・`self`・`super`・子モジュール・`use`で取り込んだモジュール・ルート直下のモジュールの順に探す
+    fn resolve_module(&mut self, module: usize, path: &[String], node: &Node) -> Option<usize> {
+        let mut current = module;
+        for (i, segment) in path.iter().enumerate() {
+            let next = match segment.as_str() {
+                "crate" if i == 0 => Some(0),
+                "self" if i == 0 => Some(module),
+                "super" => self.modules[current].parent,
+                _ if i == 0 => self.modules[module]
+                    .children
+                    .get(segment)
+                    .or_else(|| self.modules[module].imported_modules.get(segment))
+                    .or_else(|| self.modules[0].children.get(segment))
+                    .copied(),
+                _ => self.modules[current].children.get(segment).copied(),
+            };
+            current = match next {
+                Some(next) => next,
+                None if segment == "super" => {
+                    self.error(
+                        module,
+                        node,
+                        "There are too many `super` segments: the root module has no parent",
+                    );
+                    return None;
+                }
+                None if i == 0 => {
+                    let message = format!("Cannot find module `{}` in this scope", segment);
+                    self.error(module, node, &message);
+                    return None;
+                }
+                None => {
+                    let message = format!(
+                        "Cannot find module `{}` in `{}`",
+                        segment,
+                        self.module_name(current)
+                    );
+                    self.error(module, node, &message);
+                    return None;
+                }
+            };
+        }
+        Some(current)
+    }
+
+    // targetの定義を探し、moduleから参照できなければエラーにする
+    fn find_item(
+        &mut self,
+        module: usize,
+        target: usize,
+        namespace: Namespace,
+        name: &str,
+        node: &Node,
+    ) -> Option<Item> {
+        let item = match namespace {
+            Namespace::Function => self.modules[target].functions.get(name),
+            Namespace::Type => self.modules[target].types.get(name),
+        }
+        .cloned()?;
+        if !item.is_public && !self.is_accessible_from(module, target) {
+            let kind = match namespace {
+                Namespace::Function => "Function",
+                Namespace::Type => "Struct",
+            };
+            let message = format!("{} `{}` is private", kind, item.symbol);
+            self.error_with_help(
+                module,
+                node,
+                &message,
+                &format!(
+                    "declare it with `pub` in module `{}` to use it from other modules",
+                    self.module_name(target)
+                ),
+            );
+        }
+        Some(item)
+    }
+
+    // パス(`a::b::名前`)を解決して修飾名を返す
+    fn resolve_path(
+        &mut self,
+        module: usize,
+        node: &Node,
+        segments: &[Box<Node>],
+        namespace: Namespace,
+    ) -> Option<String> {
+        let (last, heads) = segments.split_last()?;
+        let mut module_path = Vec::new();
+        for segment in heads {
+            match ModuleLoader::segment_name(segment) {
+                Some(name) => module_path.push(name),
+                None => {
+                    self.error(module, segment, "Expected a module name in the path");
+                    return None;
+                }
+            }
+        }
+        let name = match last.value {
+            NodeValue::Expr(Expr::Call(ref name, ..))
+            | NodeValue::Expr(Expr::StructInstance(ref name, _)) => name.clone(),
+            _ => return None,
+        };
+        let target = self.resolve_module(module, &module_path, node)?;
+        match self.find_item(module, target, namespace, &name, node) {
+            Some(item) => Some(item.symbol),
+            None => {
+                let kind = match namespace {
+                    Namespace::Function => "function",
+                    Namespace::Type => "struct",
+                };
+                let message = format!(
+                    "Cannot find {} `{}` in module `{}`",
+                    kind,
+                    name,
+                    self.module_name(target)
+                );
+                self.error(module, node, &message);
+                None
+            }
+        }
+    }
+
+    // moduleの中から見た関数名・型名の修飾名
+    // モジュールで定義も`use`もされていない名前は組み込みの関数・型としてそのまま残す
+    // ルートの定義は修飾名を持たないので、子モジュールから`use`なしで参照していればエラーにする
+    fn lookup(&mut self, module: usize, node: &Node, namespace: Namespace, name: &str) -> String {
+        let current = &self.modules[module];
+        let (declared, imported) = match namespace {
+            Namespace::Function => (&current.functions, &current.imported_functions),
+            Namespace::Type => (&current.types, &current.imported_types),
+        };
+        if let Some(symbol) = declared
+            .get(name)
+            .map(|item| item.symbol.clone())
+            .or_else(|| imported.get(name).cloned())
+        {
+            return symbol;
+        }
+        let root = &self.modules[0];
+        let (kind, is_root_item) = match namespace {
+            Namespace::Function => ("function", root.functions.contains_key(name)),
+            Namespace::Type => ("struct", root.types.contains_key(name)),
+        };
+        if module != 0 && is_root_item {
+            let message = format!(
+                "Cannot find {} `{}` in module `{}`",
+                kind,
+                name,
+                self.module_name(module)
+            );
+            self.error_with_help(
+                module,
+                node,
+                &message,
+                &format!("import it from the root module with `use crate::{};`", name),
+            );
+        }
+        name.to_string()
+    }
+
+    // モジュールのトップレベルの文を修飾名に置き換える(mod宣言とuse宣言は取り除く)
+    fn link_module(&mut self, module: usize) -> Vec<Node> {
+        let items = self.modules[module].items.clone();
+        let mut statements = Vec::new();
+        for item in &items {
+            let value = match item.value {
+                NodeValue::Statement(Statement::Mod(..))
+                | NodeValue::Statement(Statement::ModDeclaration(..))
+                | NodeValue::Statement(Statement::Use(..)) => continue,
+                NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                    ref name,
+                    ref args,
+                    ref body,
+                    ref return_type,
+                    is_system,
+                    is_public,
+                ))) => {
+                    let (args, body, return_type) =
+                        self.rewrite_function(module, args, body, return_type);
+                    NodeValue::Statement(Statement::Declaration(Declaration::Function(
+                        self.lookup(module, item, Namespace::Function, name),
+                        args,
+                        body,
+                        return_type,
+                        is_system,
+                        is_public,
+                    )))
+                }
+                NodeValue::Statement(Statement::Declaration(Declaration::Struct(
+                    ref name,
+                    ref members,
+                    is_public,
+                ))) => NodeValue::Statement(Statement::Declaration(Declaration::Struct(
+                    self.lookup(module, item, Namespace::Type, name),
+                    members
+                        .iter()
+                        .map(|member| self.rewrite_member(module, member))
+                        .collect(),
+                    is_public,
+                ))),
+                _ => self.rewrite(module, item).value,
+            };
+            let mut statement = item.clone();
+            statement.value = value;
+            statements.push(statement);
+        }
+        statements
+    }
+
+    // 構文木の関数名・型名・パスを修飾名に置き換えたコピーを作る
+    fn rewrite(&mut self, module: usize, node: &Node) -> Node {
+        let value = match node.value {
+            NodeValue::Expr(ref expr) => self.rewrite_expr(module, node, expr),
+            NodeValue::Statement(ref statement) => {
+                NodeValue::Statement(self.rewrite_statement(module, node, statement))
+            }
+            NodeValue::SingleComment(..) | NodeValue::MultiComment(..) => node.value.clone(),
+        };
+        let next = node
+            .next
+            .borrow()
+            .as_ref()
+            .map(|next| Box::new(self.rewrite(module, next)));
+        Node {
+            value,
+            next: Rc::new(RefCell::new(next)),
+            line: node.line,
+            column: node.column,
+            is_statement: node.is_statement,
+            span: node.span,
+        }
+    }
+
+    fn rewrite_box(&mut self, module: usize, node: &Node) -> Box<Node> {
+        Box::new(self.rewrite(module, node))
+    }
+
+    // 型注釈のノード
+    fn rewrite_type(&mut self, module: usize, node: &Node) -> Box<Node> {
+        let value = match node.value {
+            NodeValue::Expr(Expr::DataType(DataType::String(ref name))) => {
+                let name = self.lookup(module, node, Namespace::Type, name);
+                NodeValue::Expr(Expr::DataType(DataType::String(name)))
+            }
+            NodeValue::Expr(Expr::DataType(DataType::FixedArray(ref element, length))) => {
+                NodeValue::Expr(Expr::DataType(DataType::FixedArray(
+                    self.rewrite_type(module, element),
+                    length,
+                )))
+            }
+            NodeValue::Expr(Expr::Variable(
+                ref data_type,
+                ref name,
+                is_mutable,
+                is_reference,
+                ref generics,
+            )) => NodeValue::Expr(Expr::Variable(
+                self.rewrite_type(module, data_type),
+                self.lookup(module, node, Namespace::Type, name),
+                is_mutable,
+                is_reference,
+                generics.clone(),
+            )),
+            _ => return Box::new(node.clone()),
+        };
+        let mut rewritten = node.clone();
+        rewritten.value = value;
+        Box::new(rewritten)
+    }
+
+    fn rewrite_function(
+        &mut self,
+        module: usize,
+        args: &[FunctionArg],
+        body: &Node,
+        return_type: &Node,
+    ) -> FunctionParts {
+        let args = args
+            .iter()
+            .map(|(arg_type, name, span)| {
+                (self.rewrite_type(module, arg_type), name.clone(), *span)
+            })
+            .collect();
+        (
+            args,
+            self.rewrite_box(module, body),
+            self.rewrite_type(module, return_type),
+        )
+    }
+
+    // 構造体のフィールド(フィールドの型だけを置き換える)
+    fn rewrite_member(&mut self, module: usize, member: &Node) -> Box<Node> {
+        let mut rewritten = member.clone();
+        if let NodeValue::Expr(Expr::Variable(
+            ref field_type,
+            ref name,
+            is_mutable,
+            is_reference,
+            ref generics,
+        )) = member.value
+        {
+            rewritten.value = NodeValue::Expr(Expr::Variable(
+                self.rewrite_type(module, field_type),
+                name.clone(),
+                is_mutable,
+                is_reference,
+                generics.clone(),
+            ));
+        }
+        Box::new(rewritten)
+    }
+
+    fn rewrite_expr(&mut self, module: usize, node: &Node, expr: &Expr) -> NodeValue {
+        let expr = match *expr {
+            Expr::DataType(DataType::Array(ref data_type, ref values)) => {
+                Expr::DataType(DataType::Array(
+                    self.rewrite_type(module, data_type),
+                    values
+                        .iter()
+                        .map(|node| self.rewrite_box(module, node))
+                        .collect(),
+                ))
+            }
+            Expr::DataType(_) | Expr::Use(_) | Expr::EndStatement => expr.clone(),
+            Expr::Operator(ref op) => Expr::Operator(self.rewrite_operator(module, op)),
+            Expr::Assign(ref target, ref value, ref index) => Expr::Assign(
+                self.rewrite_box(module, target),
+                self.rewrite_box(module, value),
+                self.rewrite_box(module, index),
+            ),
+            Expr::Block(ref statements) => Expr::Block(
+                statements
+                    .iter()
+                    .map(|node| self.rewrite_box(module, node))
+                    .collect(),
+            ),
+            // 変数名はそのままで、型注釈だけを置き換える
+            Expr::Variable(ref data_type, ref name, is_mutable, is_reference, ref generics) => {
+                Expr::Variable(
+                    self.rewrite_type(module, data_type),
+                    name.clone(),
+                    is_mutable,
+                    is_reference,
+                    generics.clone(),
+                )
+            }
+            Expr::Call(ref name, ref args, is_system) => {
+                let name = if is_system {
+                    name.clone()
+                } else {
+                    self.lookup(module, node, Namespace::Function, name)
+                };
+                Expr::Call(name, self.rewrite_args(module, args), is_system)
+            }
+            Expr::StructInstance(ref name, ref fields) => Expr::StructInstance(
+                self.lookup(module, node, Namespace::Type, name),
+                self.rewrite_fields(module, fields),
+            ),
+            Expr::Index(ref base, ref index) => Expr::Index(
+                self.rewrite_box(module, base),
+                self.rewrite_box(module, index),
+            ),
+            // メソッド名は型で決まるので置き換えない
+            Expr::MemberAccess(ref base, ref member) => {
+                let member = match member.value {
+                    NodeValue::Expr(Expr::Call(ref name, ref args, is_system)) => {
+                        let mut rewritten = (**member).clone();
+                        rewritten.value = NodeValue::Expr(Expr::Call(
+                            name.clone(),
+                            self.rewrite_args(module, args),
+                            is_system,
+                        ));
+                        Box::new(rewritten)
+                    }
+                    _ => member.clone(),
+                };
+                Expr::MemberAccess(self.rewrite_box(module, base), member)
+            }
+            Expr::ScopeResolution(ref segments) => {
+                return self.rewrite_path(module, node, segments);
+            }
+        };
+        NodeValue::Expr(expr)
+    }
+
+    // パスの末尾の関数呼び出し・構造体インスタンスを修飾名で置き換える
+    fn rewrite_path(&mut self, module: usize, node: &Node, segments: &[Box<Node>]) -> NodeValue {
+        let last = match segments.last() {
+            Some(last) => last,
+            None => return node.value.clone(),
+        };
+        let expr = match last.value {
+            NodeValue::Expr(Expr::Call(_, ref args, _)) => {
+                match self.resolve_path(module, node, segments, Namespace::Function) {
+                    Some(symbol) => Expr::Call(symbol, self.rewrite_args(module, args), false),
+                    None => return node.value.clone(),
+                }
+            }
+            NodeValue::Expr(Expr::StructInstance(_, ref fields)) => {
+                match self.resolve_path(module, node, segments, Namespace::Type) {
+                    Some(symbol) => {
+                        Expr::StructInstance(symbol, self.rewrite_fields(module, fields))
+                    }
+                    None => return node.value.clone(),
+                }
+            }
+            _ => {
+                self.error(
+                    module,
+                    node,
+                    "Expected a function call or a struct literal at the end of the path",
+                );
+                return node.value.clone();
+            }
+        };
+        NodeValue::Expr(expr)
+    }
+
+    fn rewrite_args(&mut self, module: usize, args: &[Node]) -> Vec<Node> {
+        args.iter().map(|arg| self.rewrite(module, arg)).collect()
+    }
+
+    fn rewrite_fields(
+        &mut self,
+        module: usize,
+        fields: &[(String, Box<Node>)],
+    ) -> Vec<(String, Box<Node>)> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.clone(), self.rewrite_box(module, value)))
+            .collect()
+    }
+
+    fn rewrite_operator(&mut self, module: usize, op: &Operator) -> Operator {
+        match *op {
+            Operator::Increment(ref operand, is_prefix) => {
+                Operator::Increment(self.rewrite_box(module, operand), is_prefix)
+            }
+            Operator::Decrement(ref operand, is_prefix) => {
+                Operator::Decrement(self.rewrite_box(module, operand), is_prefix)
+            }
+            Operator::BitNot(ref operand) => Operator::BitNot(self.rewrite_box(module, operand)),
+            Operator::Range(ref start, ref end, ref step, is_inclusive) => Operator::Range(
+                self.rewrite_box(module, start),
+                self.rewrite_box(module, end),
+                self.rewrite_box(module, step),
+                is_inclusive,
+            ),
+            Operator::Eq(ref l, ref r) => {
+                Operator::Eq(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Ne(ref l, ref r) => {
+                Operator::Ne(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Lt(ref l, ref r) => {
+                Operator::Lt(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Gt(ref l, ref r) => {
+                Operator::Gt(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Le(ref l, ref r) => {
+                Operator::Le(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Ge(ref l, ref r) => {
+                Operator::Ge(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::And(ref l, ref r) => {
+                Operator::And(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Or(ref l, ref r) => {
+                Operator::Or(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Add(ref l, ref r) => {
+                Operator::Add(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Sub(ref l, ref r) => {
+                Operator::Sub(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Mul(ref l, ref r) => {
+                Operator::Mul(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Div(ref l, ref r) => {
+                Operator::Div(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::Modulus(ref l, ref r) => {
+                Operator::Modulus(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::AddAssign(ref l, ref r) => {
+                Operator::AddAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::SubAssign(ref l, ref r) => {
+                Operator::SubAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::MulAssign(ref l, ref r) => {
+                Operator::MulAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::DivAssign(ref l, ref r) => {
+                Operator::DivAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::ModulusAssign(ref l, ref r) => {
+                Operator::ModulusAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::BitAnd(ref l, ref r) => {
+                Operator::BitAnd(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::BitOr(ref l, ref r) => {
+                Operator::BitOr(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::BitXor(ref l, ref r) => {
+                Operator::BitXor(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::ShiftLeft(ref l, ref r) => {
+                Operator::ShiftLeft(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::ShiftRight(ref l, ref r) => {
+                Operator::ShiftRight(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::BitAndAssign(ref l, ref r) => {
+                Operator::BitAndAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::BitOrAssign(ref l, ref r) => {
+                Operator::BitOrAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::BitXorAssign(ref l, ref r) => {
+                Operator::BitXorAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::ShiftLeftAssign(ref l, ref r) => {
+                Operator::ShiftLeftAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+            Operator::ShiftRightAssign(ref l, ref r) => {
+                Operator::ShiftRightAssign(self.rewrite_box(module, l), self.rewrite_box(module, r))
+            }
+        }
+    }
+
+    fn rewrite_statement(
+        &mut self,
+        module: usize,
+        node: &Node,
+        statement: &Statement,
+    ) -> Statement {
+        match *statement {
+            Statement::ControlFlow(ref control_flow) => {
+                Statement::ControlFlow(match *control_flow {
+                    ControlFlow::If(ref condition, ref body) => ControlFlow::If(
+                        self.rewrite_box(module, condition),
+                        self.rewrite_box(module, body),
+                    ),
+                    ControlFlow::ElseIf(ref condition, ref body) => ControlFlow::ElseIf(
+                        self.rewrite_box(module, condition),
+                        self.rewrite_box(module, body),
+                    ),
+                    ControlFlow::While(ref condition, ref body) => ControlFlow::While(
+                        self.rewrite_box(module, condition),
+                        self.rewrite_box(module, body),
+                    ),
+                    ControlFlow::Else(ref body) => {
+                        ControlFlow::Else(self.rewrite_box(module, body))
+                    }
+                    ControlFlow::Loop(ref body) => {
+                        ControlFlow::Loop(self.rewrite_box(module, body))
+                    }
+                    ControlFlow::For(ref var, ref iterator, ref body) => ControlFlow::For(
+                        self.rewrite_box(module, var),
+                        self.rewrite_box(module, iterator),
+                        self.rewrite_box(module, body),
+                    ),
+                    ControlFlow::Return(ref value) => {
+                        ControlFlow::Return(self.rewrite_box(module, value))
+                    }
+                    ControlFlow::Break | ControlFlow::Continue => control_flow.clone(),
+                })
+            }
+            Statement::Declaration(ref declaration) => {
+                Statement::Declaration(self.rewrite_declaration(module, node, declaration))
+            }
+            Statement::Mod(_) | Statement::ModDeclaration(..) | Statement::Use(_) => {
+                self.error(
+                    module,
+                    node,
+                    "`mod` and `use` declarations are only allowed at the top level of a module",
+                );
+                statement.clone()
+            }
+        }
+    }
+
+    // ブロック内の定義(関数名と構造体名はそのまま)
+    fn rewrite_declaration(
+        &mut self,
+        module: usize,
+        node: &Node,
+        declaration: &Declaration,
+    ) -> Declaration {
+        match *declaration {
+            Declaration::Variable(
+                ref var,
+                ref data_type,
+                ref value,
+                is_mutable,
+                is_reference,
+                is_public,
+            ) => Declaration::Variable(
+                self.rewrite_box(module, var),
+                self.rewrite_type(module, data_type),
+                self.rewrite_box(module, value),
+                is_mutable,
+                is_reference,
+                is_public,
+            ),
+            Declaration::Const(ref var, ref data_type, ref value, is_mutable, is_public) => {
+                Declaration::Const(
+                    self.rewrite_box(module, var),
+                    self.rewrite_type(module, data_type),
+                    self.rewrite_box(module, value),
+                    is_mutable,
+                    is_public,
+                )
+            }
+            Declaration::Struct(ref name, ref members, is_public) => Declaration::Struct(
+                name.clone(),
+                members
+                    .iter()
+                    .map(|member| self.rewrite_member(module, member))
+                    .collect(),
+                is_public,
+            ),
+            Declaration::Impl(ref type_name, ref members) => Declaration::Impl(
+                self.lookup(module, node, Namespace::Type, type_name),
+                members
+                    .iter()
+                    .map(|node| self.rewrite_box(module, node))
+                    .collect(),
+            ),
+            Declaration::Function(
+                ref name,
+                ref args,
+                ref body,
+                ref return_type,
+                is_system,
+                is_public,
+            ) => {
+                let (args, body, return_type) =
+                    self.rewrite_function(module, args, body, return_type);
+                Declaration::Function(name.clone(), args, body, return_type, is_system, is_public)
+            }
+            Declaration::CallBackFunction(
+                ref name,
+                ref args,
+                ref body,
+                ref return_type,
+                is_system,
+            ) => {
+                let (args, body, return_type) =
+                    self.rewrite_function(module, args, body, return_type);
+                Declaration::CallBackFunction(name.clone(), args, body, return_type, is_system)
+            }
+            Declaration::Type(ref name, ref data_type, is_public) => Declaration::Type(
+                name.clone(),
+                self.rewrite_type(module, data_type),
+                is_public,
+            ),
+            Declaration::UserSyntax(..) => declaration.clone(),
+        }
+    }
+
+    // 文のチェーンを、後続の文とのつながりを持たない文の並びにする
+    fn detach_chain(node: &Node) -> Vec<Node> {
+        node.iter()
+            .map(|statement| {
+                let statement = statement.borrow();
+                let mut detached = Node::new(
+                    statement.value.clone(),
+                    None,
+                    statement.line,
+                    statement.column,
+                );
+                detached.is_statement = statement.is_statement;
+                detached.span = statement.span;
+                detached
+            })
+            .collect()
+    }
+
+    fn link_chain(statements: Vec<Node>) -> Option<Box<Node>> {
+        statements.into_iter().rev().fold(None, |next, statement| {
+            *statement.next.borrow_mut() = next;
+            Some(Box::new(statement))
+        })
+    }
+}
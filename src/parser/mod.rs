@@ -1,2 +1,4 @@
 #[cfg(any(feature = "full", feature = "parser"))]
 pub mod syntax;
+#[cfg(any(feature = "full", all(feature = "lexer", feature = "parser")))]
+pub mod loader;
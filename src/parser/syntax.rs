@@ -1,8 +1,12 @@
 use crate::compile_error;
 use crate::error::*;
 use crate::lexer::tokenizer::Token;
+use crate::source::{SourceMap, Span};
 use crate::traits::*;
-use crate::types::{TokenType,NodeValue,Expr,Statement,DataType,ControlFlow,Operator,Declaration,Keywords};
+use crate::types::{
+    ControlFlow, DataType, Declaration, Expr, FunctionArg, Keywords, NodeValue, Operator,
+    Statement, TokenType,
+};
 use anyhow::{anyhow, Context, Result as R};
 use log::{debug, error, info};
 use property_rs::Property;
@@ -20,6 +24,8 @@ pub struct Node {
     pub column: usize,
     #[property(get)]
     pub is_statement: bool,
+    #[property(get)]
+    pub span: Span, // ノードのソース上の範囲(先頭のトークンから最後のトークンまで)
 }
 
 pub struct NodeIter {
@@ -101,6 +107,7 @@ impl Default for Node {
             line: 0,
             column: 0,
             is_statement: false,
+            span: Span::default(),
         }
     }
 }
@@ -112,6 +119,7 @@ impl Node {
             line,
             column,
             is_statement: false,
+            span: Span::default(),
         }
     }
 
@@ -130,6 +138,8 @@ pub struct Parser<'a> {
     tokens: &'a Vec<Token>,
     i: usize,
     is_statement: bool,
+    is_condition: bool, // 条件式の解析中は`ident {`を構造体インスタンスとして扱わない
+    source_map: SourceMap, // トークンのSpanが指すファイル(includeやモジュールのファイルのエラー表示に使う)
 }
 
 impl<'a> Parser<'a> {
@@ -140,13 +150,77 @@ impl<'a> Parser<'a> {
             input_path: input_path.to_string(),
             input_content,
             is_statement: false,
+            is_condition: false,
+            source_map: SourceMap::new(),
         }
     }
+    pub fn set_source_map(&mut self, source_map: &SourceMap) {
+        self.source_map = source_map.clone();
+    }
+    // 現在のトークンのファイルの内容(SourceMapにない場合は入力の内容)
     pub fn input_content(&self) -> String {
-        self.input_content.clone()
+        self.current_token()
+            .and_then(|token| self.source_map.file(token.span().file))
+            .map_or_else(|| self.input_content.clone(), |file| file.content.clone())
     }
     pub fn input_path(&self) -> String {
-        self.input_path.clone()
+        self.current_token()
+            .and_then(|token| self.source_map.file(token.span().file))
+            .map_or_else(|| self.input_path.clone(), |file| file.path.clone())
+    }
+    // start番目のトークンから直前に読んだトークンまでの範囲
+    fn span_from(&self, start: usize) -> Span {
+        let first = match self.tokens.get(start) {
+            Some(token) => token.span(),
+            None => return Span::default(),
+        };
+        match self.i.checked_sub(1).and_then(|last| self.tokens.get(last)) {
+            Some(last) if self.i > start => first.to(last.span()),
+            _ => first,
+        }
+    }
+    // 解析したノードの位置をstart番目のトークン(構文の先頭)からの範囲にする
+    fn with_span(&self, start: usize, mut node: Box<Node>) -> Box<Node> {
+        if let Some(token) = self.tokens.get(start) {
+            node.line = token.line();
+            node.column = token.column();
+        }
+        node.span = self.span_from(start);
+        node
+    }
+    // start番目のトークンから直前に読んだトークンまでを範囲とするノード
+    fn new_node(&self, start: usize, value: NodeValue, next: Option<Box<Node>>) -> Box<Node> {
+        self.with_span(start, Box::new(Node::new(value, next, 0, 0)))
+    }
+    fn new_statement(&self, start: usize, value: NodeValue, is_statement: bool) -> Box<Node> {
+        let mut node = self.new_node(start, value, None);
+        node.is_statement = is_statement;
+        node
+    }
+    // index番目のトークン(宣言や代入の対象の名前)を指す型なしの変数ノード
+    fn new_name_node(&self, index: usize, name: String) -> Box<Node> {
+        let token = &self.tokens[index];
+        let mut node = Node::new(
+            NodeValue::Expr(Expr::Variable(
+                Parser::<'a>::new_null(token.line(), token.column()),
+                name,
+                false,
+                false,
+                None,
+            )),
+            None,
+            token.line(),
+            token.column(),
+        );
+        node.span = token.span();
+        Box::new(node)
+    }
+    // start番目から読んだ引数の中の引数名のトークンの範囲(`mut`や`&`の後にある)
+    fn arg_name_span(&self, start: usize, name: &str) -> Span {
+        self.tokens[start..self.i]
+            .iter()
+            .find(|token| token.token_type() == TokenType::Ident && token.token_value() == name)
+            .map_or(Span::default(), |token| token.span())
     }
     pub fn new_add(left: Box<Node>, right: Box<Node>, line: usize, column: usize) -> Box<Node> {
         let node = Node::new(
@@ -254,19 +328,9 @@ impl<'a> Parser<'a> {
         Box::new(node)
     }
 
-    pub fn new_include(include_file: String, line: usize, column: usize) -> Box<Node> {
-        let node = Node::new(
-            NodeValue::Statement(Statement::Include(include_file)),
-            None,
-            line,
-            column,
-        );
-        Box::new(node)
-    }
-
     pub fn new_function(
         func_name: String,
-        args: Vec<(Box<Node>, String)>,
+        args: Vec<FunctionArg>,
         body: Box<Node>,
         return_type: Box<Node>,
         is_system: bool,
@@ -355,11 +419,17 @@ impl<'a> Parser<'a> {
         tokens: &Vec<Token>,
         input_path: &str,
         input_content: String,
-    ) -> R<Box<Node>, String> {
+    ) -> R<Box<Node>, Diagnostic> {
         let mut parser = Parser::new(tokens, input_path, input_content);
         parser.parse()
     }
 
+    // 全てのトークンを読み終えたか(REPLで1文ずつ解析するときに使う)
+    pub fn is_eof(&self) -> bool {
+        self.current_token()
+            .is_none_or(|token| token.token_type() == TokenType::Eof)
+    }
+
     fn current_token(&self) -> Option<&Token> {
         self.tokens.get(self.i)
     }
@@ -371,6 +441,20 @@ impl<'a> Parser<'a> {
             None
         }
     }
+    // 現在の`<`から`Ident (, Ident)* >`が続く場合のみジェネリクス引数として扱う
+    // (`x < 1`のような比較式と区別する)
+    fn is_generic_argument_list(&self) -> bool {
+        let mut expect_ident = true;
+        for token in self.tokens.iter().skip(self.i + 1) {
+            match (token.token_type(), expect_ident) {
+                (TokenType::Ident, true) => expect_ident = false,
+                (TokenType::Conma, false) => expect_ident = true,
+                (TokenType::Gt, false) => return true,
+                _ => return false,
+            }
+        }
+        false
+    }
     fn previous_token(&mut self, i: usize) -> Option<Token> {
         if (self.i - 1) < self.tokens.len() {
             Some(self.tokens[self.i - i].clone())
@@ -383,34 +467,161 @@ impl<'a> Parser<'a> {
         self.i += 1;
     }
 
-    fn term(&mut self) -> R<Box<Node>, String> {
-        let mut node = self.factor()?;
-        while matches!(
-            self.current_token().unwrap().token_type(),
-            TokenType::Mul
-                | TokenType::Div
-                | TokenType::Modulus
+    // 代入式(右結合): `p.x = 1`, `x += 1`, `x <<= 2` など
+    fn parse_assignment_expr(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
+        let node = self.expr()?;
+        let op = self.current_token().unwrap().clone();
+        // メンバへの代入: `p.x = 1`
+        if op.token_type() == TokenType::Equals {
+            self.check_assignable(&node, &op)?;
+            self.next_token();
+            let rhs = self.parse_assignment_expr()?;
+            if self.current_token().unwrap().token_type() == TokenType::Semi {
+                self.is_statement = true;
+            }
+            return Ok(self.new_node(
+                start,
+                NodeValue::Expr(Expr::Assign(
+                    node,
+                    rhs,
+                    Parser::<'a>::new_null(op.line(), op.column()),
+                )),
+                None,
+            ));
+        }
+        if !matches!(
+            op.token_type(),
+            TokenType::AddAssign
+                | TokenType::SubAssign
                 | TokenType::MulAssign
                 | TokenType::DivAssign
                 | TokenType::ModulusAssign
+                | TokenType::BitAndAssign
+                | TokenType::BitOrAssign
+                | TokenType::BitXorAssign
+                | TokenType::ShiftLeftAssign
+                | TokenType::ShiftRightAssign
+        ) {
+            return Ok(node);
+        }
+        self.check_assignable(&node, &op)?;
+        self.next_token();
+        let rhs = self.parse_assignment_expr()?;
+        if self.current_token().unwrap().token_type() == TokenType::Semi {
+            self.is_statement = true;
+        }
+        Ok(self.new_node(
+            start,
+            NodeValue::Expr(Expr::Operator(match op.token_type() {
+                TokenType::AddAssign => Operator::AddAssign(node, rhs),
+                TokenType::SubAssign => Operator::SubAssign(node, rhs),
+                TokenType::MulAssign => Operator::MulAssign(node, rhs),
+                TokenType::DivAssign => Operator::DivAssign(node, rhs),
+                TokenType::ModulusAssign => Operator::ModulusAssign(node, rhs),
+                TokenType::BitAndAssign => Operator::BitAndAssign(node, rhs),
+                TokenType::BitOrAssign => Operator::BitOrAssign(node, rhs),
+                TokenType::BitXorAssign => Operator::BitXorAssign(node, rhs),
+                TokenType::ShiftLeftAssign => Operator::ShiftLeftAssign(node, rhs),
+                _ => Operator::ShiftRightAssign(node, rhs),
+            })),
+            None,
+        ))
+    }
+
+    // 後置インクリメント/デクリメント: `x++`, `x--`
+    fn postfix(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
+        let mut node = self.factor()?;
+        loop {
+            let op = self.current_token().unwrap().clone();
+            match op.token_type() {
+                TokenType::Increment | TokenType::Decrement => {
+                    self.check_assignable(&node, &op)?;
+                    self.next_token();
+                    node = self.new_node(
+                        start,
+                        NodeValue::Expr(Expr::Operator(match op.token_type() {
+                            TokenType::Increment => Operator::Increment(node, false),
+                            _ => Operator::Decrement(node, false),
+                        })),
+                        None,
+                    );
+                }
+                // 添字アクセス `a[i]`
+                TokenType::LeftSquareBrace => {
+                    self.next_token(); // [
+                    let index = self.expr()?;
+                    if self.current_token().unwrap().token_type() != TokenType::RightSquareBrace {
+                        return Err(compile_error!(
+                            "error",
+                            self.current_token().unwrap().line(),
+                            self.current_token().unwrap().column(),
+                            self.current_token().unwrap().span(),
+                            &self.input_path(),
+                            &self.input_content(),
+                            "Expected `]` after the index, found {:?}",
+                            self.current_token().unwrap().token_value()
+                        ));
+                    }
+                    self.next_token(); // ]
+                    node = self.new_node(start, NodeValue::Expr(Expr::Index(node, index)), None);
+                }
+                // 添字アクセスの後のメンバアクセス `a[i].x`
+                TokenType::Dot => {
+                    self.next_token(); // .
+                    let item = self.parse_member_name()?;
+                    node =
+                        self.new_node(start, NodeValue::Expr(Expr::MemberAccess(node, item)), None);
+                }
+                _ => break,
+            }
+        }
+        Ok(node)
+    }
+
+    // 代入先として有効な式(変数, メンバアクセス, 添字アクセス)かどうかを検査する
+    fn check_assignable(&self, node: &Node, op: &Token) -> R<(), Diagnostic> {
+        match node.value {
+            NodeValue::Expr(Expr::Variable(_, _, _, false, _))
+            | NodeValue::Expr(Expr::MemberAccess(..))
+            | NodeValue::Expr(Expr::Index(..)) => Ok(()),
+            _ => Err(compile_error!(
+                "error",
+                op.line(),
+                op.column(),
+                op.span(),
+                &self.input_path(),
+                &self.input_content(),
+                "Invalid left-hand side of assignment"
+            )
+            .with_label(node.span, "cannot assign to this expression")),
+        }
+    }
+
+    fn term(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
+        let mut node = self.postfix()?;
+        while matches!(
+            self.current_token().unwrap().token_type(),
+            TokenType::Mul | TokenType::Div | TokenType::Modulus
         ) {
             let op = self.current_token().unwrap().clone();
             self.next_token();
-            let rhs = self.factor()?;
-            node = Box::new(Node::new(
+            let rhs = self.postfix()?;
+            node = self.new_node(
+                start,
                 NodeValue::Expr(Expr::Operator(match op.token_type() {
                     TokenType::Mul => Operator::Mul(node, rhs),
                     TokenType::Div => Operator::Div(node, rhs),
                     TokenType::Modulus => Operator::Modulus(node, rhs),
-                    TokenType::MulAssign => Operator::MulAssign(node, rhs),
-                    TokenType::DivAssign => Operator::DivAssign(node, rhs),
-                    TokenType::ModulusAssign => Operator::ModulusAssign(node, rhs),
                     _ => panic!(
                         "{}",
                         compile_error!(
                             "error",
                             op.line(),
                             op.column(),
+                            op.span(),
                             &self.input_path(),
                             &self.input_content(),
                             "Unexpected token: {:?}",
@@ -419,23 +630,18 @@ impl<'a> Parser<'a> {
                     ),
                 })),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ));
+            );
         }
         Ok(node)
     }
 
-    fn expr(&mut self) -> R<Box<Node>, String> {
+    fn expr(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         let mut node = self.term()?;
         while matches!(
             self.current_token().unwrap().token_type(),
             TokenType::Add
                 | TokenType::Sub
-                | TokenType::AddAssign
-                | TokenType::SubAssign
-                | TokenType::Increment
-                | TokenType::Decrement
                 | TokenType::BitAnd
                 | TokenType::BitOr
                 | TokenType::BitXor
@@ -445,14 +651,11 @@ impl<'a> Parser<'a> {
             let op = self.current_token().unwrap().clone();
             self.next_token();
             let rhs = self.term()?;
-            node = Box::new(Node::new(
+            node = self.new_node(
+                start,
                 NodeValue::Expr(Expr::Operator(match op.token_type() {
                     TokenType::Add => Operator::Add(node, rhs),
                     TokenType::Sub => Operator::Sub(node, rhs),
-                    TokenType::AddAssign => Operator::AddAssign(node, rhs),
-                    TokenType::SubAssign => Operator::SubAssign(node, rhs),
-                    TokenType::Increment => Operator::Increment(node),
-                    TokenType::Decrement => Operator::Decrement(node),
                     TokenType::BitAnd => Operator::BitAnd(node, rhs),
                     TokenType::BitOr => Operator::BitOr(node, rhs),
                     TokenType::BitXor => Operator::BitXor(node, rhs),
@@ -464,6 +667,7 @@ impl<'a> Parser<'a> {
                             "error",
                             op.line(),
                             op.column(),
+                            op.span(),
                             &self.input_path(),
                             &self.input_content(),
                             "Unexpected token: {:?}",
@@ -472,14 +676,13 @@ impl<'a> Parser<'a> {
                     ),
                 })),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ));
+            );
         }
         Ok(node)
     }
 
-    fn factor(&mut self) -> R<Box<Node>, String> {
+    fn factor(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         let mut token = self.current_token().unwrap().clone();
         let mut is_system = false;
         let mut node = Node::default();
@@ -488,6 +691,23 @@ impl<'a> Parser<'a> {
         let mut is_dereference = false;
         let mut generic_type_name: Vec<String> = vec![];
 
+        // 前置インクリメント/デクリメント: `++x`, `--x`
+        if matches!(
+            token.token_type(),
+            TokenType::Increment | TokenType::Decrement
+        ) {
+            self.next_token();
+            let operand = self.factor()?;
+            self.check_assignable(&operand, &token)?;
+            return Ok(self.new_node(
+                start,
+                NodeValue::Expr(Expr::Operator(match token.token_type() {
+                    TokenType::Increment => Operator::Increment(operand, true),
+                    _ => Operator::Decrement(operand, true),
+                })),
+                None,
+            ));
+        }
         if token.token_type() == TokenType::AtSign {
             self.next_token();
             token = self.current_token().unwrap().clone();
@@ -510,23 +730,30 @@ impl<'a> Parser<'a> {
             return Ok(self.parse_single_statement().unwrap()?);
             // panic!("{:?}",self.current_token());
         }
+        if token.token_type() == TokenType::BitNot {
+            self.next_token(); // ~
+            let operand = self.factor()?;
+            return Ok(self.new_node(
+                start,
+                NodeValue::Expr(Expr::Operator(Operator::BitNot(operand))),
+                None,
+            ));
+        }
         match self.current_token().unwrap().token_type() {
             TokenType::MultiComment(content, (line, column)) => {
                 self.next_token();
-                node = Node::new(
+                node = *self.new_node(
+                    start,
                     NodeValue::MultiComment(content, (line, column)),
                     None,
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
                 );
             }
             TokenType::SingleComment(content, (line, column)) => {
                 self.next_token();
-                node = Node::new(
+                node = *self.new_node(
+                    start,
                     NodeValue::SingleComment(content, (line, column)),
                     None,
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
                 );
             }
             TokenType::DoubleQuote | TokenType::SingleQuote => {
@@ -539,17 +766,17 @@ impl<'a> Parser<'a> {
 
                 if let Ok(string) = token.token_value().parse::<String>() {
                     self.next_token();
-                    node = Node::new(
+                    node = *self.new_node(
+                        start,
                         NodeValue::Expr(Expr::DataType(DataType::String(string))),
                         None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
                     );
                 } else {
                     return Err(compile_error!(
                     "error",
                     self.current_token().unwrap().line(),
                     self.current_token().unwrap().column(),
+                    self.current_token().unwrap().span(),
                     &self.input_path(),
                     &self.input_content(),
                     "Unexpected end of input_content, no closing DoubleQuote or SingleQuote found: {:?}",
@@ -569,21 +796,19 @@ impl<'a> Parser<'a> {
                 if token.token_value().contains(".") {
                     if let Ok(number) = token.token_value().parse::<f64>() {
                         self.next_token();
-                        node = Node::new(
+                        node = *self.new_node(
+                            start,
                             NodeValue::Expr(Expr::DataType(DataType::Float(number))),
                             None,
-                            self.current_token().unwrap().line(),
-                            self.current_token().unwrap().column(),
                         );
                         return Ok(Box::new(node));
                     }
                 } else if let Ok(number) = token.token_value().parse::<i64>() {
                     self.next_token();
-                    node = Node::new(
+                    node = *self.new_node(
+                        start,
                         NodeValue::Expr(Expr::DataType(DataType::Int(number))),
                         None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
                     );
                     return Ok(Box::new(node));
                 }
@@ -591,14 +816,12 @@ impl<'a> Parser<'a> {
 
             TokenType::Ident => {
                 if self.current_token().unwrap().token_value() == "null" {
-                    node = Node::new(
+                    self.next_token();
+                    return Ok(self.new_node(
+                        start,
                         NodeValue::Expr(Expr::DataType(DataType::Null)),
                         None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    );
-                    self.next_token();
-                    return Ok(Box::new(node));
+                    ));
                 }
                 if let Ok(bool_value) = token.token_value().parse::<bool>() {
                     if self.peek_next_token(1).unwrap().token_type() == TokenType::Dot {
@@ -608,15 +831,18 @@ impl<'a> Parser<'a> {
                         return Ok(Box::new(node));
                     } else {
                         self.next_token();
-                        node = Node::new(
+                        node = *self.new_node(
+                            start,
                             NodeValue::Expr(Expr::DataType(DataType::Bool(bool_value))),
                             None,
-                            self.current_token().unwrap().line(),
-                            self.current_token().unwrap().column(),
                         );
 
                         return Ok(Box::new(node));
                     }
+                } else if Keywords::CONTROL_FROW_IF_KEYWORD.contains(&token.token_value().as_str())
+                {
+                    // 値を返すif式
+                    return self.parse_if_statement();
                 } else {
                     let ident_token = self.current_token().unwrap().clone();
                     self.next_token();
@@ -627,6 +853,7 @@ impl<'a> Parser<'a> {
 
                     if self.current_token().unwrap().token_type() == TokenType::LeftCurlyBrace
                         && self.previous_token(1).unwrap().token_type() != TokenType::RightArrow
+                        && !self.is_condition
                     {
                         node = *self.parse_struct_instance(&ident_token)?;
                         return Ok(Box::new(node));
@@ -642,7 +869,9 @@ impl<'a> Parser<'a> {
                         return Ok(Box::new(node));
                     }
 
-                    if self.current_token().unwrap().token_type() == TokenType::Lt {
+                    if self.current_token().unwrap().token_type() == TokenType::Lt
+                        && self.is_generic_argument_list()
+                    {
                         self.next_token();
                         while self.current_token().unwrap().token_type() != TokenType::Gt {
                             generic_type_name.push(self.current_token().unwrap().token_value());
@@ -657,10 +886,7 @@ impl<'a> Parser<'a> {
                         //panic!("{:?}",self.current_token());
                     }
 
-                    let mut data_type = Parser::<'a>::new_null(
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    );
+                    let mut data_type = Parser::<'a>::new_null(token.line(), token.column());
 
                     if self.current_token().unwrap().token_type() == TokenType::Colon {
                         self.next_token();
@@ -671,7 +897,8 @@ impl<'a> Parser<'a> {
                     } else {
                         Some(generic_type_name)
                     };
-                    node = Node::new(
+                    node = *self.new_node(
+                        start,
                         NodeValue::Expr(Expr::Variable(
                             data_type,
                             token.token_value().clone(),
@@ -680,8 +907,6 @@ impl<'a> Parser<'a> {
                             _generic_type_name,
                         )),
                         None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
                     );
                 }
             }
@@ -693,6 +918,7 @@ impl<'a> Parser<'a> {
                         "error",
                         self.current_token().unwrap().line(),
                         self.current_token().unwrap().column(),
+                        self.current_token().unwrap().span(),
                         &self.input_path(),
                         &self.input_content(),
                         "no closing parenthesis in factor: {:?}",
@@ -701,7 +927,8 @@ impl<'a> Parser<'a> {
                 } else {
                     self.next_token();
                 }
-                return Ok(Box::new(node));
+                // 括弧も式の範囲に含める
+                return Ok(self.with_span(start, Box::new(node)));
             }
 
             TokenType::LeftCurlyBrace => {
@@ -709,10 +936,7 @@ impl<'a> Parser<'a> {
                 return Ok(Box::new(node));
             }
             TokenType::LeftSquareBrace => {
-                let data_type = Parser::<'a>::new_null(
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                );
+                let data_type = Parser::<'a>::new_null(token.line(), token.column());
                 node = *self.parse_array(&data_type)?;
                 return Ok(Box::new(node));
             }
@@ -722,6 +946,7 @@ impl<'a> Parser<'a> {
                     "error",
                     self.current_token().unwrap().line(),
                     self.current_token().unwrap().column(),
+                    self.current_token().unwrap().span(),
                     &self.input_path(),
                     &self.input_content(),
                     "Unexpected token in factor: {:?}",
@@ -732,86 +957,120 @@ impl<'a> Parser<'a> {
         Ok(Box::new(node))
     }
 
-    fn parse_scope_resolution(&mut self, ident_token: &Token) -> R<Box<Node>, String> {
-        let mut scope_resolution = vec![];
-        //pub fn new_variable(name: String, expr: Box<Node>, line: usize, column: usize) -> Box<Node> {
-        // 最初のトークンはident_tokenを
-        scope_resolution.push(Box::new(Node::new(
-            NodeValue::Expr(Expr::ScopeResolution(vec![Parser::<'a>::new_variable(
-                ident_token.token_value(),
-                Parser::<'a>::new_null(ident_token.line(), ident_token.column()),
-                ident_token.line(),
-                ident_token.column(),
-            )])),
-            None,
-            ident_token.line(),
-            ident_token.column(),
-        )));
-        // 二個目以降はself.exprを呼ぶ
+    // `a::b::c`のパスを解析する(ident_tokenは最初の区切り)
+    // 区切りは変数ノードの並びで、最後の要素は関数呼び出し・構造体インスタンス・
+    // `*`(use文のグロブ)・`{..}`(use文のグループ、要素は区切りかパス)のいずれかになる
+    fn parse_scope_resolution(&mut self, ident_token: &Token) -> R<Box<Node>, Diagnostic> {
+        let start = self.i - 1; // ident_tokenは直前に読んだトークン
+        let mut segments = vec![Parser::<'a>::new_path_segment(ident_token)];
         while self.current_token().unwrap().token_type() == TokenType::ScopeResolution {
             self.next_token(); // ::
-            if self.current_token().unwrap().token_type() == TokenType::LeftCurlyBrace {
-                let mut expr = Parser::<'a>::new_null(
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                );
-                self.next_token(); // {
-                while self.current_token().unwrap().token_type() != TokenType::RightCurlyBrace {
-                    if self.current_token().unwrap().token_type() == TokenType::Conma {
-                        self.next_token(); // ,
-                        continue;
+            let token = self.current_token().unwrap().clone();
+            match token.token_type() {
+                TokenType::LeftCurlyBrace => {
+                    let group_start = self.i;
+                    self.next_token(); // {
+                    let mut group = vec![];
+                    while self.current_token().unwrap().token_type() != TokenType::RightCurlyBrace {
+                        if self.current_token().unwrap().token_type() == TokenType::Conma {
+                            self.next_token(); // ,
+                            continue;
+                        }
+                        let item_token = self.current_token().unwrap().clone();
+                        if item_token.token_type() != TokenType::Ident {
+                            return Err(compile_error!(
+                                "error",
+                                item_token.line(),
+                                item_token.column(),
+                                item_token.span(),
+                                &self.input_path(),
+                                &self.input_content(),
+                                "Expected a name in the `{{..}}` group of a path, found {:?}",
+                                item_token.token_value()
+                            ));
+                        }
+                        self.next_token(); // ident
+                        if self.current_token().unwrap().token_type() == TokenType::ScopeResolution
+                        {
+                            group.push(self.parse_scope_resolution(&item_token)?);
+                        } else {
+                            group.push(Parser::<'a>::new_path_segment(&item_token));
+                        }
                     }
-                    if self.current_token().unwrap().token_type() == TokenType::Mul {
-                        self.next_token(); // *
-                        scope_resolution.push(Box::new(Node::new(
-                            NodeValue::Expr(Expr::DataType(DataType::String("*".to_string()))),
-                            None,
-                            self.current_token().unwrap().line(),
-                            self.current_token().unwrap().column(),
-                        )));
-                        continue;
+                    self.next_token(); // }
+                    segments.push(self.new_node(
+                        group_start,
+                        NodeValue::Expr(Expr::Block(group)),
+                        None,
+                    ));
+                    break;
+                }
+                TokenType::Mul => {
+                    self.next_token(); // *
+                    segments.push(self.new_node(
+                        self.i - 1,
+                        NodeValue::Expr(Expr::DataType(DataType::String("*".to_string()))),
+                        None,
+                    ));
+                    break;
+                }
+                TokenType::Ident => {
+                    self.next_token(); // ident
+                    if self.current_token().unwrap().token_type() == TokenType::LeftParen {
+                        segments.push(self.parse_function_call(token, false)?);
+                        break;
                     }
-                    scope_resolution.push(self.expr()?);
+                    if self.current_token().unwrap().token_type() == TokenType::LeftCurlyBrace
+                        && !self.is_condition
+                    {
+                        segments.push(self.parse_struct_instance(&token)?);
+                        break;
+                    }
+                    segments.push(Parser::<'a>::new_path_segment(&token));
                 }
-
-                if self.current_token().unwrap().token_type() == TokenType::RightCurlyBrace {
-                    self.next_token(); // }
+                _ => {
+                    return Err(compile_error!(
+                        "error",
+                        token.line(),
+                        token.column(),
+                        token.span(),
+                        &self.input_path(),
+                        &self.input_content(),
+                        "Expected a name after `::`, found {:?}",
+                        token.token_value()
+                    ));
                 }
-                return Ok(Box::new(Node::new(
-                    NodeValue::Expr(Expr::ScopeResolution(scope_resolution)),
-                    None,
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                )));
             }
-
-            let scope = if self.peek_next_token(1).unwrap().token_type() == TokenType::LeftParen {
-                let ident_token = self.current_token().unwrap().clone();
-                self.next_token(); // ident
-                self.parse_function_call(ident_token, false)?
-            } else if self.current_token().unwrap().token_type() == TokenType::Mul {
-                self.next_token(); // *
-                Box::new(Node::new(
-                    NodeValue::Expr(Expr::DataType(DataType::String("*".to_string()))),
-                    None,
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                ))
-            } else {
-                self.expr()?
-            };
-            scope_resolution.push(scope);
         }
 
-        Ok(Box::new(Node::new(
-            NodeValue::Expr(Expr::ScopeResolution(scope_resolution)),
+        Ok(self.new_node(
+            start,
+            NodeValue::Expr(Expr::ScopeResolution(segments)),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
+    }
+
+    // パスの区切り1つ分(型を持たない変数ノード)
+    fn new_path_segment(token: &Token) -> Box<Node> {
+        let mut node = Node::new(
+            NodeValue::Expr(Expr::Variable(
+                Parser::<'a>::new_null(token.line(), token.column()),
+                token.token_value(),
+                false,
+                false,
+                None,
+            )),
+            None,
+            token.line(),
+            token.column(),
+        );
+        node.span = token.span();
+        Box::new(node)
     }
 
-    fn parse_function_call(&mut self, token: Token, is_system: bool) -> R<Box<Node>, String> {
+    fn parse_function_call(&mut self, token: Token, is_system: bool) -> R<Box<Node>, Diagnostic> {
+        // 関数名は直前に読んだトークン(システム関数なら`@`から)
+        let start = if is_system { self.i - 2 } else { self.i - 1 };
         self.next_token(); // '(' をスキップ
         let mut args = Vec::new();
         while self.current_token().unwrap().token_type() != TokenType::RightParen {
@@ -827,16 +1086,17 @@ impl<'a> Parser<'a> {
             self.is_statement = true;
         }
 
-        Ok(Box::new(Node {
-            value: NodeValue::Expr(Expr::Call(token.token_value().clone(), args, is_system)),
-            next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>として初期化
-            line: self.current_token().unwrap().line(),
-            column: self.current_token().unwrap().column(),
-            is_statement: self.is_statement,
-        }))
+        let mut node = self.new_node(
+            start,
+            NodeValue::Expr(Expr::Call(token.token_value().clone(), args, is_system)),
+            None,
+        );
+        node.is_statement = self.is_statement;
+        Ok(node)
     }
 
-    fn parse_callback_function_definition(&mut self) -> R<Box<Node>, String> {
+    fn parse_callback_function_definition(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // 'callback' をスキップ
         if self.current_token().unwrap().token_value() == "fn" {
             self.next_token(); // 'fn' をスキップ
@@ -849,35 +1109,32 @@ impl<'a> Parser<'a> {
             let name = self.current_token().unwrap().token_value().clone();
             self.next_token(); // 関数名をスキップ
             self.next_token(); // '(' をスキップ
-            let mut args: Vec<(Box<Node>, String)> = Vec::new();
-            let mut return_type = Parser::<'a>::new_null(
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            );
+            let mut args: Vec<FunctionArg> = Vec::new();
+            let mut return_type =
+                Parser::<'a>::new_null(self.tokens[start].line(), self.tokens[start].column());
             while self.current_token().unwrap().token_type() != TokenType::RightParen {
+                let arg_start = self.i;
                 let arg = self.expr()?;
-                let mut data_type = Parser::<'a>::new_null(
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                );
+                let mut data_type = Parser::<'a>::new_null(arg.line, arg.column);
                 if self.current_token().unwrap().token_type() == TokenType::Colon {
                     self.next_token(); // ':' をスキップ
+                    let type_start = self.i;
                     data_type = self.expr()?;
-                    data_type = Box::new(Node::new(
+                    data_type = self.new_node(
+                        type_start,
                         NodeValue::Expr(Expr::DataType(DataType::from(data_type))),
                         None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    ));
+                    );
                 }
                 let arg_name = match arg.value() {
                     NodeValue::Expr(ref expr) => match *expr {
                         Expr::Variable(_, ref name, _, _, _) => name.clone(),
-                        _ => return Err("Invalid argument name".to_string()),
+                        _ => return Err(self.invalid_argument_name(&arg)),
                     },
-                    _ => return Err("Invalid argument name".to_string()),
+                    _ => return Err(self.invalid_argument_name(&arg)),
                 };
-                args.push((data_type, arg_name));
+                let name_span = self.arg_name_span(arg_start, &arg_name);
+                args.push((data_type, arg_name, name_span));
                 if self.current_token().unwrap().token_type() == TokenType::Conma {
                     self.next_token(); // ',' をスキップ
                 }
@@ -888,7 +1145,8 @@ impl<'a> Parser<'a> {
             }
             let body = self.parse_block()?; // ブロックの解析
 
-            return Ok(Box::new(Node::new(
+            return Ok(self.new_node(
+                start,
                 NodeValue::Statement(Statement::Declaration(Declaration::CallBackFunction(
                     name,
                     args,
@@ -897,14 +1155,26 @@ impl<'a> Parser<'a> {
                     is_system,
                 ))),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            )));
+            ));
         }
         Ok(Box::new(Node::default()))
     }
 
-    fn parse_function_definition(&mut self, is_public: bool) -> R<Box<Node>, String> {
+    // 引数名として使えない式のエラー
+    fn invalid_argument_name(&self, arg: &Node) -> Diagnostic {
+        compile_error!(
+            "error",
+            arg.line,
+            arg.column,
+            arg.span,
+            &self.input_path(),
+            &self.input_content(),
+            "Invalid argument name"
+        )
+    }
+
+    fn parse_function_definition(&mut self, is_public: bool) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // 'fn' をスキップ
         let mut is_system = false;
         if self.current_token().unwrap().token_type() == TokenType::AtSign {
@@ -914,35 +1184,50 @@ impl<'a> Parser<'a> {
         let name = self.current_token().unwrap().token_value().clone();
         self.next_token(); // 関数名をスキップ
         self.next_token(); // '(' をスキップ
-        let mut args: Vec<(Box<Node>, String)> = Vec::new();
-        let mut return_type = Parser::<'a>::new_null(
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        );
+        let mut args: Vec<FunctionArg> = Vec::new();
+        let mut return_type =
+            Parser::<'a>::new_null(self.tokens[start].line(), self.tokens[start].column());
         while self.current_token().unwrap().token_type() != TokenType::RightParen {
+            let arg_start = self.i;
             let arg = self.expr()?;
             //debug!("arg: {:?}", arg);
             let mut data_type = match arg.value().clone() {
                 NodeValue::Expr(ref expr) => match *expr {
                     Expr::Variable(ref data_type, _, _, _, _) => data_type.clone(),
-                    _ => Parser::<'a>::new_null(
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    ),
+                    _ => Parser::<'a>::new_null(arg.line, arg.column),
                 },
-                _ => Parser::<'a>::new_null(
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                ),
+                _ => Parser::<'a>::new_null(arg.line, arg.column),
             };
             let arg_name = match arg.value() {
                 NodeValue::Expr(ref expr) => match *expr {
                     Expr::Variable(_, ref name, _, _, _) => name.clone(),
-                    _ => return Err("Invalid argument name".to_string()),
+                    _ => return Err(self.invalid_argument_name(&arg)),
                 },
-                _ => return Err("Invalid argument name".to_string()),
+                _ => return Err(self.invalid_argument_name(&arg)),
             };
-            args.push((data_type, arg_name));
+            // selfレシーバ(`self`, `&self`, `&mut self`)は型を`Self`とし、参照かどうかを保持する
+            if arg_name == "self" {
+                if let NodeValue::Expr(Expr::Variable(_, _, is_mutable, is_reference, _)) =
+                    arg.value()
+                {
+                    let mut receiver = Node::new(
+                        NodeValue::Expr(Expr::Variable(
+                            Parser::<'a>::new_null(arg.line, arg.column),
+                            "Self".to_string(),
+                            is_mutable,
+                            is_reference,
+                            None,
+                        )),
+                        None,
+                        arg.line,
+                        arg.column,
+                    );
+                    receiver.span = arg.span;
+                    data_type = Box::new(receiver);
+                }
+            }
+            let name_span = self.arg_name_span(arg_start, &arg_name);
+            args.push((data_type, arg_name, name_span));
 
             //debug!("args: {:?}", args);
             if self.current_token().unwrap().token_type() == TokenType::Conma {
@@ -958,7 +1243,8 @@ impl<'a> Parser<'a> {
 
         let body = self.parse_block()?; // ブロックの解析
 
-        Ok(Box::new(Node::new(
+        Ok(self.new_node(
+            start,
             NodeValue::Statement(Statement::Declaration(Declaration::Function(
                 name,
                 args,
@@ -968,12 +1254,50 @@ impl<'a> Parser<'a> {
                 is_public,
             ))),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
+    }
+
+    fn parse_condition(&mut self) -> R<Box<Node>, Diagnostic> {
+        let is_condition = self.is_condition;
+        self.is_condition = true;
+        let node = self.parse_condition_expr();
+        self.is_condition = is_condition;
+        node
+    }
+
+    // 優先順位: || < && < 比較演算子 < 算術式
+    fn parse_condition_expr(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
+        let mut node = self.parse_and_expr()?;
+        while self.current_token().unwrap().token_type() == TokenType::Or {
+            self.next_token();
+            let rhs = self.parse_and_expr()?;
+            node = self.new_node(
+                start,
+                NodeValue::Expr(Expr::Operator(Operator::Or(node, rhs))),
+                None,
+            );
+        }
+        Ok(node)
+    }
+
+    fn parse_and_expr(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
+        let mut node = self.parse_comparison_expr()?;
+        while self.current_token().unwrap().token_type() == TokenType::And {
+            self.next_token();
+            let rhs = self.parse_comparison_expr()?;
+            node = self.new_node(
+                start,
+                NodeValue::Expr(Expr::Operator(Operator::And(node, rhs))),
+                None,
+            );
+        }
+        Ok(node)
     }
 
-    fn parse_condition(&mut self) -> R<Box<Node>, String> {
+    fn parse_comparison_expr(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         let mut node = self.expr()?; // 基本の式を解析
 
         while matches!(
@@ -984,14 +1308,13 @@ impl<'a> Parser<'a> {
                 | TokenType::Gt
                 | TokenType::Le
                 | TokenType::Ge
-                | TokenType::And
-                | TokenType::Or
         ) {
             let op = self.current_token().unwrap().clone();
             self.next_token();
-            let rhs = self.expr()?; // 条件演算子の右側の式を解析
+            let rhs = self.expr()?; // 比較演算子の右側の式を解析
 
-            node = Box::new(Node::new(
+            node = self.new_node(
+                start,
                 NodeValue::Expr(Expr::Operator(match op.token_type() {
                     TokenType::Eq => Operator::Eq(node, rhs),
                     TokenType::Ne => Operator::Ne(node, rhs),
@@ -999,15 +1322,13 @@ impl<'a> Parser<'a> {
                     TokenType::Gt => Operator::Gt(node, rhs),
                     TokenType::Le => Operator::Le(node, rhs),
                     TokenType::Ge => Operator::Ge(node, rhs),
-                    TokenType::And => Operator::And(node, rhs),
-                    TokenType::Or => Operator::Or(node, rhs),
-
                     _ => panic!(
                         "{}",
                         compile_error!(
                             "error",
                             op.line(),
                             op.column(),
+                            op.span(),
                             &self.input_path(),
                             &self.input_content(),
                             "Unexpected token: {:?}",
@@ -1016,47 +1337,45 @@ impl<'a> Parser<'a> {
                     ),
                 })),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ));
+            );
         }
         Ok(node)
     }
 
-    fn parse_loop_statement(&mut self) -> R<Box<Node>, String> {
+    fn parse_loop_statement(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // 'loop' をスキップ
-        self.next_token(); // { をスキップ
 
-        let body = self.parse_block()?; // ブロックの解析
-        Ok(Box::new(Node::new(
+        let body = self.parse_block()?; // ブロックの解析('{'から)
+        Ok(self.new_node(
+            start,
             NodeValue::Statement(Statement::ControlFlow(ControlFlow::Loop(body))),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
     }
 
-    fn parse_break(&mut self) -> R<Box<Node>, String> {
+    fn parse_break(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // break
-        Ok(Box::new(Node::new(
+        Ok(self.new_node(
+            start,
             NodeValue::Statement(Statement::ControlFlow(ControlFlow::Break)),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
     }
 
-    fn parse_continue(&mut self) -> R<Box<Node>, String> {
+    fn parse_continue(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // continue
-        Ok(Box::new(Node::new(
+        Ok(self.new_node(
+            start,
             NodeValue::Statement(Statement::ControlFlow(ControlFlow::Continue)),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
     }
 
-    fn parse_if_statement(&mut self) -> R<Box<Node>, String> {
+    fn parse_if_statement(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // 'if' をスキップ
         let mut condition = Parser::<'a>::new_null(
             self.current_token().unwrap().line(),
@@ -1065,156 +1384,157 @@ impl<'a> Parser<'a> {
         if self.current_token().unwrap().token_type() != TokenType::LeftCurlyBrace {
             condition = self.parse_condition()?;
         }
-        self.next_token(); // { をスキップ
-        let body = self.parse_block()?; // ブロックの解析
+        let body = self.parse_block()?; // ブロックの解析('{'から)
 
-        let mut if_node = Node {
-            value: NodeValue::Statement(Statement::ControlFlow(ControlFlow::If(
+        // else節は別のノード(next)になるので、if節の範囲はブロックの終わりまで
+        let mut if_node = self.new_node(
+            start,
+            NodeValue::Statement(Statement::ControlFlow(ControlFlow::If(
                 Box::new(*condition),
                 Box::new(*body),
             ))),
-            next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>として初期化
-            line: self.current_token().unwrap().line(),
-            column: self.current_token().unwrap().column(),
-            is_statement: true,
-        };
+            None,
+        );
+        if_node.is_statement = true;
 
         // 'else' または 'else if' の処理
-        if let Some(next_token) = self.peek_next_token(1) {
-            if next_token.token_value() == "else" {
-                self.next_token(); // 'else' をスキップ
-                if let Some(next_token) = self.peek_next_token(1) {
-                    if next_token.token_value() == "if" {
-                        // 'else if' の処理
-                        self.next_token(); // 'if' をスキップ
-                        let else_if_node = self.parse_if_statement()?;
-                        // Rc<RefCell<Option<Box<Node>>>>に次のノードを設定
-                        *if_node.next.borrow_mut() = Some(else_if_node);
-                    } else {
-                        // 'else' の処理
-                        self.next_token(); // { をスキップ
-                        let else_body = self.parse_block()?;
-                        let else_node = Node {
-                            value: NodeValue::Statement(Statement::ControlFlow(ControlFlow::Else(
-                                Box::new(*else_body),
-                            ))),
-                            next: Rc::new(RefCell::new(None)), // nextを初期化
-                            line: self.current_token().unwrap().line(),
-                            column: self.current_token().unwrap().column(),
-                            is_statement: true,
-                        };
-                        // Rc<RefCell<Option<Box<Node>>>>に次のノードを設定
-                        *if_node.next.borrow_mut() = Some(Box::new(else_node));
-                    }
+        if self.current_token().unwrap().token_value() == "else" {
+            let else_start = self.i;
+            self.next_token(); // 'else' をスキップ
+            if self.current_token().unwrap().token_value() == "if" {
+                // 'else if' の処理(後続の独立したif文と区別するためElseIfに置き換える)
+                let mut else_if_node = self.parse_if_statement()?;
+                // 位置は`else`から(続くelse節はelse_if_nodeのnextにある)
+                let else_token = &self.tokens[else_start];
+                else_if_node.line = else_token.line();
+                else_if_node.column = else_token.column();
+                else_if_node.span = else_token.span().to(else_if_node.span);
+                if let NodeValue::Statement(Statement::ControlFlow(ControlFlow::If(
+                    condition,
+                    body,
+                ))) = else_if_node.value.clone()
+                {
+                    else_if_node.value = NodeValue::Statement(Statement::ControlFlow(
+                        ControlFlow::ElseIf(condition, body),
+                    ));
                 }
+                // Rc<RefCell<Option<Box<Node>>>>に次のノードを設定
+                *if_node.next.borrow_mut() = Some(else_if_node);
+            } else {
+                // 'else' の処理
+                let else_body = self.parse_block()?;
+                let mut else_node = self.new_node(
+                    else_start,
+                    NodeValue::Statement(Statement::ControlFlow(ControlFlow::Else(Box::new(
+                        *else_body,
+                    )))),
+                    None,
+                );
+                else_node.is_statement = true;
+                // Rc<RefCell<Option<Box<Node>>>>に次のノードを設定
+                *if_node.next.borrow_mut() = Some(else_node);
             }
         }
-        Ok(Box::new(if_node))
+        Ok(if_node)
     }
 
-    fn parse_for_statement(&mut self) -> R<Box<Node>, String> {
+    fn parse_for_statement(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // for
-        let var = self.current_token().unwrap().token_value().clone();
+        let var_token = self.current_token().unwrap().clone();
+        let var = var_token.token_value().clone();
         self.next_token(); // var
+        let var_node = self.new_node(
+            self.i - 1,
+            NodeValue::Expr(Expr::Variable(
+                Parser::<'a>::new_null(var_token.line(), var_token.column()),
+                var,
+                false,
+                false,
+                None,
+            )),
+            None,
+        );
         self.next_token(); // in
 
-        let start_token = self.current_token().unwrap().token_value().clone();
-        let iterator_node = if self.peek_next_token(1).unwrap().token_type() == TokenType::Range {
-            self.next_token(); // skip ..
-            self.next_token();
-            let end_token = self.current_token().unwrap().token_value().clone();
-            Box::new(Node::new(
-                NodeValue::Expr(Expr::Operator(Operator::Range(
-                    Box::new(Node::new(
-                        NodeValue::Expr(Expr::DataType(DataType::Int(
-                            start_token.parse().unwrap(),
-                        ))),
-                        None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    )),
-                    Box::new(Node::new(
-                        NodeValue::Expr(Expr::DataType(DataType::Int(end_token.parse().unwrap()))),
-                        None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    )),
-                ))),
-                None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ))
-        } else {
-            Box::new(Node::new(
-                NodeValue::Expr(Expr::Variable(
-                    Parser::<'a>::new_null(
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    ),
-                    start_token,
-                    false,
-                    false,
-                    None,
-                )),
-                None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ))
-        };
-        self.next_token(); // { をスキップ
-        let body = self.parse_block()?;
-        Ok(Box::new(Node::new(
+        // 範囲の端点と増分は任意の式(`{`を構造体インスタンスとして扱わない)
+        let is_condition = self.is_condition;
+        self.is_condition = true;
+        let iterator_node = self.parse_for_iterator();
+        self.is_condition = is_condition;
+        let iterator_node = iterator_node?;
+        let body = self.parse_block()?; // ブロックの解析('{'から)
+        Ok(self.new_node(
+            start,
             NodeValue::Statement(Statement::ControlFlow(ControlFlow::For(
-                Box::new(Node::new(
-                    NodeValue::Expr(Expr::Variable(
-                        Parser::<'a>::new_null(
-                            self.current_token().unwrap().line(),
-                            self.current_token().unwrap().column(),
-                        ),
-                        var,
-                        false,
-                        false,
-                        None,
-                    )),
-                    None,
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                )),
+                var_node,
                 iterator_node,
                 body,
             ))),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
     }
 
-    fn parse_return_type(&mut self) -> R<Box<Node>, String> {
+    // `始点..終点`, `始点..=終点`, `始点..終点 step 増分` またはコレクション式
+    fn parse_for_iterator(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start_index = self.i;
+        let start = self.expr()?;
+        let is_inclusive = match self.current_token().unwrap().token_type() {
+            TokenType::Range => false,
+            TokenType::RangeInclusive => true,
+            _ => return Ok(start),
+        };
+        self.next_token(); // '..' または '..=' をスキップ
+        let end = self.expr()?;
+        let step = if self.current_token().unwrap().token_value() == "step" {
+            self.next_token(); // 'step' をスキップ
+            self.expr()?
+        } else {
+            Parser::<'a>::new_null(end.line, end.column)
+        };
+        Ok(self.new_node(
+            start_index,
+            NodeValue::Expr(Expr::Operator(Operator::Range(
+                start,
+                end,
+                step,
+                is_inclusive,
+            ))),
+            None,
+        ))
+    }
+
+    // 戻り値の型(位置は`->`の後の型)
+    fn parse_return_type(&mut self) -> R<Box<Node>, Diagnostic> {
         self.next_token(); // '->' をスキップ
+        let start = self.i;
+        if self.current_token().unwrap().token_type() == TokenType::LeftSquareBrace {
+            return self.parse_array_type();
+        }
         let mut return_type = String::new();
         if self.current_token().unwrap().token_type() == TokenType::LeftParen {
             self.next_token(); // (
             self.next_token(); // )
             return_type = String::from("()");
-            return Ok(Box::new(Node::new(
+            return Ok(self.new_node(
+                start,
                 NodeValue::Expr(Expr::DataType(DataType::String(return_type))),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            )));
+            ));
         }
 
         return_type = self.current_token().unwrap().token_value().clone();
         //debug!("{:?}", return_type);
-        Ok(Box::new(Node::new(
+        self.next_token(); // 型名をスキップ
+        Ok(self.new_node(
+            start,
             NodeValue::Expr(Expr::DataType(DataType::String(return_type))),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
     }
 
-    fn parse_while_statement(&mut self) -> R<Box<Node>, String> {
+    fn parse_while_statement(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // 'while' をスキップ
         let mut condition = Parser::<'a>::new_null(
             self.current_token().unwrap().line(),
@@ -1223,20 +1543,19 @@ impl<'a> Parser<'a> {
         if self.current_token().unwrap().token_type() != TokenType::LeftCurlyBrace {
             condition = self.parse_condition()?;
         }
-        self.next_token(); // { をスキップ
-        let body = self.parse_block()?; // ブロックの解析
-        Ok(Box::new(Node::new(
+        let body = self.parse_block()?; // ブロックの解析('{'から)
+        Ok(self.new_node(
+            start,
             NodeValue::Statement(Statement::ControlFlow(ControlFlow::While(
                 Box::new(*condition),
                 Box::new(*body),
             ))),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
     }
 
-    fn parse_block(&mut self) -> R<Box<Node>, String> {
+    fn parse_block(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         if self.current_token().unwrap().token_type() == TokenType::LeftCurlyBrace {
             self.next_token(); // '{' をスキップ
         }
@@ -1247,6 +1566,7 @@ impl<'a> Parser<'a> {
                     "error",
                     self.current_token().unwrap().line(),
                     self.current_token().unwrap().column(),
+                    self.current_token().unwrap().span(),
                     &self.input_path(),
                     &self.input_content(),
                     "Unexpected end of input, no closing curly brace found: {:?}",
@@ -1261,6 +1581,7 @@ impl<'a> Parser<'a> {
                 "error",
                 self.current_token().unwrap().line(),
                 self.current_token().unwrap().column(),
+                self.current_token().unwrap().span(),
                 &self.input_path(),
                 &self.input_content(),
                 "no closing curly brace in block: {:?}",
@@ -1268,67 +1589,113 @@ impl<'a> Parser<'a> {
             ));
         } else {
             self.next_token(); // '}' をスキップ
-            Ok(Box::new(Node::new(
-                NodeValue::Expr(Expr::Block(nodes)),
-                None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            )))
+            Ok(self.new_node(start, NodeValue::Expr(Expr::Block(nodes)), None))
         }
     }
 
-    fn parse_data_type(&mut self) -> R<Box<Node>, String> {
+    fn parse_data_type(&mut self) -> R<Box<Node>, Diagnostic> {
+        // `let a: [i32; 4]` では ':' の位置から呼ばれる
+        if self.current_token().unwrap().token_type() == TokenType::Colon
+            && self.peek_next_token(1).unwrap().token_type() == TokenType::LeftSquareBrace
+        {
+            self.next_token(); // :
+        }
+        if self.current_token().unwrap().token_type() == TokenType::LeftSquareBrace {
+            return self.parse_array_type();
+        }
         if self.peek_next_token(1).unwrap().token_type() != TokenType::Eof
             && self.peek_next_token(1).unwrap().token_type() != TokenType::RightCurlyBrace
             && self.peek_next_token(1).unwrap().token_type() == TokenType::Ident
         {
             self.next_token(); // 変数名 をスキップ
         }
+        let start = self.i;
         let data_type = self.expr()?;
-        Ok(Box::new(Node::new(
+        Ok(self.new_node(
+            start,
             NodeValue::Expr(Expr::DataType(DataType::from(data_type))),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
     }
 
-    fn parse_type_declaration(&mut self, is_public: bool) -> R<Box<Node>, String> {
+    // 固定長配列型 `[要素の型; 要素数]`
+    fn parse_array_type(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
+        self.next_token(); // [
+        let element_type = self.parse_data_type()?;
+        if self.current_token().unwrap().token_type() != TokenType::Semi {
+            return Err(compile_error!(
+                "error",
+                self.current_token().unwrap().line(),
+                self.current_token().unwrap().column(),
+                self.current_token().unwrap().span(),
+                &self.input_path(),
+                &self.input_content(),
+                "Expected `;` and a length in the array type, found {:?}",
+                self.current_token().unwrap().token_value()
+            ));
+        }
+        self.next_token(); // ;
+        let length = match self.current_token().unwrap().token_value().parse::<usize>() {
+            Ok(length) if self.current_token().unwrap().token_type() == TokenType::Number => length,
+            _ => {
+                return Err(compile_error!(
+                    "error",
+                    self.current_token().unwrap().line(),
+                    self.current_token().unwrap().column(),
+                    self.current_token().unwrap().span(),
+                    &self.input_path(),
+                    &self.input_content(),
+                    "Array length must be a non-negative integer literal, found {:?}",
+                    self.current_token().unwrap().token_value()
+                ))
+            }
+        };
+        self.next_token(); // 要素数
+        if self.current_token().unwrap().token_type() != TokenType::RightSquareBrace {
+            return Err(compile_error!(
+                "error",
+                self.current_token().unwrap().line(),
+                self.current_token().unwrap().column(),
+                self.current_token().unwrap().span(),
+                &self.input_path(),
+                &self.input_content(),
+                "Expected `]` to close the array type, found {:?}",
+                self.current_token().unwrap().token_value()
+            ));
+        }
+        self.next_token(); // ]
+        Ok(self.new_node(
+            start,
+            NodeValue::Expr(Expr::DataType(DataType::FixedArray(element_type, length))),
+            None,
+        ))
+    }
+
+    fn parse_type_declaration(&mut self, is_public: bool) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // type
+        let name_index = self.i;
         let _type_name = self.current_token().unwrap().token_value().clone();
         self.next_token(); // name
         self.next_token(); // =
         let value_node = self.expr()?;
 
-        Ok(Box::new(Node {
-            value: NodeValue::Statement(Statement::Declaration(Declaration::Type(
-                Box::new(Node::new(
-                    NodeValue::Expr(Expr::Variable(
-                        Parser::<'a>::new_null(
-                            self.current_token().unwrap().line(),
-                            self.current_token().unwrap().column(),
-                        ),
-                        _type_name,
-                        false,
-                        false,
-                        None,
-                    )),
-                    None,
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                )),
+        Ok(self.new_statement(
+            start,
+            NodeValue::Statement(Statement::Declaration(Declaration::Type(
+                self.new_name_node(name_index, _type_name),
                 value_node,
                 is_public,
             ))),
-            next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-            line: self.current_token().unwrap().line(),
-            column: self.current_token().unwrap().column(),
-            is_statement: self.is_statement,
-        }))
+            self.is_statement,
+        ))
     }
 
-    fn parse_const_declaration(&mut self, is_public: bool) -> R<Box<Node>, String> {
+    fn parse_const_declaration(&mut self, is_public: bool) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token();
+        let name_index = self.i;
         let var = self.current_token().unwrap().token_value().clone();
         let mut data_type = Box::new(Node::new(
             NodeValue::Expr(Expr::DataType(DataType::from(Parser::<'a>::new_null(
@@ -1363,33 +1730,17 @@ impl<'a> Parser<'a> {
                 }
             }
 
-            return Ok(Box::new(Node {
-                value: NodeValue::Statement(Statement::Declaration(Declaration::Const(
-                    Box::new(Node::new(
-                        NodeValue::Expr(Expr::Variable(
-                            Parser::<'a>::new_null(
-                                self.current_token().unwrap().line(),
-                                self.current_token().unwrap().column(),
-                            ),
-                            var,
-                            false,
-                            false,
-                            None,
-                        )),
-                        None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    )),
+            return Ok(self.new_statement(
+                start,
+                NodeValue::Statement(Statement::Declaration(Declaration::Const(
+                    self.new_name_node(name_index, var),
                     data_type,
                     value_node,
                     is_local,
                     is_public,
                 ))),
-                next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-                line: self.current_token().unwrap().line(),
-                column: self.current_token().unwrap().column(),
-                is_statement: self.is_statement,
-            }));
+                self.is_statement,
+            ));
         }
         self.next_token();
         if self.current_token().unwrap().token_type() == TokenType::Equals {
@@ -1411,33 +1762,17 @@ impl<'a> Parser<'a> {
                 }
             }
 
-            return Ok(Box::new(Node {
-                value: NodeValue::Statement(Statement::Declaration(Declaration::Const(
-                    Box::new(Node::new(
-                        NodeValue::Expr(Expr::Variable(
-                            Parser::<'a>::new_null(
-                                self.current_token().unwrap().line(),
-                                self.current_token().unwrap().column(),
-                            ),
-                            var,
-                            false,
-                            false,
-                            None,
-                        )),
-                        None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    )),
+            return Ok(self.new_statement(
+                start,
+                NodeValue::Statement(Statement::Declaration(Declaration::Const(
+                    self.new_name_node(name_index, var),
                     data_type,
                     value_node,
                     is_local,
                     is_public,
                 ))),
-                next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-                line: self.current_token().unwrap().line(),
-                column: self.current_token().unwrap().column(),
-                is_statement: self.is_statement,
-            }));
+                self.is_statement,
+            ));
         }
         value_node = self.expr()?;
         if self.current_token().unwrap().token_type() == TokenType::Semi {
@@ -1457,35 +1792,20 @@ impl<'a> Parser<'a> {
             }
         }
 
-        return Ok(Box::new(Node {
-            value: NodeValue::Statement(Statement::Declaration(Declaration::Const(
-                Box::new(Node::new(
-                    NodeValue::Expr(Expr::Variable(
-                        Parser::<'a>::new_null(
-                            self.current_token().unwrap().line(),
-                            self.current_token().unwrap().column(),
-                        ),
-                        var,
-                        false,
-                        false,
-                        None,
-                    )),
-                    None,
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                )),
+        return Ok(self.new_statement(
+            start,
+            NodeValue::Statement(Statement::Declaration(Declaration::Const(
+                self.new_name_node(name_index, var),
                 data_type,
                 value_node,
                 is_local,
                 is_public,
             ))),
-            next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-            line: self.current_token().unwrap().line(),
-            column: self.current_token().unwrap().column(),
-            is_statement: self.is_statement,
-        }));
+            self.is_statement,
+        ));
     }
-    fn parse_variable_declaration(&mut self, is_public: bool) -> R<Box<Node>, String> {
+    fn parse_variable_declaration(&mut self, is_public: bool) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token();
         let mut is_mutable = false;
         if let Some(token) = self.current_token() {
@@ -1494,6 +1814,7 @@ impl<'a> Parser<'a> {
                 is_mutable = true;
             }
         }
+        let name_index = self.i;
         let var = self.current_token().unwrap().token_value().clone();
         let mut data_type = Box::new(Node::new(
             NodeValue::Expr(Expr::DataType(DataType::from(Parser::<'a>::new_null(
@@ -1528,34 +1849,18 @@ impl<'a> Parser<'a> {
                 }
             }
 
-            return Ok(Box::new(Node {
-                value: NodeValue::Statement(Statement::Declaration(Declaration::Variable(
-                    Box::new(Node::new(
-                        NodeValue::Expr(Expr::Variable(
-                            Parser::<'a>::new_null(
-                                self.current_token().unwrap().line(),
-                                self.current_token().unwrap().column(),
-                            ),
-                            var,
-                            false,
-                            false,
-                            None,
-                        )),
-                        None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    )),
+            return Ok(self.new_statement(
+                start,
+                NodeValue::Statement(Statement::Declaration(Declaration::Variable(
+                    self.new_name_node(name_index, var),
                     data_type,
                     value_node,
                     is_local,
                     is_mutable,
                     is_public,
                 ))),
-                next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-                line: self.current_token().unwrap().line(),
-                column: self.current_token().unwrap().column(),
-                is_statement: self.is_statement,
-            }));
+                self.is_statement,
+            ));
         }
         self.next_token();
         if self.current_token().unwrap().token_type() == TokenType::Equals {
@@ -1577,34 +1882,18 @@ impl<'a> Parser<'a> {
                 }
             }
 
-            return Ok(Box::new(Node {
-                value: NodeValue::Statement(Statement::Declaration(Declaration::Variable(
-                    Box::new(Node::new(
-                        NodeValue::Expr(Expr::Variable(
-                            Parser::<'a>::new_null(
-                                self.current_token().unwrap().line(),
-                                self.current_token().unwrap().column(),
-                            ),
-                            var,
-                            false,
-                            false,
-                            None,
-                        )),
-                        None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    )),
+            return Ok(self.new_statement(
+                start,
+                NodeValue::Statement(Statement::Declaration(Declaration::Variable(
+                    self.new_name_node(name_index, var),
                     data_type,
                     value_node,
                     is_local,
                     is_mutable,
                     is_public,
                 ))),
-                next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-                line: self.current_token().unwrap().line(),
-                column: self.current_token().unwrap().column(),
-                is_statement: self.is_statement,
-            }));
+                self.is_statement,
+            ));
         }
         value_node = self.expr()?;
         if self.current_token().unwrap().token_type() == TokenType::Semi {
@@ -1624,37 +1913,22 @@ impl<'a> Parser<'a> {
             }
         }
 
-        return Ok(Box::new(Node {
-            value: NodeValue::Statement(Statement::Declaration(Declaration::Variable(
-                Box::new(Node::new(
-                    NodeValue::Expr(Expr::Variable(
-                        Parser::<'a>::new_null(
-                            self.current_token().unwrap().line(),
-                            self.current_token().unwrap().column(),
-                        ),
-                        var,
-                        false,
-                        false,
-                        None,
-                    )),
-                    None,
-                    self.current_token().unwrap().line(),
-                    self.current_token().unwrap().column(),
-                )),
+        return Ok(self.new_statement(
+            start,
+            NodeValue::Statement(Statement::Declaration(Declaration::Variable(
+                self.new_name_node(name_index, var),
                 data_type,
                 value_node,
                 is_local,
                 is_mutable,
                 is_public,
             ))),
-            next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-            line: self.current_token().unwrap().line(),
-            column: self.current_token().unwrap().column(),
-            is_statement: self.is_statement,
-        }));
+            self.is_statement,
+        ));
     }
 
-    fn parse_array(&mut self, data_type: &Box<Node>) -> R<Box<Node>, String> {
+    fn parse_array(&mut self, data_type: &Box<Node>) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // [ をスキップ
         let mut value_vec = vec![];
         while self.current_token().unwrap().token_type() != TokenType::RightSquareBrace {
@@ -1664,18 +1938,19 @@ impl<'a> Parser<'a> {
             }
         }
         self.next_token(); // ] をスキップ
-        Ok(Box::new(Node::new(
+        Ok(self.new_node(
+            start,
             NodeValue::Expr(Expr::DataType(DataType::Array(
                 data_type.clone(),
                 value_vec,
             ))),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        ))
     }
 
-    fn parse_assign_variable(&mut self) -> R<Box<Node>, String> {
+    fn parse_assign_variable(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
+        let name_index = self.i;
         let var = self.current_token().unwrap().token_value().clone();
         let data_type = Parser::<'a>::new_null(
             self.current_token().unwrap().line(),
@@ -1700,31 +1975,15 @@ impl<'a> Parser<'a> {
 
             value_node = self.expr()?;
 
-            Ok(Box::new(Node {
-                value: NodeValue::Expr(Expr::Assign(
-                    Box::new(Node::new(
-                        NodeValue::Expr(Expr::Variable(
-                            Parser::<'a>::new_null(
-                                self.current_token().unwrap().line(),
-                                self.current_token().unwrap().column(),
-                            ),
-                            var,
-                            false,
-                            false,
-                            None,
-                        )),
-                        None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    )),
+            Ok(self.new_statement(
+                start,
+                NodeValue::Expr(Expr::Assign(
+                    self.new_name_node(name_index, var),
                     value_node,
                     index,
                 )),
-                next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-                line: self.current_token().unwrap().line(),
-                column: self.current_token().unwrap().column(),
-                is_statement: self.is_statement,
-            }))
+                self.is_statement,
+            ))
         } else {
             self.next_token(); // =
 
@@ -1733,62 +1992,33 @@ impl<'a> Parser<'a> {
                 self.is_statement = true;
             }
 
-            Ok(Box::new(Node {
-                value: NodeValue::Expr(Expr::Assign(
-                    Box::new(Node::new(
-                        NodeValue::Expr(Expr::Variable(
-                            Parser::<'a>::new_null(
-                                self.current_token().unwrap().line(),
-                                self.current_token().unwrap().column(),
-                            ),
-                            var,
-                            false,
-                            false,
-                            None,
-                        )),
-                        None,
-                        self.current_token().unwrap().line(),
-                        self.current_token().unwrap().column(),
-                    )),
+            Ok(self.new_statement(
+                start,
+                NodeValue::Expr(Expr::Assign(
+                    self.new_name_node(name_index, var),
                     value_node,
                     index,
                 )),
-                next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-                line: self.current_token().unwrap().line(),
-                column: self.current_token().unwrap().column(),
-                is_statement: self.is_statement,
-            }))
+                self.is_statement,
+            ))
         }
     }
 
-    fn parse_return(&mut self) -> R<Box<Node>, String> {
+    fn parse_return(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token();
         let mut ret_value = Box::new(Node::default());
         ret_value = self.expr()?;
 
-        Ok(Box::new(Node {
-            value: NodeValue::Statement(Statement::ControlFlow(ControlFlow::Return(ret_value))),
-            next: Rc::new(RefCell::new(None)), // nextをRc<RefCell<Option<Box<Node>>>>で初期化
-            line: self.current_token().unwrap().line(),
-            column: self.current_token().unwrap().column(),
-            is_statement: self.is_statement,
-        }))
+        Ok(self.new_statement(
+            start,
+            NodeValue::Statement(Statement::ControlFlow(ControlFlow::Return(ret_value))),
+            self.is_statement,
+        ))
     }
 
-    fn parse_include(&mut self) -> R<Box<Node>, String> {
-        self.next_token(); // include
-        let include_file_path = self.current_token().unwrap().token_value().clone();
-        let include_node = Node::new(
-            NodeValue::Statement(Statement::Include(include_file_path)),
-            None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        );
-        self.next_token();
-
-        Ok(Box::new(include_node))
-    }
-    fn parse_impl_definition(&mut self, is_public: bool) -> R<Box<Node>, String> {
+    fn parse_impl_definition(&mut self, is_public: bool) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // impl
         let var = self.current_token().unwrap().token_value().clone();
         let mut member: Vec<Box<Node>> = Vec::new();
@@ -1801,20 +2031,29 @@ impl<'a> Parser<'a> {
                 member.push(member_value);
             }
             self.next_token(); // }
-            Ok(Box::new(Node::new(
+            Ok(self.new_node(
+                start,
                 NodeValue::Statement(Statement::Declaration(Declaration::Impl(
                     var.clone(),
                     member.clone(),
                 ))),
                 None,
+            ))
+        } else {
+            Err(compile_error!(
+                "error",
                 self.current_token().unwrap().line(),
                 self.current_token().unwrap().column(),
-            )))
-        } else {
-            Err(String::from(""))
+                self.current_token().unwrap().span(),
+                &self.input_path(),
+                &self.input_content(),
+                "Expected `{{` after `impl {}`",
+                var
+            ))
         }
     }
-    fn parse_struct_instance(&mut self, ident_token: &Token) -> R<Box<Node>, String> {
+    fn parse_struct_instance(&mut self, ident_token: &Token) -> R<Box<Node>, Diagnostic> {
+        let start = self.i - 1; // 構造体名は直前に読んだトークン
         let struct_name = ident_token.token_value().clone();
         let mut field_value = vec![];
         self.next_token(); // {
@@ -1841,14 +2080,19 @@ impl<'a> Parser<'a> {
         }
 
         //panic!("{:?} {:?}", self.current_token(), field_value);
-        Ok(Box::new(Node::new(
+        let mut node = self.new_node(
+            start,
             NodeValue::Expr(Expr::StructInstance(struct_name, field_value)),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )))
+        );
+        // 末尾の`,`の後の`}`はここでは読まないが、範囲には含める
+        if self.current_token().unwrap().token_type() == TokenType::RightCurlyBrace {
+            node.span = node.span.to(self.current_token().unwrap().span());
+        }
+        Ok(node)
     }
-    fn parse_struct_definition(&mut self, is_public: bool) -> R<Box<Node>, String> {
+    fn parse_struct_definition(&mut self, is_public: bool) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
         self.next_token(); // struct
         let var = self.current_token().unwrap().token_value().clone();
         let mut member: Vec<Box<Node>> = Vec::new();
@@ -1864,21 +2108,21 @@ impl<'a> Parser<'a> {
                 }
             }
             self.next_token(); // }
-            Ok(Box::new(Node::new(
+            Ok(self.new_node(
+                start,
                 NodeValue::Statement(Statement::Declaration(Declaration::Struct(
                     var.clone(),
                     member.clone(),
                     is_public,
                 ))),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            )))
+            ))
         } else {
             if self.current_token().unwrap().token_type() == TokenType::Semi {
                 self.is_statement = true;
             }
-            Ok(Box::new(Node::new(
+            Ok(self.new_node(
+                start,
                 NodeValue::Statement(Statement::Declaration(Declaration::Struct(
                     var.clone(),
                     vec![Parser::<'a>::new_null(
@@ -1888,55 +2132,115 @@ impl<'a> Parser<'a> {
                     is_public,
                 ))),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            )))
+            ))
         }
     }
 
-    fn parse_use(&mut self) -> R<Box<Node>, String> {
+    fn parse_use(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
+        let use_token = self.current_token().unwrap().clone();
         self.next_token(); // use
-        let scope_solution = self.expr()?;
-        return Ok(Box::new(Node::new(
-            NodeValue::Statement(Statement::Use(scope_solution)),
-            None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        )));
+        let ident_token = self.current_token().unwrap().clone();
+        if ident_token.token_type() != TokenType::Ident {
+            return Err(compile_error!(
+                "error",
+                ident_token.line(),
+                ident_token.column(),
+                ident_token.span(),
+                &self.input_path(),
+                &self.input_content(),
+                "Expected a path after `{}`, found {:?}",
+                use_token.token_value(),
+                ident_token.token_value()
+            ));
+        }
+        self.next_token(); // ident
+        let path = if self.current_token().unwrap().token_type() == TokenType::ScopeResolution {
+            self.parse_scope_resolution(&ident_token)?
+        } else {
+            Parser::<'a>::new_path_segment(&ident_token)
+        };
+        Ok(self.new_node(start, NodeValue::Statement(Statement::Use(path)), None))
     }
-    fn parse_member_access(&mut self, ident_token: &Token) -> R<Box<Node>, String> {
+
+    // `mod 名前;`(別ファイルのモジュール)または`mod 名前 { .. }`(モジュール定義)
+    fn parse_mod_declaration(&mut self) -> R<Box<Node>, Diagnostic> {
+        let start = self.i;
+        let mod_token = self.current_token().unwrap().clone();
+        self.next_token(); // mod
+        let name = self.current_token().unwrap().token_value();
+        self.next_token(); // name
+        let value = match self.current_token().unwrap().token_type() {
+            TokenType::LeftCurlyBrace => {
+                self.next_token(); // {
+                let mut items: Vec<Box<Node>> = Vec::new();
+                while self.current_token().unwrap().token_type() != TokenType::RightCurlyBrace {
+                    if self.current_token().unwrap().token_type() == TokenType::Eof {
+                        return Err(compile_error!(
+                            "error",
+                            mod_token.line(),
+                            mod_token.column(),
+                            mod_token.span(),
+                            &self.input_path(),
+                            &self.input_content(),
+                            "Unclosed `{{` in the definition of module `{}`",
+                            name
+                        ));
+                    }
+                    items.push(self.parse_single_statement().unwrap()?);
+                }
+                self.next_token(); // }
+                Statement::ModDeclaration(name, items)
+            }
+            TokenType::Semi => Statement::Mod(name),
+            _ => {
+                return Err(compile_error!(
+                    "error",
+                    self.current_token().unwrap().line(),
+                    self.current_token().unwrap().column(),
+                    self.current_token().unwrap().span(),
+                    &self.input_path(),
+                    &self.input_content(),
+                    "Expected `;` or `{{` after `{} {}`, found {:?}",
+                    mod_token.token_value(),
+                    name,
+                    self.current_token().unwrap().token_value()
+                ));
+            }
+        };
+        Ok(self.new_node(start, NodeValue::Statement(value), None))
+    }
+    fn parse_member_access(&mut self, ident_token: &Token) -> R<Box<Node>, Diagnostic> {
+        let start = self.i - 1; // ident_tokenは直前に読んだトークン
         let member = if let Ok(v) = ident_token.token_value().parse::<bool>() {
-            Box::new(Node::new(
+            self.new_node(
+                start,
                 NodeValue::Expr(Expr::DataType(DataType::String(ident_token.token_value()))),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ))
+            )
         } else if let Ok(number) = ident_token.token_value().parse::<i64>() {
-            Box::new(Node::new(
+            self.new_node(
+                start,
                 NodeValue::Expr(Expr::DataType(DataType::Int(number))),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ))
+            )
         } else if let Ok(number) = ident_token.token_value().parse::<f64>() {
-            Box::new(Node::new(
+            self.new_node(
+                start,
                 NodeValue::Expr(Expr::DataType(DataType::Float(number))),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ))
+            )
         } else if ident_token.token_type() == TokenType::DoubleQuote
             || ident_token.token_type() == TokenType::SingleQuote
         {
-            Box::new(Node::new(
+            self.new_node(
+                start,
                 NodeValue::Expr(Expr::DataType(DataType::String(ident_token.token_value()))),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ))
+            )
         } else {
-            Box::new(Node::new(
+            self.new_node(
+                start,
                 NodeValue::Expr(Expr::Variable(
                     Box::new(Node::default()),
                     ident_token.token_value(),
@@ -1945,36 +2249,54 @@ impl<'a> Parser<'a> {
                     None,
                 )),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ))
+            )
         };
         self.next_token();
-        let item = self.expr()?;
-        let mut node = Box::new(Node::new(
+        let item = self.parse_member_name()?;
+        let mut node = self.new_node(
+            start,
             NodeValue::Expr(Expr::MemberAccess(member.clone(), item)),
             None,
-            self.current_token().unwrap().line(),
-            self.current_token().unwrap().column(),
-        ));
+        );
 
         while self.current_token().unwrap().token_type() == TokenType::Dot {
             self.next_token();
-            let next_item = self.expr()?;
-            node = Box::new(Node::new(
+            let next_item = self.parse_member_name()?;
+            node = self.new_node(
+                start,
                 NodeValue::Expr(Expr::MemberAccess(node.clone(), next_item)),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            ));
+            );
         }
 
         Ok(node)
     }
-    fn parse_primitive_type(&mut self, token: &Token) -> R<Box<Node>, String> {
+    // `.`の後のメンバ名(`a.b + 1`の`+ 1`はメンバに含めない)
+    fn parse_member_name(&mut self) -> R<Box<Node>, Diagnostic> {
+        let token = self.current_token().unwrap().clone();
+        if token.token_type() != TokenType::Ident {
+            return Err(compile_error!(
+                "error",
+                token.line(),
+                token.column(),
+                token.span(),
+                &self.input_path(),
+                &self.input_content(),
+                "Expected a member name after `.`, found {:?}",
+                token.token_value()
+            ));
+        }
+        if self.peek_next_token(1).unwrap().token_type() == TokenType::LeftParen {
+            return self.factor();
+        }
+        self.next_token();
+        Ok(self.new_name_node(self.i - 1, token.token_value()))
+    }
+    fn parse_primitive_type(&mut self, token: &Token) -> R<Box<Node>, Diagnostic> {
         Ok(Box::new(Node::default()))
     }
-    pub fn parse_single_statement(&mut self) -> Option<R<Box<Node>, String>> {
+    pub fn parse_single_statement(&mut self) -> Option<R<Box<Node>, Diagnostic>> {
+        let start = self.i;
         let mut is_public = false;
         if (Keywords::ACCESS_PUB_KEYWORD
             .contains(&&self.current_token().unwrap().token_value().as_str()))
@@ -1994,6 +2316,14 @@ impl<'a> Parser<'a> {
             .contains(&&self.current_token().unwrap().token_value().as_str()))
         {
             self.parse_use()
+        } else if self.current_token().unwrap().token_type() == TokenType::Ident
+            && (Keywords::MODULE_DECLARATION_KEYWORD
+                .contains(&&self.current_token().unwrap().token_value().as_str()))
+            && self
+                .peek_next_token(1)
+                .is_some_and(|token| token.token_type() == TokenType::Ident)
+        {
+            self.parse_mod_declaration()
         } else if (Keywords::DECLARATION_IMPL_KEYWORD
             .contains(&&self.current_token().unwrap().token_value().as_str()))
         {
@@ -2032,15 +2362,15 @@ impl<'a> Parser<'a> {
         {
             self.parse_const_declaration(is_public)
         } else if self.current_token().unwrap().token_type() == TokenType::Ident
-            && self.peek_next_token(2).unwrap().token_type() == TokenType::Equals
             && (Keywords::DECLARATION_TYPE_KEYWORD
                 .contains(&&self.current_token().unwrap().token_value().as_str()))
+            && self
+                .peek_next_token(2)
+                .is_some_and(|token| token.token_type() == TokenType::Equals)
         {
             self.parse_type_declaration(is_public)
         } else if self.current_token().unwrap().token_type() == TokenType::Ident
             && self.peek_next_token(1).unwrap().token_type() == TokenType::Equals
-            || self.current_token().unwrap().token_type() == TokenType::Ident
-                && self.peek_next_token(1).unwrap().token_type() == TokenType::LeftSquareBrace
         {
             self.parse_assign_variable()
         } else if self.current_token().unwrap().token_type() == TokenType::Ident
@@ -2058,26 +2388,19 @@ impl<'a> Parser<'a> {
                 .contains(&&self.current_token().unwrap().token_value().as_str()))
         {
             self.parse_continue()
-        }
-        // 途中で廃止
-        else if self.current_token().unwrap().token_type() == TokenType::Ident
-            && self.current_token().unwrap().token_value() == "include"
-        {
-            self.parse_include()
         } else if self.current_token().unwrap().token_type() == TokenType::LeftCurlyBrace {
             self.parse_block()
         } else if self.current_token().unwrap().token_type() == TokenType::Semi {
             self.is_statement = true;
             self.next_token();
-            return Some(Ok(Box::new(Node::new(
+            return Some(Ok(self.new_node(
+                start,
                 NodeValue::Expr(Expr::EndStatement),
                 None,
-                self.current_token().unwrap().line(),
-                self.current_token().unwrap().column(),
-            )))); // ステートメントを終了
+            ))); // ステートメントを終了
         } else {
             self.is_statement = false;
-            self.expr()
+            self.parse_assignment_expr()
         };
 
         Some(result)
@@ -2086,11 +2409,11 @@ impl<'a> Parser<'a> {
     fn parse_statement_recursive(
         &mut self,
         previous_node: Option<Box<Node>>,
-    ) -> R<Box<Node>, String> {
+    ) -> R<Box<Node>, Diagnostic> {
         if self.current_token().unwrap().token_type() == TokenType::Eof
             || self.current_token().unwrap().token_type() == TokenType::RightCurlyBrace
         {
-            return previous_node.ok_or("No statements found".to_string());
+            return previous_node.ok_or_else(|| Diagnostic::error("No statements found"));
         }
 
         if let Some(statement) = self.parse_single_statement() {
@@ -2117,14 +2440,22 @@ impl<'a> Parser<'a> {
                 self.parse_statement_recursive(Some(node))
             }
         } else {
-            Err("Failed to parse statement".to_string())
+            Err(compile_error!(
+                "error",
+                self.current_token().unwrap().line(),
+                self.current_token().unwrap().column(),
+                self.current_token().unwrap().span(),
+                &self.input_path(),
+                &self.input_content(),
+                "Failed to parse statement"
+            ))
         }
     }
 
-    fn parse_statement(&mut self) -> R<Box<Node>, String> {
+    fn parse_statement(&mut self) -> R<Box<Node>, Diagnostic> {
         self.parse_statement_recursive(None)
     }
-    pub fn parse(&mut self) -> R<Box<Node>, String> {
+    pub fn parse(&mut self) -> R<Box<Node>, Diagnostic> {
         self.parse_statement()
     }
 }
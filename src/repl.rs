@@ -0,0 +1,355 @@
+use crate::decoder::ir::{Decoder, GlobalVariable};
+use crate::decoder::jit::{HostFunction, Jit};
+use crate::driver::{format_ast, format_tokens};
+use crate::error::{CompilerError, Diagnostic};
+use crate::lexer::preprocess::Preprocessor;
+use crate::lexer::tokenizer::{Lexer, Token};
+use crate::parser::syntax::{Node, Parser};
+use crate::source::SourceMap;
+use crate::types::{Declaration, NodeValue, Statement, TokenType, R};
+
+extern crate inkwell as iw;
+use iw::{context::Context, module::Module, OptimizationLevel};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+// エラー表示に使う入力元の名前
+pub const REPL_INPUT_PATH: &str = "<repl>";
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+
+const HELP: &str = "\
+Enter statements to run them; the value of a trailing expression (without `;`) is printed.
+Input continues on the next line while braces are unbalanced.
+
+Commands:
+  :tokens <code>   Print the tokens of <code>
+  :ast <code>      Print the syntax tree of <code>
+  :ir [code]       Print the LLVM IR of <code> without running it, or of the last entry
+  :help            Print this help
+  :quit            Exit the REPL
+";
+
+extern "C" {
+    // スクリプトのprintfはCのバッファに書かれるので、REPLの表示と順序が入れ替わらないよう吐き出す
+    fn fflush(stream: *mut c_void) -> i32;
+}
+
+// 入力ごとに新しいモジュールを生成し、同じJITに追加して実行する
+// 前の入力で定義した関数・構造体とトップレベルの変数は、外部宣言として次のモジュールから参照する
+// 名前解決と型検査はプログラム全体を対象にするため、REPLではデコーダのエラー検出に任せる
+pub struct Repl<'ctx> {
+    context: &'ctx Context,
+    opt_level: OptimizationLevel,
+    host_functions: Vec<HostFunction>,
+    jit: Option<Jit<'ctx>>,
+    modules: Vec<Module<'ctx>>,         // JITに追加したモジュール(JITより先に破棄しない)
+    definitions: Vec<Node>,             // 実行済みの入力に含まれていた関数・構造体・implの定義
+    globals: Vec<GlobalVariable<'ctx>>, // 実行済みの入力で定義したトップレベルの変数
+    last_ir: Option<String>,            // 直前に実行した入力のIR
+    source_map: SourceMap,              // これまでの入力(入力ごとに別のファイルとして登録する)
+}
+
+impl<'ctx> Repl<'ctx> {
+    pub fn new(
+        context: &'ctx Context,
+        opt_level: OptimizationLevel,
+        host_functions: &[HostFunction],
+    ) -> Self {
+        Repl {
+            context,
+            opt_level,
+            host_functions: host_functions.to_vec(),
+            jit: None,
+            modules: Vec::new(),
+            definitions: Vec::new(),
+            globals: Vec::new(),
+            last_ir: None,
+            source_map: SourceMap::new(),
+        }
+    }
+
+    // 標準入力から1入力ずつ読んで実行する(EOFか:quitで終了)
+    pub fn run(&mut self) -> R<(), String> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        let mut buffer = String::new();
+        loop {
+            print!("{}", if buffer.is_empty() { PROMPT } else { CONTINUATION_PROMPT });
+            io::stdout().flush().map_err(|e| e.to_string())?;
+            let line = match lines.next() {
+                Some(line) => line.map_err(|e| e.to_string())?,
+                None => {
+                    println!();
+                    return Ok(());
+                }
+            };
+            if buffer.is_empty() {
+                let command = line.trim();
+                if command.is_empty() {
+                    continue;
+                }
+                if command.starts_with(':') {
+                    match self.command(command) {
+                        Ok(true) => continue,
+                        Ok(false) => return Ok(()),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            continue;
+                        }
+                    }
+                }
+            }
+            buffer.push_str(&line);
+            buffer.push('\n');
+
+            // 波括弧が閉じていなければ次の行を読み足す
+            let tokens = match self.tokenize(&buffer) {
+                Ok(tokens) => tokens,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    buffer.clear();
+                    continue;
+                }
+            };
+            if Repl::brace_depth(&tokens) > 0 {
+                continue;
+            }
+            let source = std::mem::take(&mut buffer);
+            if let Err(e) = self.eval(&source) {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    // `:`で始まるコマンドを処理する(falseならREPLを終了する)
+    fn command(&mut self, line: &str) -> R<bool, String> {
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+        match name {
+            ":q" | ":quit" | ":exit" => return Ok(false),
+            ":h" | ":help" => print!("{}", HELP),
+            ":tokens" => print!("{}", format_tokens(&self.tokenize(argument)?, &self.source_map)),
+            ":ast" => {
+                let tokens = self.tokenize(argument)?;
+                for statement in self.parse(&tokens, argument)? {
+                    print!("{}", format_ast(&statement));
+                }
+            }
+            ":ir" if argument.is_empty() => match &self.last_ir {
+                Some(ir) => print!("{}", ir),
+                None => println!("No entry has been run yet"),
+            },
+            ":ir" => {
+                let tokens = self.tokenize(argument)?;
+                let statements = self.parse(&tokens, argument)?;
+                let (module, _) = self.compile(argument, &statements)?;
+                print!("{}", module.print_to_string().to_string());
+            }
+            _ => {
+                return Err(format!(
+                    "error: Unknown command `{}` (type :help for a list of commands)",
+                    name
+                ))
+            }
+        }
+        Ok(true)
+    }
+
+    // 1入力分のソースをコンパイルしてJITで実行する
+    pub fn eval(&mut self, source: &str) -> R<(), String> {
+        let tokens = self.tokenize(source)?;
+        let statements = self.parse(&tokens, source)?;
+        if statements.is_empty() {
+            return Ok(());
+        }
+        let definitions: Vec<Node> = statements
+            .iter()
+            .filter(|statement| Repl::is_definition(statement))
+            .map(Repl::detach)
+            .collect();
+        for definition in &definitions {
+            if let Some(name) = Repl::definition_name(definition) {
+                let is_defined = self
+                    .definitions
+                    .iter()
+                    .any(|previous| Repl::definition_name(previous).as_deref() == Some(&name));
+                if is_defined {
+                    return Err(format!(
+                        "error: `{}` is already defined in this session and cannot be redefined",
+                        name
+                    ));
+                }
+            }
+        }
+
+        let (module, globals) = self.compile(source, &statements)?;
+        match &self.jit {
+            Some(jit) => jit.add_module(&module)?,
+            None => self.jit = Some(Jit::new(&module, self.opt_level, &self.host_functions)?),
+        }
+        // JITに追加したモジュールは実行に失敗しても残るので、ここで記録して
+        // 次の入力が同じエントリ関数名を使わないようにする
+        let entry_name = self.entry_name();
+        let has_entry = module
+            .get_function(&entry_name)
+            .is_some_and(|function| function.count_basic_blocks() > 0);
+        let ir = module.print_to_string().to_string();
+        self.modules.push(module);
+        if has_entry {
+            if let Some(jit) = &self.jit {
+                jit.run_function(&entry_name)?;
+            }
+            unsafe {
+                fflush(std::ptr::null_mut());
+            }
+        }
+
+        // 実行できた入力の定義と変数だけを次の入力に引き継ぐ
+        self.definitions.extend(definitions);
+        for global in globals {
+            self.globals.retain(|previous| previous.name != global.name);
+            self.globals.push(global);
+        }
+        self.last_ir = Some(ir);
+        Ok(())
+    }
+
+    // 入力ごとに異なるエントリ関数名(JITに追加したモジュール数で区別する)
+    fn entry_name(&self) -> String {
+        format!("repl.{}", self.modules.len())
+    }
+
+    // これまでの定義と変数を外部宣言として取り込んだ新しいモジュールを生成する
+    fn compile(
+        &self,
+        source: &str,
+        statements: &[Node],
+    ) -> R<(Module<'ctx>, Vec<GlobalVariable<'ctx>>), String> {
+        let entry_name = self.entry_name();
+        let module = self.context.create_module(&entry_name);
+        let builder = self.context.create_builder();
+        let mut decoder = Decoder::new(REPL_INPUT_PATH, source.to_string());
+        decoder.set_source_map(&self.source_map);
+        decoder.set_entry_function_name(&entry_name);
+        decoder.set_incremental(true);
+        decoder.set_print_result(true);
+        for host_function in &self.host_functions {
+            host_function.register(&mut decoder);
+        }
+        if let Some(definitions) = Repl::link(self.definitions.iter().map(Repl::detach))
+        {
+            decoder
+                .declare_definitions(self.context, &module, &definitions)
+                .map_err(|e| self.report(e))?;
+        }
+        for global in &self.globals {
+            decoder.import_global_variable(&module, global);
+        }
+        if let Some(program) = Repl::link(statements.iter().cloned()) {
+            decoder
+                .decode_program(&builder, self.context, &module, &program)
+                .map_err(|e| self.report(e))?;
+        }
+        module
+            .verify()
+            .map_err(|e| format!("error: Generated invalid LLVM IR: {}", e))?;
+        Ok((module, decoder.global_variables().to_vec()))
+    }
+
+    // includeのパスはカレントディレクトリからの相対パスになる
+    fn tokenize(&self, source: &str) -> R<Vec<Token>, String> {
+        let mut lexer = Lexer::new();
+        lexer.set_input_path(REPL_INPUT_PATH.to_string());
+        lexer.set_input_content(source.to_string());
+        lexer.set_source_map(self.source_map.clone());
+        lexer
+            .tokenize()
+            .and_then(|tokens| {
+                Preprocessor::new(&self.source_map).preprocess(Path::new(REPL_INPUT_PATH), tokens)
+            })
+            .map_err(|e| self.report(e))
+    }
+
+    // 入力を1文ずつ解析する
+    fn parse(&self, tokens: &Vec<Token>, source: &str) -> R<Vec<Node>, String> {
+        let mut parser = Parser::new(tokens, REPL_INPUT_PATH, source.to_string());
+        parser.set_source_map(&self.source_map);
+        let mut statements = Vec::new();
+        while !parser.is_eof() {
+            match parser.parse_single_statement() {
+                Some(statement) => statements.push(*statement.map_err(|e| self.report(e))?),
+                None => break,
+            }
+        }
+        Ok(statements)
+    }
+
+    // 診断を表示する文字列(前の入力やincludeしたファイルの範囲もSourceMapから表示する)
+    fn report(&self, diagnostic: Diagnostic) -> String {
+        let mut errors = CompilerError::new();
+        errors.set_source_map(&self.source_map);
+        errors.push(diagnostic);
+        errors.format_error_string(REPL_INPUT_PATH, "")
+    }
+
+    // 閉じていない波括弧の数
+    fn brace_depth(tokens: &[Token]) -> i32 {
+        tokens.iter().fold(0, |depth, token| match token.token_type() {
+            TokenType::LeftCurlyBrace => depth + 1,
+            TokenType::RightCurlyBrace => depth - 1,
+            _ => depth,
+        })
+    }
+
+    // 文を1つのチェーンにつなぐ(各文が既に持つelse節などのチェーンの末尾に次の文をつなぐ)
+    fn link(statements: impl Iterator<Item = Node>) -> Option<Box<Node>> {
+        let mut head: Option<Box<Node>> = None;
+        for statement in statements.map(Box::new) {
+            match &head {
+                None => head = Some(statement),
+                Some(head) => {
+                    let mut last = head.clone();
+                    loop {
+                        let next = last.next.borrow().clone();
+                        match next {
+                            Some(next) => last = next,
+                            None => break,
+                        }
+                    }
+                    last.set_next(Rc::new(RefCell::new(Some(statement))));
+                }
+            }
+        }
+        head
+    }
+
+    // 後続の文とのつながりを持たない定義のコピー(linkでつなぎ直しても元のチェーンを壊さない)
+    fn detach(node: &Node) -> Node {
+        Node::new(node.value.clone(), None, node.line, node.column)
+    }
+
+    fn is_definition(node: &Node) -> bool {
+        matches!(
+            node.value,
+            NodeValue::Statement(Statement::Declaration(Declaration::Function(..)))
+                | NodeValue::Statement(Statement::Declaration(Declaration::Struct(..)))
+                | NodeValue::Statement(Statement::Declaration(Declaration::Impl(..)))
+        )
+    }
+
+    fn definition_name(node: &Node) -> Option<String> {
+        match node.value {
+            NodeValue::Statement(Statement::Declaration(Declaration::Function(ref name, ..)))
+            | NodeValue::Statement(Statement::Declaration(Declaration::Struct(ref name, ..))) => {
+                Some(name.clone())
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// SourceMapに登録したファイルの番号
+pub type FileId = usize;
+
+// ファイル内のバイト範囲[start, end)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(file: FileId, start: usize, end: usize) -> Self {
+        Span { file, start, end }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    // selfの先頭からotherの末尾までの範囲(別のファイルならselfのまま)
+    pub fn to(&self, other: Span) -> Span {
+        if self.file != other.file || other.is_empty() {
+            return *self;
+        }
+        if self.is_empty() {
+            return other;
+        }
+        Span::new(self.file, self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+// 登録したソースファイル(行の先頭のバイト位置を持ち、位置と行・列を変換する)
+#[derive(Debug)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub path: String,
+    pub content: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(id: FileId, path: String, content: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceFile {
+            id,
+            path,
+            content,
+            line_starts,
+        }
+    }
+
+    // バイト位置の(行, 列)(どちらも1始まりで、列は文字単位、字句解析器と同じく`\r`は数えない)
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.content.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let start = self.line_starts[line - 1];
+        let column = self.content[start..offset]
+            .chars()
+            .filter(|&c| c != '\r')
+            .count()
+            + 1;
+        (line, column)
+    }
+
+    // (行, 列)のバイト位置(範囲外は行末・ファイル末尾に丸める)
+    pub fn offset(&self, line: usize, column: usize) -> usize {
+        let start = match self.line_starts.get(line.max(1) - 1) {
+            Some(&start) => start,
+            None => return self.content.len(),
+        };
+        let mut remaining = column.max(1) - 1;
+        for (i, c) in self.content[start..].char_indices() {
+            if remaining == 0 || c == '\n' {
+                return start + i;
+            }
+            if c != '\r' {
+                remaining -= 1;
+            }
+        }
+        self.content.len()
+    }
+
+    // 行の内容(改行を含まない)
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.content.len(), |&next| next - 1);
+        Some(self.content[start..end].trim_end_matches('\r'))
+    }
+}
+
+// コンパイル中に読み込んだ全ファイル
+// 字句解析器・プリプロセッサ・モジュールの読み込みで共有するため、複製しても同じ表を指す
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Rc<RefCell<Vec<Rc<SourceFile>>>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    pub fn add_file(&self, path: &str, content: &str) -> FileId {
+        let mut files = self.files.borrow_mut();
+        let id = files.len();
+        files.push(Rc::new(SourceFile::new(
+            id,
+            path.to_string(),
+            content.to_string(),
+        )));
+        id
+    }
+
+    pub fn file(&self, id: FileId) -> Option<Rc<SourceFile>> {
+        self.files.borrow().get(id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.borrow().is_empty()
+    }
+}
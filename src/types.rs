@@ -72,6 +72,23 @@ impl Keywords {
 }
 #[cfg(any(feature = "full", feature = "parser"))]
 use crate::parser::syntax::Node;
+#[cfg(any(feature = "full", feature = "parser"))]
+use crate::source::Span;
+
+// 関数の引数(型, 引数名, 引数名の範囲)
+#[cfg(any(feature = "full", feature = "parser"))]
+pub type FunctionArg = (Box<Node>, String, Span);
+
+// ホスト(Rust)側から`@名前`で呼べるよう登録する関数の引数・戻り値の型
+// Strは引数専用で、NUL終端の文字列ポインタ(*const c_char)として渡される
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum HostType {
+    I32,
+    I64,
+    F64,
+    Str,
+    Unit,
+}
 // トークンの種類
 #[derive(PartialEq, Debug, Clone)]
 pub enum TokenType {
@@ -127,6 +144,7 @@ pub enum TokenType {
     RightArrow,                                // 右矢印
     Eof,                                       // トークンの終わり
     Range,                                     // 範囲指定
+    RangeInclusive,                            // 終端を含む範囲指定
     ScopeResolution,                           //  スコープ解決
 }
 
@@ -171,8 +189,8 @@ pub enum Operator {
     Mul(Box<Node>, Box<Node>),              // 乗算(左辺, 右辺)
     Div(Box<Node>, Box<Node>),              // 除算(左辺, 右辺)
     Modulus(Box<Node>, Box<Node>),          // 余り(左辺, 右辺)
-    Increment(Box<Node>),                   // 増加(左辺)
-    Decrement(Box<Node>),                   // 減少(左辺)
+    Increment(Box<Node>, bool),             // 増加(対象, 前置フラグ)
+    Decrement(Box<Node>, bool),             // 減少(対象, 前置フラグ)
     AddAssign(Box<Node>, Box<Node>),        // 加算代入(左辺, 右辺)
     SubAssign(Box<Node>, Box<Node>),        // 減算代入(左辺, 右辺)
     MulAssign(Box<Node>, Box<Node>),        // 乗算代入(左辺, 右辺)
@@ -189,7 +207,7 @@ pub enum Operator {
     BitXorAssign(Box<Node>, Box<Node>),     // ビット単位の排他的論理和と代入(左辺, 右辺)
     ShiftLeftAssign(Box<Node>, Box<Node>),  // ビットシフト左と代入(左辺, 右辺)
     ShiftRightAssign(Box<Node>, Box<Node>), // ビットシフト右と代入(左辺, 右辺)
-    Range(Box<Node>, Box<Node>),            // 範囲指定(左辺, 右辺)
+    Range(Box<Node>, Box<Node>, Box<Node>, bool), // 範囲指定(始点, 終点, 増分, 終端を含むか)
 }
 
 // 基本型
@@ -203,6 +221,7 @@ pub enum DataType {
     Unit(()),                         // Unit値(())
     Generic(String, Vec<String>),     // ジェネリック型(ジェネリック名, パラメータリスト)
     Array(Box<Node>, Vec<Box<Node>>), // 配列(型名, 値)
+    FixedArray(Box<Node>, usize),     // 固定長配列型(要素の型, 要素数)
     Null,                             // 値なし
 }
 
@@ -214,16 +233,9 @@ pub enum Declaration {
     Variable(Box<Node>, Box<Node>, Box<Node>, bool, bool, bool), // 変数定義(変数, 型, 値, 可変フラグ, 参照フラグ, パブリックフラグ)
     Struct(String, Vec<Box<Node>>, bool),                        // 構造体定義()
     Impl(String, Vec<Box<Node>>),                                // 構造体実装()
-    Function(
-        String,
-        Vec<(Box<Node>, String)>,
-        Box<Node>,
-        Box<Node>,
-        bool,
-        bool,
-    ), // 関数定義(関数名, 引数, 戻り値の型, ボディ, システム関数フラグ, パブリックフラグ)
-    CallBackFunction(String, Vec<(Box<Node>, String)>, Box<Node>, Box<Node>, bool), // コールバック関数定義()
-    Type(Box<Node>, Box<Node>, bool),                                               // 型別名定義
+    Function(String, Vec<FunctionArg>, Box<Node>, Box<Node>, bool, bool), // 関数定義(関数名, 引数, 戻り値の型, ボディ, システム関数フラグ, パブリックフラグ)
+    CallBackFunction(String, Vec<FunctionArg>, Box<Node>, Box<Node>, bool), // コールバック関数定義()
+    Type(Box<Node>, Box<Node>, bool),                                       // 型別名定義
     UserSyntax(String, Box<Node>), // ユーザー定義構文(構文名, 構文)
 }
 
@@ -233,7 +245,6 @@ pub enum Declaration {
 pub enum Statement {
     ControlFlow(ControlFlow),               // 制御フロー
     Declaration(Declaration),               // 定義
-    Include(String),                        // インクルード
     Use(Box<Node>),                         // インポート宣言()
     Mod(String),                            // モジュール宣言
     ModDeclaration(String, Vec<Box<Node>>), // モジュール定義
@@ -252,6 +263,7 @@ pub enum Expr {
     ScopeResolution(Vec<Box<Node>>),                              // スコープ解決
     Use(Box<Node>),                                               // インポート宣言()
     MemberAccess(Box<Node>, Box<Node>),                           // メンバアクセス演算子
+    Index(Box<Node>, Box<Node>),                                  // 添字アクセス(対象, 添字)
     StructInstance(String, Vec<(String, Box<Node>)>), // 構造体インスタンス(構造体名, フィールド値のリスト(名前, 値))
     EndStatement,                                     // ステートメントの終わり
 }
@@ -0,0 +1,108 @@
+// ソースをJITで実行し、生成したコードの動作(mainの戻り値)を確かめる
+#![cfg(any(
+    feature = "full",
+    all(feature = "lexer", feature = "parser", feature = "decoder")
+))]
+
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tanucc_script_ir::driver::{run_jit, Action};
+
+// srcを一時ファイルに書き、`tanucc run`と同じようにコンパイルして実行する
+fn run(src: &str) -> Result<i32, String> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = env::temp_dir().join(format!(
+        "tanucc-codegen-{}-{}.tanu",
+        process::id(),
+        COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::write(&path, src).expect("failed to write the input");
+    let args = vec!["run".to_string(), path.to_string_lossy().to_string()];
+    let result = match Action::parse(args) {
+        Ok(Action::Run(options)) => run_jit(&options, &[]),
+        _ => panic!("failed to parse the arguments"),
+    };
+    let _ = fs::remove_file(&path);
+    result
+}
+
+#[test]
+fn f32_and_integer_operands() {
+    let src = "\
+fn main() -> i32 {
+    let x: f32 = 1.5;
+    let y = x + 1;
+    if y > 2.4 {
+        return 1;
+    }
+    return 0;
+}
+";
+    assert_eq!(run(src), Ok(1));
+}
+
+#[test]
+fn f32_and_f64_operands() {
+    let src = "\
+fn main() -> i32 {
+    let a: f32 = 1.5;
+    let b: f64 = 2.25;
+    let c = a + b;
+    if a < b && c == 3.75 {
+        return 1;
+    }
+    return 0;
+}
+";
+    assert_eq!(run(src), Ok(1));
+}
+
+#[test]
+fn inclusive_range_ending_at_the_maximum() {
+    let src = "\
+fn main() -> i32 {
+    let a: i8 = 120;
+    let b: i8 = 127;
+    let mut count = 0;
+    for _i in a..=b {
+        count += 1;
+    }
+    return count;
+}
+";
+    assert_eq!(run(src), Ok(8));
+}
+
+#[test]
+fn range_step_past_the_maximum() {
+    let src = "\
+fn main() -> i32 {
+    let a: i8 = 0;
+    let b: i8 = 127;
+    let s: i8 = 100;
+    let mut count = 0;
+    for _i in a..b step s {
+        count += 1;
+    }
+    return count;
+}
+";
+    assert_eq!(run(src), Ok(2));
+}
+
+#[test]
+fn zero_range_step() {
+    let src = "\
+fn main() -> i32 {
+    let mut count = 0;
+    for _i in 0..10 step 0 {
+        count += 1;
+    }
+    return count;
+}
+";
+    let error = run(src).unwrap_err();
+    assert!(error.contains("Range step cannot be zero"), "{}", error);
+}
@@ -0,0 +1,134 @@
+// Diagnosticの下線・ラベル・提案の表示と、複数の診断の蓄積を確かめる
+use tanucc_script_ir::error::{CompilerError, Diagnostic, TYPE_ERROR, UNDEFINED_VARIABLE};
+use tanucc_script_ir::source::{SourceMap, Span};
+
+const INPUT_PATH: &str = "test.tanu";
+
+fn render(src: &str, diagnostics: Vec<Diagnostic>) -> String {
+    colored::control::set_override(false);
+    let source_map = SourceMap::new();
+    source_map.add_file(INPUT_PATH, src);
+    let mut errors = CompilerError::new();
+    errors.set_source_map(&source_map);
+    errors.extend(diagnostics);
+    errors.format_error_string(INPUT_PATH, src)
+}
+
+// srcの中で最初に現れるtextの範囲
+fn span_of(src: &str, text: &str) -> Span {
+    let start = src.find(text).unwrap();
+    Span::new(0, start, start + text.len())
+}
+
+#[test]
+fn underlines_the_whole_range() {
+    let src = "let x = 1;\nlet total: i64 = 1.5;";
+    let diagnostic = Diagnostic::error("Mismatched types")
+        .with_code(&TYPE_ERROR)
+        .with_span_label(span_of(src, "1.5"), "expected `i64`, found `f64`")
+        .with_label(span_of(src, "i64"), "expected due to this type")
+        .with_note("floating point values are not implicitly converted to integers");
+    assert_eq!(
+        render(src, vec![diagnostic]),
+        "error[E1120]: Mismatched types\n\
+         \x20--> test.tanu:2:18\n\
+         \x20 |\n\
+         2 | let total: i64 = 1.5;\n\
+         \x20 |                  ^^^ expected `i64`, found `f64`\n\
+         \x20 |            --- expected due to this type\n\
+         \x20 = note: floating point values are not implicitly converted to integers\n"
+    );
+}
+
+#[test]
+fn multi_line_ranges_are_underlined_on_every_line() {
+    let src = "let p: i64 = Point {\n    x: 1,\n};";
+    let start = src.find("Point").unwrap();
+    let end = src.find('}').unwrap() + 1;
+    let diagnostic = Diagnostic::error("Mismatched types")
+        .with_span_label(Span::new(0, start, end), "found `Point`");
+    assert_eq!(
+        render(src, vec![diagnostic]),
+        "error: Mismatched types\n\
+         \x20--> test.tanu:1:14\n\
+         \x20 |\n\
+         1 | let p: i64 = Point {\n\
+         \x20 |              ^^^^^^^\n\
+         2 |     x: 1,\n\
+         \x20 |     ^^^^^\n\
+         3 | };\n\
+         \x20 | ^ found `Point`\n"
+    );
+}
+
+#[test]
+fn long_ranges_elide_the_middle_lines() {
+    let src = "{\n  a;\n  b;\n  c;\n  d;\n}";
+    let diagnostic = Diagnostic::error("Long block").with_span(Span::new(0, 0, src.len()));
+    let rendered = render(src, vec![diagnostic]);
+    assert!(rendered.contains("2 |   a;\n"));
+    assert!(!rendered.contains("3 |   b;\n"));
+    assert!(rendered.contains("...\n"));
+    assert!(rendered.contains("5 |   d;\n"));
+    assert!(rendered.contains("6 | }\n"));
+}
+
+#[test]
+fn suggestions_show_the_replaced_line() {
+    let src = "let r = ad(1, 2);";
+    let diagnostic = Diagnostic::error("Cannot find function `ad` in this scope")
+        .with_code(&UNDEFINED_VARIABLE)
+        .with_span_label(span_of(src, "ad"), "not found in this scope")
+        .with_suggestion(span_of(src, "ad"), "a similar name exists: `add`", "add");
+    assert_eq!(
+        render(src, vec![diagnostic]),
+        "error[E1130]: Cannot find function `ad` in this scope\n\
+         \x20--> test.tanu:1:9\n\
+         \x20 |\n\
+         1 | let r = ad(1, 2);\n\
+         \x20 |         ^^ not found in this scope\n\
+         help: a similar name exists: `add`\n\
+         \x20 |\n\
+         1 | let r = add(1, 2);\n\
+         \x20 |         ~~~\n"
+    );
+}
+
+#[test]
+fn diagnostics_without_spans_use_line_and_column() {
+    let src = "let a = 1;\nlet b = c;";
+    let diagnostic = Diagnostic::warning("Something odd").at(2, 9);
+    assert_eq!(
+        render(src, vec![diagnostic]),
+        "warning: Something odd\n\
+         \x20--> test.tanu:2:9\n\
+         \x20 |\n\
+         2 | let b = c;\n\
+         \x20 |         ^\n"
+    );
+}
+
+#[test]
+fn diagnostics_accumulate() {
+    colored::control::set_override(false);
+    let mut errors = CompilerError::new();
+    errors.push(Diagnostic::warning("first"));
+    assert!(!errors.has_errors());
+    assert_eq!(errors.summary(), "");
+    errors.extend(vec![
+        Diagnostic::error("second"),
+        Diagnostic::error("third"),
+    ]);
+    assert!(errors.has_errors());
+    assert_eq!(errors.count("error"), 2);
+    assert_eq!(
+        errors.summary(),
+        "error: aborting due to 2 previous errors; 1 warning emitted\n"
+    );
+    let messages: Vec<&str> = errors
+        .diagnostics()
+        .iter()
+        .map(Diagnostic::message)
+        .collect();
+    assert_eq!(messages, vec!["first", "second", "third"]);
+}
@@ -0,0 +1,139 @@
+// `mod`で参照されるファイルの読み込みと、モジュールをまたいだ名前の解決を確かめる
+#![cfg(any(feature = "full", all(feature = "lexer", feature = "parser")))]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tanucc_script_ir::lexer::tokenizer::Lexer;
+use tanucc_script_ir::parser::loader::ModuleLoader;
+use tanucc_script_ir::parser::syntax::Parser;
+use tanucc_script_ir::source::SourceMap;
+
+// 一時ディレクトリにファイルを書き、`main.tanu`をルートとして読み込む
+// 成功すれば読み込んだファイルの数を、失敗すればエラーのメッセージを返す
+fn load(files: &[(&str, &str)]) -> Result<usize, Vec<String>> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = env::temp_dir().join(format!(
+        "tanucc-loader-{}-{}",
+        process::id(),
+        COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    for (name, content) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+    }
+    let root: PathBuf = dir.join("main.tanu");
+    let input_path = root.to_string_lossy().to_string();
+    let src = fs::read_to_string(&root).unwrap();
+
+    let source_map = SourceMap::new();
+    let mut lexer = Lexer::new_with_value(&input_path, src.clone());
+    lexer.set_source_map(source_map.clone());
+    let tokens = lexer.tokenize().unwrap();
+    let mut parser = Parser::new(&tokens, &input_path, src.clone());
+    parser.set_source_map(&source_map);
+    let node = parser.parse().unwrap();
+    let mut loader = ModuleLoader::new(&source_map);
+    let result = loader
+        .load(&root, &input_path, src, &node)
+        .map(|_| loader.files().len())
+        .map_err(|errors| {
+            errors
+                .iter()
+                .map(|error| error.message().to_string())
+                .collect()
+        });
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+#[test]
+fn loads_a_file_module() {
+    let files = [
+        ("main.tanu", "mod foo;\nfoo::bar();\n"),
+        ("foo.tanu", "pub fn bar() {}\n"),
+    ];
+    assert_eq!(load(&files), Ok(2));
+}
+
+#[test]
+fn loads_a_directory_module() {
+    let files = [
+        ("main.tanu", "mod foo;\nfoo::baz::qux();\n"),
+        ("foo/mod.tanu", "pub mod baz;\n"),
+        ("foo/baz.tanu", "pub fn qux() {}\n"),
+    ];
+    assert_eq!(load(&files), Ok(3));
+}
+
+#[test]
+fn module_file_found_twice() {
+    let files = [
+        ("main.tanu", "mod foo;\n"),
+        ("foo.tanu", "pub fn bar() {}\n"),
+        ("foo/mod.tanu", "pub fn bar() {}\n"),
+    ];
+    let errors = load(&files).unwrap_err();
+    assert!(errors[0].starts_with("File for module `foo` found at both"));
+}
+
+#[test]
+fn private_function_of_another_module() {
+    let files = [
+        ("main.tanu", "mod foo;\nfoo::bar();\n"),
+        ("foo.tanu", "fn bar() {}\n"),
+    ];
+    assert_eq!(
+        load(&files),
+        Err(vec![String::from("Function `foo::bar` is private")])
+    );
+}
+
+#[test]
+fn use_glob_imports_public_items() {
+    let files = [
+        ("main.tanu", "mod foo;\nuse foo::*;\nbar();\n"),
+        ("foo.tanu", "pub fn bar() {}\nfn hidden() {}\n"),
+    ];
+    assert_eq!(load(&files), Ok(2));
+}
+
+#[test]
+fn use_glob_conflicts_with_a_definition() {
+    let files = [
+        ("main.tanu", "mod foo;\nuse foo::*;\nfn bar() {}\nbar();\n"),
+        ("foo.tanu", "pub fn bar() {}\n"),
+    ];
+    assert_eq!(
+        load(&files),
+        Err(vec![String::from(
+            "`bar` is defined multiple times in module `crate`"
+        )])
+    );
+}
+
+#[test]
+fn root_items_are_not_visible_without_use() {
+    let files = [
+        ("main.tanu", "mod foo;\nfn helper() {}\nfoo::bar();\n"),
+        ("foo.tanu", "pub fn bar() {\n    helper();\n}\n"),
+    ];
+    assert_eq!(
+        load(&files),
+        Err(vec![String::from(
+            "Cannot find function `helper` in module `foo`"
+        )])
+    );
+
+    let files = [
+        ("main.tanu", "mod foo;\nfn helper() {}\nfoo::bar();\n"),
+        (
+            "foo.tanu",
+            "use crate::helper;\npub fn bar() {\n    helper();\n}\n",
+        ),
+    ];
+    assert_eq!(load(&files), Ok(2));
+}
@@ -0,0 +1,445 @@
+// 構文木の各ノードが構文の先頭の位置(行, 列)と、構文全体の範囲(Span)を持つことを確かめる
+#![cfg(any(feature = "full", all(feature = "lexer", feature = "parser")))]
+
+use tanucc_script_ir::lexer::tokenizer::Lexer;
+use tanucc_script_ir::parser::syntax::{Node, Parser};
+use tanucc_script_ir::source::SourceMap;
+use tanucc_script_ir::types::{
+    ControlFlow, DataType, Declaration, Expr, NodeValue, Operator, Statement,
+};
+
+const INPUT_PATH: &str = "test.tanu";
+
+// 文の並び(`;`のEndStatementは除く)
+fn parse(src: &str) -> Vec<Node> {
+    let source_map = SourceMap::new();
+    let mut lexer = Lexer::new_with_value(INPUT_PATH, src.to_string());
+    lexer.set_source_map(source_map.clone());
+    let tokens = lexer.tokenize().expect("failed to tokenize");
+    let mut parser = Parser::new(&tokens, INPUT_PATH, src.to_string());
+    parser.set_source_map(&source_map);
+    let node = parser.parse().expect("failed to parse");
+    node.iter()
+        .map(|statement| statement.borrow().clone())
+        .filter(|statement| !matches!(statement.value, NodeValue::Expr(Expr::EndStatement)))
+        .collect()
+}
+
+fn first(src: &str) -> Node {
+    parse(src).remove(0)
+}
+
+// (行, 列, 範囲のソース)
+fn position<'a>(src: &'a str, node: &Node) -> (usize, usize, &'a str) {
+    (node.line, node.column, &src[node.span.start..node.span.end])
+}
+
+// `let x = 値;`の値
+fn let_value(statement: &Node) -> &Node {
+    match &statement.value {
+        NodeValue::Statement(Statement::Declaration(Declaration::Variable(_, _, value, ..))) => {
+            value
+        }
+        value => panic!("expected a variable declaration, found {:?}", value),
+    }
+}
+
+fn operands(node: &Node) -> (&Node, &Node) {
+    match &node.value {
+        NodeValue::Expr(Expr::Operator(
+            Operator::Add(lhs, rhs)
+            | Operator::Mul(lhs, rhs)
+            | Operator::Lt(lhs, rhs)
+            | Operator::And(lhs, rhs),
+        )) => (lhs, rhs),
+        value => panic!("expected a binary operator, found {:?}", value),
+    }
+}
+
+fn block_statements(node: &Node) -> &[Box<Node>] {
+    match &node.value {
+        NodeValue::Expr(Expr::Block(statements)) => statements,
+        value => panic!("expected a block, found {:?}", value),
+    }
+}
+
+#[test]
+fn literals() {
+    let src = "let a = 42;\nlet b = 1.5;\nlet c = \"hi\";\nlet d = true;\nlet e = null;";
+    let statements = parse(src);
+    assert_eq!(position(src, let_value(&statements[0])), (1, 9, "42"));
+    assert_eq!(position(src, let_value(&statements[1])), (2, 9, "1.5"));
+    assert_eq!(position(src, let_value(&statements[2])), (3, 9, "\"hi\""));
+    assert_eq!(position(src, let_value(&statements[3])), (4, 9, "true"));
+    assert_eq!(position(src, let_value(&statements[4])), (5, 9, "null"));
+}
+
+#[test]
+fn variable_declaration() {
+    let src = "  let mut x: i64 = 1 + 2;";
+    let statement = first(src);
+    assert_eq!(position(src, &statement), (1, 3, "let mut x: i64 = 1 + 2"));
+    match &statement.value {
+        NodeValue::Statement(Statement::Declaration(Declaration::Variable(
+            name,
+            data_type,
+            value,
+            ..,
+        ))) => {
+            assert_eq!(position(src, name), (1, 11, "x"));
+            assert_eq!(position(src, data_type), (1, 14, "i64"));
+            assert_eq!(position(src, value), (1, 20, "1 + 2"));
+        }
+        value => panic!("expected a variable declaration, found {:?}", value),
+    }
+}
+
+#[test]
+fn const_and_type_declarations() {
+    let src = "const N: i64 = 4;\ntype Id = i64;";
+    let statements = parse(src);
+    assert_eq!(position(src, &statements[0]), (1, 1, "const N: i64 = 4"));
+    match &statements[0].value {
+        NodeValue::Statement(Statement::Declaration(Declaration::Const(name, _, value, ..))) => {
+            assert_eq!(position(src, name), (1, 7, "N"));
+            assert_eq!(position(src, value), (1, 16, "4"));
+        }
+        value => panic!("expected a const declaration, found {:?}", value),
+    }
+    assert_eq!(position(src, &statements[1]), (2, 1, "type Id = i64"));
+    match &statements[1].value {
+        NodeValue::Statement(Statement::Declaration(Declaration::Type(name, ..))) => {
+            assert_eq!(position(src, name), (2, 6, "Id"));
+        }
+        value => panic!("expected a type declaration, found {:?}", value),
+    }
+}
+
+#[test]
+fn binary_operators_span_both_operands() {
+    let src = "let x = a + b * c;";
+    let add = let_value(&first(src)).clone();
+    assert_eq!(position(src, &add), (1, 9, "a + b * c"));
+    let (lhs, mul) = operands(&add);
+    assert_eq!(position(src, lhs), (1, 9, "a"));
+    assert_eq!(position(src, mul), (1, 13, "b * c"));
+    let (b, c) = operands(mul);
+    assert_eq!(position(src, b), (1, 13, "b"));
+    assert_eq!(position(src, c), (1, 17, "c"));
+}
+
+#[test]
+fn parenthesized_expression_includes_parentheses() {
+    let src = "let x = (a + b) * c;";
+    let mul = let_value(&first(src)).clone();
+    assert_eq!(position(src, &mul), (1, 9, "(a + b) * c"));
+    let (group, _) = operands(&mul);
+    assert_eq!(position(src, group), (1, 9, "(a + b)"));
+}
+
+#[test]
+fn conditions() {
+    let src = "if a < 1 && b {\n    x;\n}";
+    let statement = first(src);
+    match &statement.value {
+        NodeValue::Statement(Statement::ControlFlow(ControlFlow::If(condition, body))) => {
+            assert_eq!(position(src, condition), (1, 4, "a < 1 && b"));
+            let (lt, b) = operands(condition);
+            assert_eq!(position(src, lt), (1, 4, "a < 1"));
+            assert_eq!(position(src, b), (1, 13, "b"));
+            assert_eq!(position(src, body), (1, 15, "{\n    x;\n}"));
+            assert_eq!(position(src, &block_statements(body)[0]), (2, 5, "x"));
+        }
+        value => panic!("expected an if statement, found {:?}", value),
+    }
+}
+
+#[test]
+fn unary_and_postfix_operators() {
+    let src = "++i;\ni--;\nlet m = ~mask;";
+    let statements = parse(src);
+    assert_eq!(position(src, &statements[0]), (1, 1, "++i"));
+    assert_eq!(position(src, &statements[1]), (2, 1, "i--"));
+    assert_eq!(position(src, let_value(&statements[2])), (3, 9, "~mask"));
+}
+
+#[test]
+fn assignments() {
+    let src = "x = 1;\np.x = 2;\ny += 3;\na[0] = 4;";
+    let statements = parse(src);
+    assert_eq!(position(src, &statements[0]), (1, 1, "x = 1"));
+    match &statements[0].value {
+        NodeValue::Expr(Expr::Assign(target, value, _)) => {
+            assert_eq!(position(src, target), (1, 1, "x"));
+            assert_eq!(position(src, value), (1, 5, "1"));
+        }
+        value => panic!("expected an assignment, found {:?}", value),
+    }
+    assert_eq!(position(src, &statements[1]), (2, 1, "p.x = 2"));
+    assert_eq!(position(src, &statements[2]), (3, 1, "y += 3"));
+    assert_eq!(position(src, &statements[3]), (4, 1, "a[0] = 4"));
+}
+
+#[test]
+fn calls_members_and_indexes() {
+    let src = "let a = f(1, 2);\nlet b = @printf(\"%d\", a);\nlet c = p.q.r;\nlet d = xs[i + 1];\nlet e = p.len();";
+    let statements = parse(src);
+    let call = let_value(&statements[0]);
+    assert_eq!(position(src, call), (1, 9, "f(1, 2)"));
+    match &call.value {
+        NodeValue::Expr(Expr::Call(_, args, _)) => {
+            assert_eq!(position(src, &args[0]), (1, 11, "1"));
+            assert_eq!(position(src, &args[1]), (1, 14, "2"));
+        }
+        value => panic!("expected a call, found {:?}", value),
+    }
+    assert_eq!(
+        position(src, let_value(&statements[1])),
+        (2, 9, "@printf(\"%d\", a)")
+    );
+
+    let member = let_value(&statements[2]);
+    assert_eq!(position(src, member), (3, 9, "p.q.r"));
+    match &member.value {
+        NodeValue::Expr(Expr::MemberAccess(inner, name)) => {
+            assert_eq!(position(src, inner), (3, 9, "p.q"));
+            assert_eq!(position(src, name), (3, 13, "r"));
+        }
+        value => panic!("expected a member access, found {:?}", value),
+    }
+
+    let index = let_value(&statements[3]);
+    assert_eq!(position(src, index), (4, 9, "xs[i + 1]"));
+    match &index.value {
+        NodeValue::Expr(Expr::Index(target, index)) => {
+            assert_eq!(position(src, target), (4, 9, "xs"));
+            assert_eq!(position(src, index), (4, 12, "i + 1"));
+        }
+        value => panic!("expected an index, found {:?}", value),
+    }
+    assert_eq!(position(src, let_value(&statements[4])), (5, 9, "p.len()"));
+}
+
+#[test]
+fn struct_instances_and_arrays() {
+    let src = "let p = Point { x: 1, y: 2 };\nlet a = [1, 2, 3];";
+    let statements = parse(src);
+    let instance = let_value(&statements[0]);
+    assert_eq!(position(src, instance), (1, 9, "Point { x: 1, y: 2 }"));
+    match &instance.value {
+        NodeValue::Expr(Expr::StructInstance(_, fields)) => {
+            assert_eq!(position(src, &fields[1].1), (1, 26, "2"));
+        }
+        value => panic!("expected a struct instance, found {:?}", value),
+    }
+    let array = let_value(&statements[1]);
+    assert_eq!(position(src, array), (2, 9, "[1, 2, 3]"));
+    match &array.value {
+        NodeValue::Expr(Expr::DataType(DataType::Array(_, values))) => {
+            assert_eq!(position(src, &values[2]), (2, 16, "3"));
+        }
+        value => panic!("expected an array, found {:?}", value),
+    }
+}
+
+#[test]
+fn fixed_array_type() {
+    let src = "let a: [i64; 3] = [1, 2, 3];";
+    match &first(src).value {
+        NodeValue::Statement(Statement::Declaration(Declaration::Variable(_, data_type, ..))) => {
+            assert_eq!(position(src, data_type), (1, 8, "[i64; 3]"));
+        }
+        value => panic!("expected a variable declaration, found {:?}", value),
+    }
+}
+
+#[test]
+fn paths() {
+    let src = "let a = geo::shapes::area(p);\nuse geo::{shapes::*, origin};";
+    let statements = parse(src);
+    let path = let_value(&statements[0]);
+    assert_eq!(position(src, path), (1, 9, "geo::shapes::area(p)"));
+    match &path.value {
+        NodeValue::Expr(Expr::ScopeResolution(segments)) => {
+            assert_eq!(position(src, &segments[0]), (1, 9, "geo"));
+            assert_eq!(position(src, &segments[1]), (1, 14, "shapes"));
+            assert_eq!(position(src, &segments[2]), (1, 22, "area(p)"));
+        }
+        value => panic!("expected a path, found {:?}", value),
+    }
+
+    assert_eq!(
+        position(src, &statements[1]),
+        (2, 1, "use geo::{shapes::*, origin}")
+    );
+    match &statements[1].value {
+        NodeValue::Statement(Statement::Use(path)) => match &path.value {
+            NodeValue::Expr(Expr::ScopeResolution(segments)) => {
+                assert_eq!(position(src, &segments[1]), (2, 10, "{shapes::*, origin}"));
+                let group = block_statements(&segments[1]);
+                assert_eq!(position(src, &group[0]), (2, 11, "shapes::*"));
+                assert_eq!(position(src, &group[1]), (2, 22, "origin"));
+            }
+            value => panic!("expected a path, found {:?}", value),
+        },
+        value => panic!("expected a use declaration, found {:?}", value),
+    }
+}
+
+#[test]
+fn modules() {
+    let src = "mod util;\nmod inner {\n    fn f() {}\n}";
+    let statements = parse(src);
+    assert_eq!(position(src, &statements[0]), (1, 1, "mod util"));
+    assert_eq!(
+        position(src, &statements[1]),
+        (2, 1, "mod inner {\n    fn f() {}\n}")
+    );
+    match &statements[1].value {
+        NodeValue::Statement(Statement::ModDeclaration(_, items)) => {
+            assert_eq!(position(src, &items[0]), (3, 5, "fn f() {}"));
+        }
+        value => panic!("expected a module definition, found {:?}", value),
+    }
+}
+
+#[test]
+fn if_else_chain() {
+    let src = "if a {\n    1;\n} else if b {\n    2;\n} else {\n    3;\n}";
+    let statements = parse(src);
+    assert_eq!(position(src, &statements[0]), (1, 1, "if a {\n    1;\n}"));
+    assert!(matches!(
+        statements[1].value,
+        NodeValue::Statement(Statement::ControlFlow(ControlFlow::ElseIf(..)))
+    ));
+    assert_eq!(
+        position(src, &statements[1]),
+        (3, 3, "else if b {\n    2;\n}")
+    );
+    assert!(matches!(
+        statements[2].value,
+        NodeValue::Statement(Statement::ControlFlow(ControlFlow::Else(..)))
+    ));
+    assert_eq!(position(src, &statements[2]), (5, 3, "else {\n    3;\n}"));
+}
+
+#[test]
+fn loops() {
+    let src = "while i < 3 {\n    i++;\n}\nloop {\n    break;\n}\nfor j in 0..10 step 2 {\n    continue;\n}";
+    let statements = parse(src);
+    assert_eq!(
+        position(src, &statements[0]),
+        (1, 1, "while i < 3 {\n    i++;\n}")
+    );
+    assert_eq!(
+        position(src, &statements[1]),
+        (4, 1, "loop {\n    break;\n}")
+    );
+    match &statements[1].value {
+        NodeValue::Statement(Statement::ControlFlow(ControlFlow::Loop(body))) => {
+            assert_eq!(position(src, &block_statements(body)[0]), (5, 5, "break"));
+        }
+        value => panic!("expected a loop, found {:?}", value),
+    }
+    assert_eq!(
+        position(src, &statements[2]),
+        (7, 1, "for j in 0..10 step 2 {\n    continue;\n}")
+    );
+    match &statements[2].value {
+        NodeValue::Statement(Statement::ControlFlow(ControlFlow::For(var, range, body))) => {
+            assert_eq!(position(src, var), (7, 5, "j"));
+            assert_eq!(position(src, range), (7, 10, "0..10 step 2"));
+            assert_eq!(
+                position(src, &block_statements(body)[0]),
+                (8, 5, "continue")
+            );
+        }
+        value => panic!("expected a for loop, found {:?}", value),
+    }
+}
+
+#[test]
+fn function_definition() {
+    let src = "pub fn add(a: i64, b: i64) -> i64 {\n    return a + b;\n}";
+    let statement = first(src);
+    assert_eq!(
+        position(src, &statement),
+        (
+            1,
+            5,
+            "fn add(a: i64, b: i64) -> i64 {\n    return a + b;\n}"
+        )
+    );
+    match &statement.value {
+        NodeValue::Statement(Statement::Declaration(Declaration::Function(
+            _,
+            args,
+            body,
+            return_type,
+            ..,
+        ))) => {
+            assert_eq!(position(src, &args[1].0), (1, 23, "i64"));
+            assert_eq!(&src[args[1].2.start..args[1].2.end], "b");
+            assert_eq!(position(src, return_type), (1, 31, "i64"));
+            assert_eq!(
+                position(src, &block_statements(body)[0]),
+                (2, 5, "return a + b")
+            );
+        }
+        value => panic!("expected a function definition, found {:?}", value),
+    }
+}
+
+#[test]
+fn struct_and_impl_definitions() {
+    let src = "struct Point {\n    x: i64,\n}\nimpl Point {\n    fn get(&self) -> i64 {\n        return self.x;\n    }\n}";
+    let statements = parse(src);
+    assert_eq!(
+        position(src, &statements[0]),
+        (1, 1, "struct Point {\n    x: i64,\n}")
+    );
+    match &statements[0].value {
+        NodeValue::Statement(Statement::Declaration(Declaration::Struct(_, fields, _))) => {
+            assert_eq!(position(src, &fields[0]), (2, 5, "x: i64"));
+        }
+        value => panic!("expected a struct definition, found {:?}", value),
+    }
+    assert_eq!(statements[1].line, 4);
+    assert_eq!(statements[1].column, 1);
+    match &statements[1].value {
+        NodeValue::Statement(Statement::Declaration(Declaration::Impl(_, members))) => {
+            assert_eq!(
+                position(src, &members[0]),
+                (
+                    5,
+                    5,
+                    "fn get(&self) -> i64 {\n        return self.x;\n    }"
+                )
+            );
+        }
+        value => panic!("expected an impl, found {:?}", value),
+    }
+}
+
+#[test]
+fn comments_and_statement_ends() {
+    let src = "// note\nx;";
+    let source_map = SourceMap::new();
+    let mut lexer = Lexer::new_with_value(INPUT_PATH, src.to_string());
+    lexer.set_source_map(source_map.clone());
+    let tokens = lexer.tokenize().unwrap();
+    let mut parser = Parser::new(&tokens, INPUT_PATH, src.to_string());
+    let statements: Vec<Node> = parser
+        .parse()
+        .unwrap()
+        .iter()
+        .map(|statement| statement.borrow().clone())
+        .collect();
+    assert_eq!(position(src, &statements[0]), (1, 1, "// note"));
+    assert_eq!(position(src, &statements[1]), (2, 1, "x"));
+    assert!(matches!(
+        statements[2].value,
+        NodeValue::Expr(Expr::EndStatement)
+    ));
+    assert_eq!(position(src, &statements[2]), (2, 2, ";"));
+}
@@ -0,0 +1,110 @@
+// `include`の展開(相対パス・重複・循環)と、展開したトークンが元のファイルの位置を持つことを確かめる
+#![cfg(any(feature = "full", feature = "lexer"))]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tanucc_script_ir::error::Diagnostic;
+use tanucc_script_ir::lexer::preprocess::Preprocessor;
+use tanucc_script_ir::lexer::tokenizer::{Lexer, Token};
+use tanucc_script_ir::source::SourceMap;
+
+// 一時ディレクトリにファイルを書き、`main.tanu`のincludeを展開する
+fn preprocess(files: &[(&str, &str)]) -> (SourceMap, Result<Vec<Token>, Diagnostic>) {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = env::temp_dir().join(format!(
+        "tanucc-preprocess-{}-{}",
+        process::id(),
+        COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    for (name, content) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+    }
+    let root: PathBuf = dir.join("main.tanu");
+    let src = fs::read_to_string(&root).unwrap();
+
+    let source_map = SourceMap::new();
+    let mut lexer = Lexer::new_with_value(&root.to_string_lossy(), src);
+    lexer.set_source_map(source_map.clone());
+    let tokens = lexer.tokenize().unwrap();
+    let result = Preprocessor::new(&source_map).preprocess(&root, tokens);
+    let _ = fs::remove_dir_all(&dir);
+    (source_map, result)
+}
+
+fn count(tokens: &[Token], value: &str) -> usize {
+    tokens
+        .iter()
+        .filter(|token| token.token_value() == value)
+        .count()
+}
+
+#[test]
+fn include_cycle() {
+    let files = [
+        ("main.tanu", "include \"a.tanu\";\n"),
+        ("a.tanu", "include \"b.tanu\";\n"),
+        ("b.tanu", "include \"a.tanu\";\n"),
+    ];
+    let (_, result) = preprocess(&files);
+    let error = result.unwrap_err();
+    assert_eq!(
+        error.message(),
+        "Include cycle detected while including `a.tanu`"
+    );
+}
+
+#[test]
+fn repeated_includes_are_expanded_once() {
+    let files = [
+        (
+            "main.tanu",
+            "include \"util.tanu\";\ninclude \"./util.tanu\";\n",
+        ),
+        ("util.tanu", "fn util() {}\n"),
+    ];
+    let (source_map, result) = preprocess(&files);
+    let tokens = result.unwrap();
+    assert_eq!(count(&tokens, "util"), 1);
+    assert_eq!(count(&tokens, "include"), 0);
+    assert_eq!(source_map.len(), 2);
+}
+
+#[test]
+fn paths_are_relative_to_the_including_file() {
+    let files = [
+        ("main.tanu", "include \"lib/a.tanu\";\n"),
+        ("lib/a.tanu", "include \"b.tanu\";\nfn a() {}\n"),
+        ("lib/b.tanu", "fn b() {}\n"),
+        ("b.tanu", "fn wrong() {}\n"),
+    ];
+    let (_, result) = preprocess(&files);
+    let tokens = result.unwrap();
+    assert_eq!(count(&tokens, "a"), 1);
+    assert_eq!(count(&tokens, "b"), 1);
+    assert_eq!(count(&tokens, "wrong"), 0);
+}
+
+#[test]
+fn included_tokens_keep_their_file_and_span() {
+    let files = [
+        ("main.tanu", "let first = 1;\ninclude \"util.tanu\";\n"),
+        ("util.tanu", "\nlet value = 42;\n"),
+    ];
+    let (source_map, result) = preprocess(&files);
+    let tokens = result.unwrap();
+    let value = tokens
+        .iter()
+        .find(|token| token.token_value() == "value")
+        .unwrap();
+    assert_eq!((value.line(), value.column()), (2, 5));
+    let span = value.span();
+    assert_eq!(span.file, 1);
+    let file = source_map.file(span.file).unwrap();
+    assert!(file.path.ends_with("util.tanu"));
+    assert_eq!(&file.content[span.start..span.end], "value");
+}
@@ -0,0 +1,150 @@
+// 名前解決が未定義の名前を似た名前の提案と共に報告し、未使用と再宣言を警告することを確かめる
+#![cfg(any(
+    feature = "full",
+    all(feature = "lexer", feature = "parser", feature = "checker")
+))]
+
+use tanucc_script_ir::checker::resolver::Resolver;
+use tanucc_script_ir::error::{CompilerError, Diagnostic};
+use tanucc_script_ir::lexer::tokenizer::Lexer;
+use tanucc_script_ir::parser::syntax::Parser;
+use tanucc_script_ir::source::SourceMap;
+
+// srcの名前を解決し、(レベル, メッセージ)の一覧と整形した報告を返す
+fn resolve(src: &str) -> (Vec<(String, String)>, String) {
+    let source_map = SourceMap::new();
+    let mut lexer = Lexer::new_with_value("test.tanu", src.to_string());
+    lexer.set_source_map(source_map.clone());
+    let tokens = lexer.tokenize().unwrap();
+    let mut parser = Parser::new(&tokens, "test.tanu", src.to_string());
+    parser.set_source_map(&source_map);
+    let node = parser.parse().unwrap();
+    let mut resolver = Resolver::new("test.tanu", src.to_string());
+    resolver.set_source_map(&source_map);
+    let diagnostics: Vec<Diagnostic> = resolver.diagnose(&node);
+    let messages = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.level().to_string(),
+                diagnostic.message().to_string(),
+            )
+        })
+        .collect();
+    let mut report = CompilerError::new();
+    report.set_source_map(&source_map);
+    report.extend(diagnostics);
+    (messages, report.format_error_string("test.tanu", src))
+}
+
+fn errors(messages: &[(String, String)]) -> Vec<&str> {
+    messages
+        .iter()
+        .filter(|(level, _)| level == "error")
+        .map(|(_, message)| message.as_str())
+        .collect()
+}
+
+fn warnings(messages: &[(String, String)]) -> Vec<&str> {
+    messages
+        .iter()
+        .filter(|(level, _)| level == "warning")
+        .map(|(_, message)| message.as_str())
+        .collect()
+}
+
+#[test]
+fn undefined_variable() {
+    let src = "\
+let count = 1;
+let total = cout + 1;
+@println(\"%d\", total);
+";
+    let (messages, report) = resolve(src);
+    assert_eq!(
+        errors(&messages),
+        vec!["Cannot find value `cout` in this scope"]
+    );
+    assert!(
+        report.contains("a similar name exists: `count`"),
+        "{}",
+        report
+    );
+    assert!(report.contains("let total = count + 1;"), "{}", report);
+}
+
+#[test]
+fn undefined_function() {
+    let src = "\
+fn compute(x: i32) -> i32 {
+    return x;
+}
+let r = compte(1);
+@println(\"%d\", r);
+";
+    let (messages, report) = resolve(src);
+    assert_eq!(
+        errors(&messages),
+        vec!["Cannot find function `compte` in this scope"]
+    );
+    assert!(
+        report.contains("a similar name exists: `compute`"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn undefined_type() {
+    let src = "\
+struct Point {
+    x: i32,
+}
+let p: Pointt = Point { x: 1 };
+@println(\"%d\", p.x);
+";
+    let (messages, report) = resolve(src);
+    assert_eq!(
+        errors(&messages),
+        vec!["Cannot find type `Pointt` in this scope"]
+    );
+    assert!(
+        report.contains("a similar name exists: `Point`"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn unused_variable() {
+    let src = "\
+let used = 1;
+let unused = 2;
+let _ignored = 3;
+@println(\"%d\", used);
+";
+    let (messages, report) = resolve(src);
+    assert!(errors(&messages).is_empty());
+    assert_eq!(warnings(&messages), vec!["Unused variable: `unused`"]);
+    assert!(report.contains("let _unused = 2;"), "{}", report);
+}
+
+#[test]
+fn shadowing() {
+    let src = "\
+let x = 1;
+let x = x + 1;
+@println(\"%d\", x);
+";
+    let (messages, report) = resolve(src);
+    assert!(errors(&messages).is_empty());
+    assert_eq!(
+        warnings(&messages),
+        vec!["`x` shadows a previous declaration"]
+    );
+    assert!(
+        report.contains("`x` was previously declared here"),
+        "{}",
+        report
+    );
+}
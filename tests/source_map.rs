@@ -0,0 +1,35 @@
+// 字句解析がSourceMapに入力を登録し、トークンとエラーの位置を入力ごとに数えることを確かめる
+#![cfg(any(feature = "full", feature = "lexer"))]
+
+use tanucc_script_ir::lexer::tokenizer::Lexer;
+use tanucc_script_ir::source::SourceMap;
+
+#[test]
+fn positions_restart_for_each_input() {
+    let source_map = SourceMap::new();
+    let mut lexer =
+        Lexer::new_with_value_vec(vec!["let a = 1;\n".to_string(), "let b = 2;".to_string()]);
+    lexer.set_source_map(source_map.clone());
+    let tokens = lexer.tokenize().unwrap();
+    let b = tokens
+        .iter()
+        .find(|token| token.token_value() == "b")
+        .unwrap();
+    assert_eq!((b.line(), b.column()), (1, 5));
+    assert_eq!(b.span().file, 1);
+    assert_eq!((b.span().start, b.span().end), (4, 5));
+    assert_eq!(source_map.len(), 2);
+}
+
+#[test]
+fn errors_point_at_the_source_map() {
+    let source_map = SourceMap::new();
+    let src = "let a = 1;\nlet s = \"abc;";
+    let mut lexer = Lexer::new_with_value("test.tanu", src.to_string());
+    lexer.set_source_map(source_map.clone());
+    let error = lexer.tokenize().unwrap_err();
+    assert_eq!(error.message(), "Double quote not closed");
+    let span = error.span().unwrap();
+    assert_eq!(span.file, 0);
+    assert_eq!(&src[span.start..span.end], "\"");
+}
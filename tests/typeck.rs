@@ -0,0 +1,117 @@
+// 型検査が推論した型で検査し、見つかった型エラーを全てまとめて報告することを確かめる
+#![cfg(any(
+    feature = "full",
+    all(feature = "lexer", feature = "parser", feature = "checker")
+))]
+
+use tanucc_script_ir::checker::typeck::TypeChecker;
+use tanucc_script_ir::lexer::tokenizer::Lexer;
+use tanucc_script_ir::parser::syntax::Parser;
+
+// srcを型検査し、エラーのメッセージを報告された順に返す
+fn check(src: &str) -> Vec<String> {
+    let mut lexer = Lexer::new_with_value("test.tanu", src.to_string());
+    let tokens = lexer.tokenize().unwrap();
+    let mut parser = Parser::new(&tokens, "test.tanu", src.to_string());
+    let node = parser.parse().unwrap();
+    let mut checker = TypeChecker::new("test.tanu", src.to_string());
+    checker
+        .diagnose(&node)
+        .iter()
+        .filter(|diagnostic| diagnostic.is_error())
+        .map(|diagnostic| diagnostic.message().to_string())
+        .collect()
+}
+
+#[test]
+fn inferred_let_types() {
+    let src = "\
+let a: i32 = 1;
+let b = a;
+let c: i32 = b;
+let d = 1.5;
+let e: i32 = d;
+";
+    assert_eq!(
+        check(src),
+        vec!["Mismatched types in the declaration of `e`: expected `i32`, found `f64`"]
+    );
+}
+
+#[test]
+fn operand_mismatch() {
+    let src = "\
+let a = 1;
+let b = true;
+let c = a + b;
+";
+    assert_eq!(check(src), vec!["Cannot apply `+` to `i64` and `bool`"]);
+}
+
+#[test]
+fn call_arity() {
+    let src = "\
+fn add(a: i32, b: i32) -> i32 {
+    return a + b;
+}
+let r = add(1);
+";
+    assert_eq!(
+        check(src),
+        vec!["Function `add` takes 2 arguments but 1 was supplied"]
+    );
+}
+
+#[test]
+fn return_type() {
+    let src = "\
+fn f() -> i32 {
+    return true;
+}
+f();
+";
+    assert_eq!(
+        check(src),
+        vec!["Mismatched types in return value: expected `i32`, found `bool`"]
+    );
+}
+
+#[test]
+fn struct_fields() {
+    let src = "\
+struct Point {
+    x: i32,
+    y: i32,
+}
+let p = Point { x: 1, y: 2 };
+let z = p.z;
+let w: bool = p.x;
+";
+    assert_eq!(
+        check(src),
+        vec![
+            "Struct `Point` has no field named `z`",
+            "Mismatched types in the declaration of `w`: expected `bool`, found `i32`",
+        ]
+    );
+}
+
+#[test]
+fn reports_every_error() {
+    let src = "\
+let a: i32 = true;
+let b: bool = 1;
+fn f() -> bool {
+    return 1;
+}
+f();
+";
+    assert_eq!(
+        check(src),
+        vec![
+            "Mismatched types in the declaration of `a`: expected `i32`, found `bool`",
+            "Mismatched types in the declaration of `b`: expected `bool`, found `i64`",
+            "Mismatched types in return value: expected `bool`, found `i64`",
+        ]
+    );
+}